@@ -0,0 +1,130 @@
+// src/abbrev.rs
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+// Well-known ECS prefixes, by path of the original key; nested keys need their parents listed
+const DEFAULT_ABBREVIATIONS: [(&str, &str); 12] = [
+    ("kubernetes", "k8s"),
+    ("kubernetes.namespace", "k8s.ns"),
+    ("kubernetes.container", "k8s.ctr"),
+    ("kubernetes.deployment", "k8s.deploy"),
+    ("service", "svc"),
+    ("http.request", "http.req"),
+    ("http.response", "http.resp"),
+    ("http.response.status_code", "http.resp.status"),
+    ("user_agent", "ua"),
+    ("source", "src"),
+    ("destination", "dst"),
+    ("cloud.availability_zone", "cloud.az"),
+];
+
+// Shorter key names for compact JSON lines, from the built-in map and the abbreviations of the config
+#[derive(Debug)]
+pub struct Abbreviations {
+    // Path of a key to the name it is printed under
+    names: BTreeMap<String, String>,
+}
+
+impl Abbreviations {
+    // The configuration adds its own and may map a built-in one to itself to keep it whole
+    pub fn new(overrides: &BTreeMap<String, String>) -> Self {
+        let names = DEFAULT_ABBREVIATIONS
+            .iter()
+            .map(|(path, short)| (path.to_string(), short.to_string()))
+            .chain(overrides.clone())
+            .map(|(path, short)| {
+                let name = short.rsplit('.').next().unwrap_or_default().to_string();
+                (path, name)
+            })
+            .filter(|(_, name)| !name.is_empty())
+            .collect();
+        Self { names }
+    }
+
+    pub fn apply(&self, source: &Value) -> Value {
+        self.rename(source, "")
+    }
+
+    fn rename(&self, value: &Value, parent: &str) -> Value {
+        match value {
+            Value::Object(fields) => {
+                let mut renamed = Map::new();
+                for (key, value) in fields {
+                    let path = match parent {
+                        "" => key.clone(),
+                        parent => format!("{}.{}", parent, key),
+                    };
+                    let short = self.short_key(parent, key);
+                    // A rename landing on a key of the document would hide it
+                    let short = if fields.contains_key(&short) {
+                        key.clone()
+                    } else {
+                        short
+                    };
+                    renamed.insert(short, self.rename(value, &path));
+                }
+                Value::Object(renamed)
+            }
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.rename(item, parent)).collect())
+            }
+            value => value.clone(),
+        }
+    }
+
+    // Each part of a dotted key is looked up by the path leading to it
+    fn short_key(&self, parent: &str, key: &str) -> String {
+        let mut path = parent.to_string();
+        key.split('.')
+            .map(|part| {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(part);
+                self.names.get(&path).map_or(part, String::as_str)
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_abbreviate_keys() {
+        let abbreviations = Abbreviations::new(&BTreeMap::new());
+        let source = json!({
+            "kubernetes": {"namespace": "prod", "pod": {"name": "api-1"}},
+            "http.response.status_code": 500,
+            "service": {"name": "checkout"},
+            "svc": "taken",
+            "message": "boom"
+        });
+        assert_eq!(
+            abbreviations.apply(&source),
+            json!({
+                "k8s": {"ns": "prod", "pod": {"name": "api-1"}},
+                "http.resp.status": 500,
+                "service": {"name": "checkout"},
+                "svc": "taken",
+                "message": "boom"
+            })
+        );
+    }
+
+    #[test]
+    fn test_configured_abbreviations() {
+        let overrides = BTreeMap::from([
+            ("service".to_string(), "service".to_string()),
+            ("trace".to_string(), "tr".to_string()),
+        ]);
+        let abbreviations = Abbreviations::new(&overrides);
+        assert_eq!(
+            abbreviations.apply(&json!({"service": {"name": "a"}, "trace": [{"id": "t1"}]})),
+            json!({"service": {"name": "a"}, "tr": [{"id": "t1"}]})
+        );
+    }
+}
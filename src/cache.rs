@@ -0,0 +1,163 @@
+// src/cache.rs
+use crate::elasticsearch::model::Hit;
+use crate::utils::*;
+use crate::windows::{SpoolBatches, read_spool};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+pub const CACHE_DIR: &str = "cache";
+
+// Hits of a bounded extraction kept as NDJSON by --cache, named after the SHA-256 of what decides
+// them. A committed file is dated of its expiry, the TTL of the run that wrote it
+pub struct QueryCache {
+    path: PathBuf,
+    ttl: Duration,
+}
+
+impl QueryCache {
+    pub fn new(dir: &Path, key: &Value, ttl: Duration) -> Self {
+        let name: String = Sha256::digest(key.to_string().as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Self {
+            path: dir.join(format!("{}.ndjson", name)),
+            ttl,
+        }
+    }
+
+    // The cached hits until they expire
+    pub fn fresh(&self, batch_size: usize) -> Result<Option<SpoolBatches>, ESQError> {
+        match expiry(&self.path) {
+            Some(expires) if expires > SystemTime::now() => {
+                read_spool(&self.path, batch_size).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn writer(&self) -> Result<CacheWriter, ESQError> {
+        if let Some(dir) = self.path.parent()
+            && !dir.exists()
+        {
+            fs::create_dir_all(dir)?;
+            set_dir_permissions(dir)?;
+        }
+        let partial = self.path.with_extension("partial");
+        Ok(CacheWriter {
+            writer: BufWriter::new(File::create(&partial)?),
+            partial,
+            path: self.path.clone(),
+            ttl: self.ttl,
+        })
+    }
+}
+
+fn expiry(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Remove the entries whose TTL ran out, whatever query they were for
+pub fn purge_expired(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "ndjson")
+            && expiry(&path).is_some_and(|expires| expires <= now)
+        {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+// Hits written while the extraction runs, kept only once it completed
+pub struct CacheWriter {
+    writer: BufWriter<File>,
+    partial: PathBuf,
+    path: PathBuf,
+    ttl: Duration,
+}
+
+impl CacheWriter {
+    pub fn write(&mut self, hits: &[Hit]) -> Result<(), ESQError> {
+        for hit in hits {
+            writeln!(self.writer, "{}", serde_json::to_string(hit)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn commit(mut self) -> Result<(), ESQError> {
+        self.writer.flush()?;
+        self.writer
+            .get_ref()
+            .set_modified(SystemTime::now() + self.ttl)?;
+        fs::rename(&self.partial, &self.path)?;
+        Ok(())
+    }
+}
+
+// A failed or interrupted extraction leaves nothing behind
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.partial);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("cache");
+        let key = json!({"index": "logs", "from": "1h", "to": "now"});
+        let cache = QueryCache::new(&dir, &key, Duration::from_secs(600));
+        assert!(cache.fresh(10).unwrap().is_none());
+
+        let hits: Vec<Hit> =
+            serde_json::from_value(json!([{"_id": "1", "_source": {"message": "a"}}])).unwrap();
+        let mut writer = cache.writer().unwrap();
+        writer.write(&hits).unwrap();
+        drop(writer);
+        assert!(cache.fresh(10).unwrap().is_none());
+
+        let mut writer = cache.writer().unwrap();
+        writer.write(&hits).unwrap();
+        writer.commit().unwrap();
+        let batches: Vec<Vec<Hit>> = cache
+            .fresh(10)
+            .unwrap()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0][0].source, hits[0].source);
+
+        // The TTL is the one the entry was written with
+        let shorter = QueryCache::new(&dir, &key, Duration::ZERO);
+        assert!(shorter.fresh(10).unwrap().is_some());
+        purge_expired(&dir);
+        assert!(cache.path.exists());
+
+        let other = QueryCache::new(&dir, &json!({"index": "other"}), Duration::ZERO);
+        let mut writer = other.writer().unwrap();
+        writer.write(&hits).unwrap();
+        writer.commit().unwrap();
+        assert!(other.fresh(10).unwrap().is_none());
+        purge_expired(&dir);
+        assert!(!other.path.exists());
+        assert!(cache.path.exists());
+    }
+}
@@ -0,0 +1,73 @@
+// src/color.rs
+use crate::output::lookup_field;
+use clap::ValueEnum;
+use serde_json::Value;
+use std::io::IsTerminal;
+
+// Checked in order, the first string found gives the level of an entry
+const LEVEL_FIELDS: [&str; 3] = ["level", "log.level", "severity"];
+const RESET: &str = "\x1b[0m";
+pub const DIM: &str = "\x1b[2m";
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    // Auto colors terminals unless NO_COLOR is set, see https://no-color.org
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                std::io::stdout().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+pub fn paint(text: &str, color: &str) -> String {
+    format!("{}{}{}", color, text, RESET)
+}
+
+// Color of an entry by its level, none for INFO and levels it does not know
+pub fn level_color(source: &Value) -> Option<&'static str> {
+    let level = LEVEL_FIELDS
+        .iter()
+        .find_map(|field| lookup_field(source, field)?.as_str())?;
+    match level.trim().to_ascii_uppercase().as_str() {
+        "FATAL" | "CRITICAL" | "CRIT" | "EMERGENCY" | "EMERG" | "ALERT" | "PANIC" => {
+            Some("\x1b[1;31m")
+        }
+        "ERROR" | "ERR" => Some("\x1b[31m"),
+        "WARN" | "WARNING" => Some("\x1b[33m"),
+        "DEBUG" | "TRACE" => Some("\x1b[90m"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_level_color() {
+        assert_eq!(level_color(&json!({"level": "error"})), Some("\x1b[31m"));
+        assert_eq!(
+            level_color(&json!({"log": {"level": "WARNING"}})),
+            Some("\x1b[33m")
+        );
+        assert_eq!(
+            level_color(&json!({"severity": "fatal"})),
+            Some("\x1b[1;31m")
+        );
+        assert_eq!(level_color(&json!({"level": "INFO"})), None);
+        assert_eq!(level_color(&json!({"level": 3, "message": "ERROR"})), None);
+        assert_eq!(paint("boom", "\x1b[31m"), "\x1b[31mboom\x1b[0m");
+    }
+}
@@ -1,5 +1,21 @@
+// src/commands/alias.rs
+use crate::commands::cat::{CatArgs, QuerySpec, run_cat};
+use crate::context::AppContext;
+use crate::elasticsearch::client::ElasticsearchClient;
 use crate::utils::*;
 use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// Copy of the aliases of aliases_url, next to the configuration
+pub const SHARED_ALIASES_FILE: &str = "shared-aliases.toml";
+// Age after which the shared aliases are fetched again
+const SHARED_ALIASES_TTL: Duration = Duration::from_secs(3600);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Subcommand)]
 pub enum AliasCommands {
@@ -12,16 +28,16 @@ pub enum AliasCommands {
         #[arg(value_name = "alias")]
         alias: String,
 
-        /// Index name
-        #[arg(value_name = "index")]
-        index: String,
+        /// Index names or patterns, prefixed with a profile to query another cluster (e.g. prod-us/logs-*)
+        #[arg(value_name = "[profile/]index", required = true)]
+        targets: Vec<String>,
 
         /// Select specific fields (comma-separated)
         #[arg(long, value_name = "field1,field2,..")]
         #[arg(short = 's')]
         select: Option<String>,
 
-        /// Filter results with a specific Elasticsearch query
+        /// Filter results with a Lucene query string
         #[arg(long, value_name = "query")]
         #[arg(short = 'q')]
         query: Option<String>,
@@ -33,40 +49,610 @@ pub enum AliasCommands {
         #[arg(value_name = "alias")]
         alias: String,
     },
+
+    /// Print aliases as TOML, e.g. to share them with a team
+    Export {
+        /// Aliases to export, all of them when none is given
+        #[arg(value_name = "alias")]
+        aliases: Vec<String>,
+    },
+
+    /// Import the aliases of a file written by esq alias export
+    Import {
+        /// File to import
+        #[arg(value_name = "file")]
+        file: PathBuf,
+
+        /// Keep the aliases missing from the file instead of removing them
+        #[arg(long)]
+        merge: bool,
+    },
+
+    /// Fetch the shared aliases of aliases_url again
+    Sync,
+}
+
+// Aliases exchanged between people, shaped like the configuration so the file can also be included
+#[derive(Serialize, Deserialize, Default)]
+struct AliasFile {
+    #[serde(default)]
+    aliases: BTreeMap<String, LocalAlias>,
 }
 
-fn handle_list_aliases() -> Result<(), ESQError> {
-    println!("Listing aliases...");
-    // TODO: Implement alias listing
-    Err(ESQError::NotYetImplemented("alias listing".to_string()))
+fn parse_alias_file(text: &str, origin: &str) -> Result<BTreeMap<String, LocalAlias>, ESQError> {
+    let file: AliasFile = toml::from_str(text)
+        .map_err(|e| ESQError::ConfigError(format!("Invalid alias file {}: {}", origin, e)))?;
+    for (name, alias) in &file.aliases {
+        if alias.targets.is_empty() {
+            return Err(ESQError::ConfigError(format!(
+                "Alias '{}' of {} has no targets",
+                name, origin
+            )));
+        }
+        for target in &alias.targets {
+            validate_index_pattern(parse_alias_target(target).1)?;
+        }
+    }
+    Ok(file.aliases)
+}
+
+// Imported aliases replace the ones of the same name, the others are removed unless merging
+fn import_aliases(
+    aliases: &mut BTreeMap<String, LocalAlias>,
+    imported: BTreeMap<String, LocalAlias>,
+    merge: bool,
+) -> String {
+    let total = imported.len();
+    let (mut added, mut updated) = (0, 0);
+    let before = aliases.len();
+    if !merge {
+        aliases.retain(|name, _| imported.contains_key(name));
+    }
+    let removed = before - aliases.len();
+    for (name, alias) in imported {
+        match aliases.insert(name, alias.clone()) {
+            None => added += 1,
+            Some(previous) if previous != alias => updated += 1,
+            Some(_) => {}
+        }
+    }
+    format!(
+        "Imported {} aliases: {} added, {} updated, {} removed",
+        total, added, updated, removed
+    )
+}
+
+fn fetch_alias_file(url: &str) -> Result<String, ESQError> {
+    let response = reqwest::blocking::Client::builder()
+        .user_agent(concat!("esq/", env!("CARGO_PKG_VERSION")))
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()?;
+    if !response.status().is_success() {
+        return Err(ESQError::NetworkError(format!(
+            "Failed to fetch shared aliases from {}. Status code: {}",
+            url,
+            response.status()
+        )));
+    }
+    Ok(response.text()?)
+}
+
+fn cache_is_fresh(cache_file: &Path) -> bool {
+    fs::metadata(cache_file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < SHARED_ALIASES_TTL)
+}
+
+// Aliases of aliases_url, read from the cache while it is fresh or when the server cannot be reached
+pub fn shared_aliases(
+    url: &str,
+    cache_file: &Path,
+    refresh: bool,
+) -> Result<BTreeMap<String, LocalAlias>, ESQError> {
+    let origin = cache_file.display().to_string();
+    if !refresh
+        && cache_is_fresh(cache_file)
+        && let Ok(text) = fs::read_to_string(cache_file)
+    {
+        return parse_alias_file(&text, &origin);
+    }
+    match fetch_alias_file(url) {
+        Ok(text) => {
+            let aliases = parse_alias_file(&text, url)?;
+            fs::write(cache_file, text)?;
+            Ok(aliases)
+        }
+        Err(e) if !refresh && cache_file.exists() => {
+            eprintln!("Warning: {}, using the shared aliases cached earlier", e);
+            parse_alias_file(&fs::read_to_string(cache_file)?, &origin)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn alias_line(name: &str, alias: &LocalAlias) -> String {
+    let mut line = format!("{} -> {}", name, alias.targets.join(", "));
+    if let Some(select) = &alias.select {
+        line.push_str(&format!(" (select {})", select));
+    }
+    if let Some(query) = &alias.query {
+        line.push_str(&format!(" (query {})", query));
+    }
+    line
+}
+
+fn handle_list_aliases(
+    config: &Config,
+    shared: &BTreeMap<String, LocalAlias>,
+) -> Result<(), ESQError> {
+    if config.aliases.is_empty() && shared.is_empty() {
+        println!("No aliases defined");
+    }
+    for (name, alias) in &config.aliases {
+        println!("{}", alias_line(name, alias));
+    }
+    // Shared aliases under the name of a local one are hidden by it
+    for (name, alias) in shared {
+        if !config.aliases.contains_key(name) {
+            println!("{} [shared]", alias_line(name, alias));
+        }
+    }
+    Ok(())
 }
 
 fn handle_add_alias(
+    mut config: Config,
+    config_file: &PathBuf,
     alias: &str,
-    index: &str,
-    _select: &Option<String>,
-    _query: &Option<String>,
+    targets: &[String],
+    select: &Option<String>,
+    query: &Option<String>,
+) -> Result<(), ESQError> {
+    for target in targets {
+        let (profile, index) = parse_alias_target(target);
+        validate_index_pattern(index)?;
+        if let Some(profile) = profile {
+            config.for_profile(Some(profile))?;
+        }
+    }
+    config.aliases.insert(
+        alias.to_string(),
+        LocalAlias {
+            targets: targets.to_vec(),
+            select: select.clone(),
+            query: query.clone(),
+        },
+    );
+    save_config(&config, config_file)?;
+    println!("{}", alias_line(alias, &config.aliases[alias]));
+    Ok(())
+}
+
+fn handle_delete_alias(
+    mut config: Config,
+    config_file: &PathBuf,
+    alias: &str,
+) -> Result<(), ESQError> {
+    if config.aliases.remove(alias).is_none() {
+        return Err(ESQError::ValidationError(format!(
+            "No alias named '{}'",
+            alias
+        )));
+    }
+    save_config(&config, config_file)?;
+    println!("Alias '{}' deleted", alias);
+    Ok(())
+}
+
+fn handle_export_aliases(config: &Config, names: &[String]) -> Result<(), ESQError> {
+    let aliases = if names.is_empty() {
+        config.aliases.clone()
+    } else {
+        names
+            .iter()
+            .map(|name| match config.aliases.get(name) {
+                Some(alias) => Ok((name.clone(), alias.clone())),
+                None => Err(ESQError::ValidationError(format!(
+                    "No alias named '{}'",
+                    name
+                ))),
+            })
+            .collect::<Result<_, _>>()?
+    };
+    if aliases.is_empty() {
+        eprintln!("No aliases defined");
+    }
+    print!("{}", toml::to_string(&AliasFile { aliases })?);
+    Ok(())
+}
+
+fn handle_import_aliases(
+    mut config: Config,
+    config_file: &PathBuf,
+    file: &Path,
+    merge: bool,
 ) -> Result<(), ESQError> {
-    println!("Adding alias '{}' for index '{}'...", alias, index);
-    // TODO: Implement alias creation
-    Err(ESQError::NotYetImplemented("alias creation".to_string()))
+    let text = fs::read_to_string(file).map_err(|e| {
+        ESQError::ConfigError(format!("Cannot read alias file {}: {}", file.display(), e))
+    })?;
+    let imported = parse_alias_file(&text, &file.display().to_string())?;
+    // Profiles are personal, an alias may name one this configuration does not have yet
+    for (name, alias) in &imported {
+        for target in &alias.targets {
+            if let (Some(profile), _) = parse_alias_target(target)
+                && !config.profiles.contains_key(profile)
+            {
+                eprintln!(
+                    "Warning: alias '{}' targets profile '{}', which is not configured",
+                    name, profile
+                );
+            }
+        }
+    }
+    let summary = import_aliases(&mut config.aliases, imported, merge);
+    save_config(&config, config_file)?;
+    println!("{}", summary);
+    Ok(())
 }
 
-fn handle_delete_alias(alias: &str) -> Result<(), ESQError> {
-    println!("Deleting alias '{}'...", alias);
-    // TODO: Implement alias deletion
-    Err(ESQError::NotYetImplemented("alias deletion".to_string()))
+fn handle_sync_aliases(config: &Config, cache_file: &Path) -> Result<(), ESQError> {
+    let url = config.aliases_url.as_deref().ok_or_else(|| {
+        ESQError::ConfigError("aliases_url is not set in the configuration".to_string())
+    })?;
+    let aliases = shared_aliases(url, cache_file, true)?;
+    println!("Synced {} shared aliases from {}", aliases.len(), url);
+    Ok(())
 }
 
-pub fn handle_alias_command(command: &AliasCommands) -> Result<(), ESQError> {
+pub fn handle_alias_command(command: &AliasCommands, context: &AppContext) -> Result<(), ESQError> {
+    let config = context.require_config()?.clone();
     match command {
-        AliasCommands::List => handle_list_aliases(),
+        AliasCommands::List => handle_list_aliases(&config, context.shared_aliases()),
         AliasCommands::Add {
             alias,
-            index,
+            targets,
             select,
             query,
-        } => handle_add_alias(alias, index, select, query),
-        AliasCommands::Delete { alias } => handle_delete_alias(alias),
+        } => handle_add_alias(
+            config,
+            context.writable_config_file()?,
+            alias,
+            targets,
+            select,
+            query,
+        ),
+        AliasCommands::Delete { alias } => {
+            handle_delete_alias(config, context.writable_config_file()?, alias)
+        }
+        AliasCommands::Export { aliases } => handle_export_aliases(&config, aliases),
+        AliasCommands::Import { file, merge } => {
+            handle_import_aliases(config, context.writable_config_file()?, file, *merge)
+        }
+        AliasCommands::Sync => handle_sync_aliases(&config, &context.shared_aliases_file()),
+    }
+}
+
+// Where a command reads from once aliases are resolved, the index as given when it is not one
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedIndex {
+    pub profile: Option<String>,
+    pub index: String,
+    select: Option<String>,
+    query: Option<String>,
+}
+
+impl ResolvedIndex {
+    fn target(&self) -> String {
+        match &self.profile {
+            Some(profile) => format!("{}/{}", profile, self.index),
+            None => self.index.clone(),
+        }
+    }
+
+    // The fields of the alias unless the command selects its own
+    pub fn select(&self, select: &Option<String>) -> Option<String> {
+        select.clone().or_else(|| self.select.clone())
+    }
+
+    // Documents must match both the query of the alias and the one of the command
+    pub fn query(&self, query: &Option<String>) -> Option<String> {
+        match (&self.query, query) {
+            (Some(alias), Some(query)) => Some(format!("({}) AND ({})", alias, query)),
+            (alias, query) => alias.clone().or_else(|| query.clone()),
+        }
+    }
+
+    pub fn apply_to_cat(&self, args: &mut CatArgs) {
+        args.index = Some(self.index.clone());
+        args.select_clause = self.select(&args.select_clause);
+        args.query = self.query(&args.query);
+    }
+
+    pub fn apply_to_spec(&self, spec: &mut QuerySpec) {
+        spec.index.clone_from(&self.index);
+        spec.projection.select_clause = self.select(&spec.projection.select_clause);
+        spec.filters.query = self.query(&spec.filters.query);
+    }
+}
+
+// One per target of the alias of that name, or the index itself
+pub fn resolve_targets(context: &AppContext, name: &str) -> Vec<ResolvedIndex> {
+    let Some(alias) = context.local_alias(name) else {
+        return vec![ResolvedIndex {
+            profile: None,
+            index: name.to_string(),
+            select: None,
+            query: None,
+        }];
+    };
+    alias
+        .targets
+        .iter()
+        .map(|target| {
+            let (profile, index) = parse_alias_target(target);
+            ResolvedIndex {
+                profile: profile.map(str::to_string),
+                index: index.to_string(),
+                select: alias.select.clone(),
+                query: alias.query.clone(),
+            }
+        })
+        .collect()
+}
+
+// The one cluster and index expression read by the commands other than cat
+pub fn resolve_index(context: &AppContext, name: &str) -> Result<ResolvedIndex, ESQError> {
+    merge_targets(name, resolve_targets(context, name))
+}
+
+// Targets on the same cluster are read together as a comma-separated index expression
+fn merge_targets(name: &str, targets: Vec<ResolvedIndex>) -> Result<ResolvedIndex, ESQError> {
+    let mut targets = targets.into_iter();
+    let Some(mut merged) = targets.next() else {
+        return Err(ESQError::ConfigError(format!(
+            "Alias '{}' has no targets",
+            name
+        )));
+    };
+    for target in targets {
+        if target.profile != merged.profile {
+            return Err(ESQError::ValidationError(format!(
+                "Alias '{}' spans several profiles, only cat can query them together",
+                name
+            )));
+        }
+        merged.index = format!("{},{}", merged.index, target.index);
+    }
+    Ok(merged)
+}
+
+// One extraction per target, with the configuration of its profile
+fn alias_runs(
+    context: &AppContext,
+    targets: &[ResolvedIndex],
+    args: &CatArgs,
+) -> Result<Vec<(Config, CatArgs)>, ESQError> {
+    let group = targets.len() > 1;
+    targets
+        .iter()
+        .map(|target| {
+            let mut args = args.clone();
+            target.apply_to_cat(&mut args);
+            if group {
+                args.output.tag = Some(target.target());
+            }
+            Ok((context.config_for(target.profile.as_deref())?, args))
+        })
+        .collect()
+}
+
+// Run cat on resolved targets, those of an alias group in parallel with tagged output
+pub fn run_targets_cat(
+    context: &AppContext,
+    targets: &[ResolvedIndex],
+    args: &CatArgs,
+) -> Result<(), ESQError> {
+    let mut runs = alias_runs(context, targets, args)?;
+    if runs.len() == 1
+        && let Some((config, args)) = runs.pop()
+    {
+        return run_cat(ElasticsearchClient::new(config)?, &args, None);
+    }
+    if args.record_session.is_some() {
+        return Err(ESQError::ValidationError(
+            "--record-session cannot record the parallel queries of an alias group".to_string(),
+        ));
+    }
+    if args.output.output_file.is_some() {
+        return Err(ESQError::ValidationError(
+            "--output-file cannot be shared by the parallel queries of an alias group".to_string(),
+        ));
+    }
+
+    let results: Vec<(String, Result<(), ESQError>)> = thread::scope(|scope| {
+        let handles: Vec<_> = runs
+            .into_iter()
+            .map(|(config, args)| {
+                scope.spawn(move || {
+                    let tag = args.output.tag.clone().unwrap_or_default();
+                    let result =
+                        ElasticsearchClient::new(config).and_then(|es| run_cat(es, &args, None));
+                    (tag, result)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("alias extraction thread panicked"))
+            .collect()
+    });
+
+    // Every target ran to its end, the failures are reported together
+    let total = results.len();
+    let mut failures = 0;
+    for (tag, result) in results {
+        if let Err(e) = result {
+            eprintln!("[{}] {}", tag, e);
+            failures += 1;
+        }
+    }
+    if failures > 0 {
+        return Err(ESQError::ESError(format!(
+            "{} of {} alias targets failed",
+            failures, total
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alias_line() {
+        let alias = LocalAlias {
+            targets: vec!["prod-eu/logs-*".to_string(), "prod-us/logs-*".to_string()],
+            select: Some("message".to_string()),
+            query: None,
+        };
+        assert_eq!(
+            alias_line("all-prod", &alias),
+            "all-prod -> prod-eu/logs-*, prod-us/logs-* (select message)"
+        );
+    }
+
+    fn resolved(profile: Option<&str>, index: &str) -> ResolvedIndex {
+        ResolvedIndex {
+            profile: profile.map(str::to_string),
+            index: index.to_string(),
+            select: Some("message".to_string()),
+            query: Some("NOT level:DEBUG".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_merge_targets() {
+        let merged = merge_targets(
+            "api",
+            vec![resolved(None, "api-*"), resolved(None, "gateway-*")],
+        )
+        .unwrap();
+        assert_eq!(merged.index, "api-*,gateway-*");
+        assert_eq!(
+            merged.query(&Some("status:500".to_string())),
+            Some("(NOT level:DEBUG) AND (status:500)".to_string())
+        );
+        assert_eq!(
+            merged.select(&Some("@timestamp".to_string())),
+            Some("@timestamp".to_string())
+        );
+        assert!(
+            merge_targets(
+                "all-prod",
+                vec![
+                    resolved(Some("prod-eu"), "logs-*"),
+                    resolved(Some("prod-us"), "logs-*")
+                ],
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_alias_target() {
+        assert_eq!(
+            parse_alias_target("prod-eu/logs-*"),
+            (Some("prod-eu"), "logs-*")
+        );
+        assert_eq!(parse_alias_target("logs-*"), (None, "logs-*"));
+    }
+
+    fn alias(targets: &[&str]) -> LocalAlias {
+        LocalAlias {
+            targets: targets.iter().map(|target| target.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_alias_file_round_trip() {
+        let mut aliases = BTreeMap::new();
+        aliases.insert(
+            "all-prod".to_string(),
+            LocalAlias {
+                query: Some("NOT level:DEBUG".to_string()),
+                ..alias(&["prod-eu/logs-*", "prod-us/logs-*"])
+            },
+        );
+        let text = toml::to_string(&AliasFile {
+            aliases: aliases.clone(),
+        })
+        .unwrap();
+        assert!(text.starts_with("[aliases.all-prod]"), "{}", text);
+        assert_eq!(parse_alias_file(&text, "test").unwrap(), aliases);
+
+        assert!(parse_alias_file("[aliases.empty]\ntargets = []\n", "test").is_err());
+        assert!(parse_alias_file("[aliases.bad]\ntargets = [\"-logs\"]\n", "test").is_err());
+    }
+
+    #[test]
+    fn test_import_aliases() {
+        let mut aliases = BTreeMap::from([
+            ("api".to_string(), alias(&["api-*"])),
+            ("mine".to_string(), alias(&["scratch-*"])),
+        ]);
+        let imported = BTreeMap::from([
+            ("api".to_string(), alias(&["api-v2-*"])),
+            ("web".to_string(), alias(&["web-*"])),
+        ]);
+
+        let mut merged = aliases.clone();
+        assert_eq!(
+            import_aliases(&mut merged, imported.clone(), true),
+            "Imported 2 aliases: 1 added, 1 updated, 0 removed"
+        );
+        assert!(merged.contains_key("mine"));
+
+        assert_eq!(
+            import_aliases(&mut aliases, imported.clone(), false),
+            "Imported 2 aliases: 1 added, 1 updated, 1 removed"
+        );
+        assert_eq!(aliases, imported);
+    }
+
+    #[test]
+    fn test_shared_aliases_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join("shared-aliases.toml");
+        fs::write(&cache_file, "[aliases.web]\ntargets = [\"web-*\"]\n").unwrap();
+        // Nothing listens on port 1
+        let url = "http://127.0.0.1:1/aliases.toml";
+
+        // A fresh cache is read without fetching
+        let aliases = shared_aliases(url, &cache_file, false).unwrap();
+        assert_eq!(aliases["web"], alias(&["web-*"]));
+
+        // A stale one stands in for a server that cannot be reached, unless syncing
+        let stale = std::time::SystemTime::now() - SHARED_ALIASES_TTL * 2;
+        fs::File::options()
+            .write(true)
+            .open(&cache_file)
+            .unwrap()
+            .set_modified(stale)
+            .unwrap();
+        assert!(
+            shared_aliases(url, &cache_file, false)
+                .unwrap()
+                .contains_key("web")
+        );
+        assert!(shared_aliases(url, &cache_file, true).is_err());
+
+        fs::remove_file(&cache_file).unwrap();
+        assert!(shared_aliases(url, &cache_file, false).is_err());
     }
 }
@@ -0,0 +1,150 @@
+// src/commands/assert.rs
+use crate::commands::cat::{QueryFilters, QuerySpec, TimeWindow};
+use crate::elasticsearch::client::ElasticsearchClient;
+use crate::project::{QueryTarget, require_index};
+use crate::utils::*;
+use clap::Args;
+
+pub const EXAMPLES: &str = "\
+Examples:
+  # Fail a deployment pipeline on errors in the last 10 minutes
+  esq assert my-logs --from 10m --where level:ERROR --max 0
+
+  # Check that logs are still flowing
+  esq assert my-logs --from 5m --min 1";
+
+#[derive(Args)]
+pub struct AssertArgs {
+    /// Index name or pattern to check
+    #[arg(value_name = "index")]
+    pub index: Option<String>,
+
+    /// Start time for filtering results
+    #[arg(long, value_name = "datetime")]
+    #[arg(short = 'F')]
+    pub from: Option<String>,
+
+    /// End time for filtering results
+    #[arg(long, value_name = "datetime")]
+    #[arg(short = 'T')]
+    pub to: Option<String>,
+
+    /// Swap --from and --to when given in reverse order instead of failing
+    #[arg(long)]
+    pub swap_ok: bool,
+
+    /// Filter results with specific values in fields
+    #[arg(long = "where", value_name = "field1:value1,field2:value2,..")]
+    #[arg(short = 'w')]
+    pub where_clause: Option<String>,
+
+    /// Fail when more documents than this match
+    #[arg(long, value_name = "count")]
+    pub max: Option<u64>,
+
+    /// Fail when fewer documents than this match
+    #[arg(long, value_name = "count")]
+    pub min: Option<u64>,
+}
+
+impl AssertArgs {
+    pub fn query_spec(&self) -> Result<QuerySpec, ESQError> {
+        Ok(QuerySpec {
+            index: require_index(&self.index)?.to_string(),
+            window: TimeWindow {
+                from: self.from.clone(),
+                to: self.to.clone(),
+                ..Default::default()
+            },
+            filters: QueryFilters {
+                where_clause: self.where_clause.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    pub fn query_target(&mut self) -> QueryTarget<'_> {
+        QueryTarget {
+            index: &mut self.index,
+            select: None,
+            where_clause: &mut self.where_clause,
+            from: &mut self.from,
+            to: &mut self.to,
+        }
+    }
+}
+
+fn check_bounds(count: u64, min: Option<u64>, max: Option<u64>) -> Result<(), ESQError> {
+    if let Some(max) = max
+        && count > max
+    {
+        return Err(ESQError::AssertionError(format!(
+            "{} matching documents, expected at most {}",
+            count, max
+        )));
+    }
+    if let Some(min) = min
+        && count < min
+    {
+        return Err(ESQError::AssertionError(format!(
+            "{} matching documents, expected at least {}",
+            count, min
+        )));
+    }
+    Ok(())
+}
+
+pub fn handle_assert_command(
+    mut es: ElasticsearchClient,
+    spec: &QuerySpec,
+    min: Option<u64>,
+    max: Option<u64>,
+) -> Result<(), ESQError> {
+    if min.is_none() && max.is_none() {
+        return Err(ESQError::ValidationError(
+            "At least one of --min or --max is required.".to_string(),
+        ));
+    }
+    if let (Some(min), Some(max)) = (min, max)
+        && min > max
+    {
+        return Err(ESQError::ValidationError(
+            "The --min value cannot be greater than the --max value.".to_string(),
+        ));
+    }
+
+    validate_index_pattern(&spec.index)?;
+    let query = spec.count_query()?.build();
+
+    es.set_index(&spec.index);
+
+    let count = es.count(query.get("query"))?;
+    check_bounds(count, min, max)?;
+
+    println!("OK: {} matching documents", count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_bounds_max() {
+        assert!(check_bounds(0, None, Some(0)).is_ok());
+        assert!(check_bounds(1, None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_check_bounds_min() {
+        assert!(check_bounds(5, Some(5), None).is_ok());
+        assert!(check_bounds(4, Some(5), None).is_err());
+    }
+
+    #[test]
+    fn test_check_bounds_range() {
+        assert!(check_bounds(10, Some(5), Some(20)).is_ok());
+        assert!(check_bounds(25, Some(5), Some(20)).is_err());
+    }
+}
@@ -1,30 +1,126 @@
-use crate::elasticsearch::builder::SearchQueryBuilder;
+use crate::cache::{CacheWriter, QueryCache, purge_expired};
+use crate::elasticsearch::builder::{SearchQueryBuilder, time_range};
 use crate::elasticsearch::client::ElasticsearchClient;
+use crate::elasticsearch::model::{Hit, SearchResponse};
+use crate::elasticsearch::transport::SearchTransport;
+use crate::enrich::{EnrichArgs, Enricher};
+use crate::output::{
+    FollowStats, OutputArgs, Printer, ProgressBar, StatsPanel, field_matches, fields_as_source,
+    hit_timestamp, lookup_field,
+};
+use crate::project::{QueryTarget, require_index};
+use crate::session::{ClusterFingerprint, SessionRecorder};
+use crate::sink::{Sink, SinkArgs};
+use crate::snapshot::Snapshotter;
 use crate::utils::*;
+use crate::windows::{SpoolBatches, spawn_windows, split_range};
+use chrono::{DateTime, Utc};
 use clap::Args;
-use dateparser::parse;
 use serde_json::Value;
 use serde_json::json;
 use std::cmp;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::thread;
 use std::time::Duration;
 
 const BATCH_SIZE: u32 = 1000;
 const DEFAULT_NUMBER_OF_LINES: u32 = 10;
 const MAX_NUMBER_OF_LINES: u32 = 5000;
-const LATENCY: &str = "1m";
-
-#[derive(Args)]
+// Extractions of at least this many lines get a progress bar, smaller ones are over before it helps
+const PROGRESS_MIN_LINES: u32 = 10_000;
+pub const LATENCY: &str = "1m";
+// Counts have no follow window to respect and reach up to now
+const COUNT_LATENCY: &str = "";
+const MAX_PRUNED_TARGET_LEN: usize = 2048;
+const FOLLOW_MAX_RETRIES: u32 = 5;
+const THROTTLE_MAX_RETRIES: u32 = 8;
+const MIN_BATCH_SIZE: u32 = 50;
+// Bounds of the follow latency, the upper one being the fixed LATENCY
+const MIN_FOLLOW_LATENCY: Duration = Duration::from_secs(2);
+const MAX_FOLLOW_LATENCY: Duration = Duration::from_secs(60);
+// Ingest rate above which following without filters floods the terminal
+const FIREHOSE_DOCS_PER_SEC: f64 = 500.0;
+// Documents dated this recently give the ingest rate
+const RATE_WINDOW_SECS: u64 = 60;
+// Ingest delays it takes before trusting them over the fixed latency
+const MIN_LATENCY_SAMPLES: usize = 5;
+// Set by the ECS ingest pipelines, when the document reached Elasticsearch
+const INGESTED_FIELD: &str = "event.ingested";
+// Values per terms clause, the default index.max_terms_count
+const MAX_TERMS_PER_CLAUSE: usize = 65536;
+const DOCVALUE_TYPES: [&str; 15] = [
+    "long",
+    "integer",
+    "short",
+    "byte",
+    "double",
+    "float",
+    "half_float",
+    "scaled_float",
+    "unsigned_long",
+    "date",
+    "date_nanos",
+    "keyword",
+    "constant_keyword",
+    "boolean",
+    "ip",
+];
+
+// Keys of a search body that esq sets itself, with what decides them
+const DSL_MANAGED_KEYS: [(&str, &str); 11] = [
+    ("sort", "the extraction mode and --reverse"),
+    ("search_after", "the paging of esq"),
+    ("pit", "the paging of esq"),
+    ("from", "the paging of esq"),
+    ("size", "-n, --max-events and the batch size"),
+    ("_source", "--select and --exclude"),
+    ("fields", "--select and --fields-api"),
+    ("docvalue_fields", "--doc-values"),
+    ("stored_fields", "--select"),
+    ("terminate_after", "--terminate-after"),
+    ("track_total_hits", "esq"),
+];
+
+pub const EXAMPLES: &str = "\
+Examples:
+  # Last 10 entries, then follow new ones
+  esq cat my-logs -f
+
+  # Everything between two instants (a bounded export, read with a PIT)
+  esq cat my-logs --from 2024-06-01T10:00:00Z --to 2024-06-01T11:00:00Z
+
+  # The 500 entries before an instant, or sampled around several ones
+  esq cat my-logs --to 14:00 -n 500
+  esq cat my-logs -a 14:02 -a 14:37 -n 20
+
+  # Context around the document of an alert
+  esq cat my-logs --around-id xG3k2ZABcd
+
+  # Filters and projection combine with every mode
+  esq cat 'logs-*,-logs-debug-*' --from 1h --where level:ERROR --select @timestamp,message
+
+Modes: --around excludes --from/--to and --follow, --from and --to together read
+the whole window and take no -n, --follow cannot be combined with --from or --to.";
+
+#[derive(Args, Clone, Debug)]
 pub struct CatArgs {
-    /// Index name or alias to query
+    /// Index name or alias to query, "-pattern" entries exclude indices (e.g. 'logs-*,-logs-debug-*')
+    /// (defaults to the index of .esq.toml, "@name" runs a saved query of it)
     #[arg(value_name = "index_or_alias")]
-    pub index: String,
+    pub index: Option<String>,
 
-    /// Display entries around a specific time
+    /// Display entries around a specific time (repeatable)
     #[arg(long, value_name = "datetime")]
     #[arg(short = 'a')]
-    pub around: Option<String>,
+    pub around: Vec<String>,
+
+    /// Display entries around the timestamp of a specific document (repeatable)
+    #[arg(long, value_name = "doc_id")]
+    pub around_id: Vec<String>,
 
     /// Number of lines to display
     #[arg(short = 'n', value_name = "number_of_lines", default_value_t = DEFAULT_NUMBER_OF_LINES)]
@@ -40,20 +136,285 @@ pub struct CatArgs {
     #[arg(short = 'T')]
     pub to: Option<String>,
 
-    /// Select specific fields (comma-separated)
+    /// Swap --from and --to when given in reverse order instead of failing
+    #[arg(long)]
+    pub swap_ok: bool,
+
+    /// Select specific fields (comma-separated), wildcards included (e.g. kubernetes.*,message)
     #[arg(long = "select", value_name = "field1,field2,..")]
     #[arg(short = 's')]
     pub select_clause: Option<String>,
 
+    /// Leave fields out of the documents (comma-separated), e.g. huge ones such as stack_trace
+    #[arg(
+        long = "exclude",
+        value_name = "field1,field2,..",
+        conflicts_with = "fields_api"
+    )]
+    pub exclude_clause: Option<String>,
+
     /// Filter results with specific values in fields
     #[arg(long = "where", value_name = "field1:value1,field2:value2,..")]
     #[arg(short = 'w')]
     pub where_clause: Option<String>,
 
+    /// Keep documents whose field holds one of the values listed in a file, one per line (repeatable)
+    #[arg(long, value_name = "field@file", value_parser = parse_where_file)]
+    pub where_file: Vec<WhereFile>,
+
+    /// Query DSL as JSON, a query clause or a search body holding one, from a file or - for stdin
+    #[arg(long, value_name = "file|-", value_parser = parse_dsl)]
+    pub dsl: Option<Value>,
+
     /// Follow new entries in the index in real-time
     #[arg(long)]
     #[arg(short = 'f')]
     pub follow: bool,
+
+    /// While following, also write the documents of each period to a timestamped file (e.g. 5m)
+    #[arg(long, value_name = "duration", value_parser = parse_duration_arg, requires = "follow")]
+    pub snapshot_every: Option<Duration>,
+
+    /// While following, print a status line to stderr when nothing arrived for this long (e.g. 60s)
+    #[arg(long, value_name = "duration", value_parser = parse_duration_arg, requires = "follow")]
+    pub heartbeat: Option<Duration>,
+
+    /// While following, keep counts per level and service of the session on stderr, redrawn after each batch
+    #[arg(long, requires = "follow")]
+    pub stats_panel: bool,
+
+    /// Stop following at this time (e.g. when a deploy window ends)
+    #[arg(long, value_name = "datetime", requires = "follow")]
+    pub until: Option<String>,
+
+    /// Stop following once this many entries were printed
+    #[arg(long, value_name = "count", requires = "follow")]
+    pub max_events: Option<u64>,
+
+    /// Stop as soon as this many entries were printed, like grep -m
+    #[arg(long, value_name = "count")]
+    #[arg(short = 'm')]
+    pub max_count: Option<u64>,
+
+    /// Directory receiving the --snapshot-every files, the current one by default
+    #[arg(long, value_name = "dir", requires = "snapshot_every")]
+    pub snapshot_dir: Option<PathBuf>,
+
+    /// Print the newest entries of a --from/--to window first, page by page
+    #[arg(long)]
+    pub reverse: bool,
+
+    /// Split a --from/--to range into this many sub-windows fetched concurrently, printed in order
+    #[arg(long, value_name = "n", value_parser = clap::value_parser!(u32).range(2..=32))]
+    pub parallel_windows: Option<u32>,
+
+    /// Query only the most recently created index matching the pattern
+    #[arg(long)]
+    #[arg(short = 'l')]
+    pub latest: bool,
+
+    /// Shell command to run when the extraction fails for good (e.g. a lost follow session)
+    #[arg(long, value_name = "command")]
+    pub on_error_exec: Option<String>,
+
+    /// Record the queries, response statuses and output into a shareable session file
+    #[arg(long, value_name = "file")]
+    pub record_session: Option<PathBuf>,
+
+    /// Keep the documents of a --from/--to window on disk for this long, re-runs print them without querying (e.g. 10m)
+    #[arg(
+        long,
+        value_name = "ttl",
+        value_parser = parse_duration_arg,
+        requires_all = ["from", "to"],
+        conflicts_with_all = ["parallel_windows", "record_session"]
+    )]
+    pub cache: Option<Duration>,
+
+    // Where --cache keeps the documents, none with --no-config
+    #[arg(skip)]
+    pub cache_dir: Option<PathBuf>,
+
+    // Where --parallel-windows spools the windows, none with --no-config
+    #[arg(skip)]
+    pub spool_dir: Option<PathBuf>,
+
+    /// Retrieve values through the fields API instead of _source
+    #[arg(long)]
+    pub fields_api: bool,
+
+    /// Read the --select fields from doc values instead of _source when all of them are numeric, date or keyword fields
+    #[arg(long, requires = "select_clause", conflicts_with_all = ["fields_api", "exclude_clause"])]
+    pub doc_values: bool,
+
+    /// Stop collecting after this many documents per shard
+    #[arg(long, value_name = "count")]
+    pub terminate_after: Option<u32>,
+
+    /// Return what the healthy shards found (true) or fail the search (false) when shards fail
+    #[arg(long, value_name = "true|false")]
+    pub allow_partial_results: Option<bool>,
+
+    /// Skip indices on the frozen tier and legacy frozen indices
+    #[arg(long)]
+    pub ignore_frozen: bool,
+
+    /// Run each batch as an async search, polled until done (for very slow queries)
+    #[arg(long = "async")]
+    pub async_search: bool,
+
+    /// Lucene query string, e.g. 'status:[500 TO 599] AND NOT method:OPTIONS', combined with --where
+    #[arg(short = 'q', long, value_name = "lucene")]
+    pub query: Option<String>,
+
+    /// When nothing matches, find out whether the index, the time range or a where filter is the cause
+    #[arg(long)]
+    pub explain_empty: bool,
+
+    #[command(flatten)]
+    pub enrich: EnrichArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub sink: SinkArgs,
+}
+
+// What a command reads, apart from how it prints it, built once the project defaults are applied
+#[derive(Clone, Debug, Default)]
+pub struct QuerySpec {
+    pub index: String,
+    pub window: TimeWindow,
+    pub filters: QueryFilters,
+    pub projection: Projection,
+}
+
+// How cat reads what its spec describes: limits, search options and output
+#[derive(Clone, Debug)]
+pub struct ExtractionOptions {
+    pub output: OutputArgs,
+    // Both --max-events and --max-count count printed entries, the lowest one ends the extraction
+    pub max_events: Option<u64>,
+    pub until: Option<String>,
+    pub reverse: bool,
+    pub parallel_windows: Option<u32>,
+    pub ignore_frozen: bool,
+    pub allow_partial_results: Option<bool>,
+    pub async_search: bool,
+    pub terminate_after: Option<u32>,
+    pub cache: Option<Duration>,
+    pub cache_dir: Option<PathBuf>,
+    pub spool_dir: Option<PathBuf>,
+    pub record_session: Option<PathBuf>,
+    pub heartbeat: Option<Duration>,
+    pub stats_panel: bool,
+    pub explain_empty: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TimeWindow {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    // Instants to read around, given as such or as the IDs of documents
+    pub around: Vec<String>,
+    pub around_ids: Vec<String>,
+    pub lines: u32,
+    pub follow: bool,
+    // Only the most recently created index matching the pattern
+    pub latest: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QueryFilters {
+    pub where_clause: Option<String>,
+    pub where_files: Vec<WhereFile>,
+    // Lucene query string of -q, ANDed with the one of a local alias
+    pub query: Option<String>,
+    // Query clause of --dsl
+    pub dsl: Option<Value>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Projection {
+    pub select_clause: Option<String>,
+    pub exclude_clause: Option<String>,
+    pub fields_api: bool,
+    // Asked with --doc-values, the values of doc values can differ from the ones of _source
+    pub doc_values: bool,
+}
+
+impl Projection {
+    fn excluded_fields(&self) -> Vec<String> {
+        self.exclude_clause
+            .iter()
+            .flat_map(|exclude| exclude.split(','))
+            .map(|field| field.trim().to_string())
+            .filter(|field| !field.is_empty())
+            .collect()
+    }
+}
+
+impl QuerySpec {
+    // The mode the time window asks for, with the select and where clauses parsed
+    fn validate(&self) -> Result<ValidationResult, ESQError> {
+        let around: Vec<String> = self
+            .window
+            .around
+            .iter()
+            .chain(&self.window.around_ids)
+            .cloned()
+            .collect();
+        validate_parameters(
+            &around,
+            &self.window.from,
+            &self.window.to,
+            &self.window.lines,
+            self.window.follow,
+            &self.projection.select_clause,
+            &self.filters.where_clause,
+        )
+    }
+
+    // Filters of the where files and --dsl, the where clause and the query string being added apart
+    pub fn filter_clauses(&self) -> Result<Vec<Value>, ESQError> {
+        Ok(self
+            .labeled_filter_clauses()?
+            .into_iter()
+            .map(|(_, clause)| clause)
+            .collect())
+    }
+
+    // The same clauses, each named after the option it came from
+    pub fn labeled_filter_clauses(&self) -> Result<Vec<(String, Value)>, ESQError> {
+        let mut clauses = self
+            .filters
+            .where_files
+            .iter()
+            .map(|file| Ok((format!("--where-file {}", file.describe()), file.clause()?)))
+            .collect::<Result<Vec<_>, ESQError>>()?;
+        if let Some(dsl) = &self.filters.dsl {
+            clauses.push(("--dsl".to_string(), dsl.clone()));
+        }
+        Ok(clauses)
+    }
+
+    // The where clause, the other filters and the time range, as counted by sizeof and assert
+    pub fn count_query(&self) -> Result<SearchQueryBuilder, ESQError> {
+        let where_filters = parse_where_clause(&self.filters.where_clause)?;
+        let mut builder = SearchQueryBuilder::new()
+            .with_query_match(gen_query_match(&where_filters))
+            .with_query_string(self.filters.query.as_deref())
+            .with_time_range(
+                self.window.from.as_deref(),
+                self.window.to.as_deref(),
+                COUNT_LATENCY,
+            )?;
+        for clause in self.filter_clauses()? {
+            builder = builder.with_query(|query| query.filter(clause));
+        }
+        Ok(builder)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -79,7 +440,116 @@ impl fmt::Display for ParameterCombination {
     }
 }
 
-#[derive(Debug)]
+// Values of a field read from a local file, e.g. user.id@ids.txt
+#[derive(Clone, Debug, PartialEq)]
+pub struct WhereFile {
+    field: String,
+    path: PathBuf,
+}
+
+pub fn parse_where_file(input: &str) -> Result<WhereFile, String> {
+    match input.split_once('@') {
+        Some((field, path)) if !field.trim().is_empty() && !path.trim().is_empty() => {
+            Ok(WhereFile {
+                field: field.trim().to_string(),
+                path: PathBuf::from(path.trim()),
+            })
+        }
+        _ => Err(format!(
+            "invalid where file '{}', expected field@file",
+            input
+        )),
+    }
+}
+
+impl WhereFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn describe(&self) -> String {
+        format!("{}@{}", self.field, self.path.display())
+    }
+
+    fn clause(&self) -> Result<Value, ESQError> {
+        let content = fs::read_to_string(&self.path).map_err(|e| {
+            ESQError::ValidationError(format!("Cannot read {}: {}", self.path.display(), e))
+        })?;
+        let mut seen = HashSet::new();
+        let values: Vec<&str> = content
+            .lines()
+            .map(str::trim)
+            .filter(|value| !value.is_empty() && seen.insert(*value))
+            .collect();
+        if values.is_empty() {
+            return Err(ESQError::ValidationError(format!(
+                "{} lists no values for {}",
+                self.path.display(),
+                self.field
+            )));
+        }
+        Ok(terms_clause(&self.field, &values))
+    }
+}
+
+// Read once when the arguments are parsed, stdin cannot be read again by a later count
+pub fn parse_dsl(input: &str) -> Result<Value, String> {
+    let content = match input {
+        "-" => std::io::read_to_string(std::io::stdin()),
+        path => fs::read_to_string(path),
+    }
+    .map_err(|e| format!("cannot read {}: {}", input, e))?;
+    let body = serde_json::from_str(&content)
+        .map_err(|e| format!("{} is not valid JSON: {}", input, e))?;
+    dsl_query(body)
+}
+
+// The query clause of a DSL document, refusing the keys esq manages itself
+fn dsl_query(body: Value) -> Result<Value, String> {
+    let Value::Object(mut body) = body else {
+        return Err("the DSL must be a JSON object".to_string());
+    };
+    for key in body.keys() {
+        if let Some((_, managed_by)) = DSL_MANAGED_KEYS.iter().find(|(managed, _)| managed == key) {
+            return Err(format!(
+                "'{}' is set by {}, leave it out of the DSL",
+                key, managed_by
+            ));
+        }
+    }
+    if let Some(query) = body.remove("query") {
+        if let Some(key) = body.keys().next() {
+            return Err(format!(
+                "only the query of a search body is read, '{}' is not supported",
+                key
+            ));
+        }
+        return dsl_query(query).map_err(|e| format!("in query: {}", e));
+    }
+    // A query clause has a single key naming its type, e.g. bool or match
+    match body.len() {
+        1 => Ok(Value::Object(body)),
+        0 => Err("the DSL holds no query".to_string()),
+        _ => Err(format!(
+            "a query clause has a single key, not {}",
+            body.keys().cloned().collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+// A terms query, split in clauses below the default max_terms_count when the list is longer
+fn terms_clause(field: &str, values: &[&str]) -> Value {
+    if values.len() <= MAX_TERMS_PER_CLAUSE {
+        return json!({"terms": {field: values}});
+    }
+    let chunks: Vec<Value> = values
+        .chunks(MAX_TERMS_PER_CLAUSE)
+        .map(|chunk| json!({"terms": {field: chunk}}))
+        .collect();
+    json!({"bool": {"should": chunks, "minimum_should_match": 1}})
+}
+
+#[derive(Debug, Clone)]
 pub struct WhereFilter {
     field: String,
     value: String,
@@ -91,8 +561,39 @@ pub struct ValidationResult {
     where_filters: Option<Vec<WhereFilter>>,
 }
 
+pub fn parse_where_clause(
+    where_clause: &Option<String>,
+) -> Result<Option<Vec<WhereFilter>>, ESQError> {
+    let Some(where_str) = where_clause else {
+        return Ok(None);
+    };
+    if where_str.is_empty() {
+        return Err(ESQError::ValidationError(
+            "Where clause cannot be empty".to_string(),
+        ));
+    }
+    let filters: Result<Vec<WhereFilter>, ESQError> = where_str
+        .split(',')
+        .map(|pair| {
+            let parts: Vec<&str> = pair.split(':').collect();
+            if parts.len() != 2 || parts[0].trim().is_empty() || parts[1].trim().is_empty() {
+                Err(ESQError::ValidationError(format!(
+                    "Invalid where clause format. Expected 'field:value', got '{}'",
+                    pair
+                )))
+            } else {
+                Ok(WhereFilter {
+                    field: parts[0].trim().to_string(),
+                    value: parts[1].trim().to_string(),
+                })
+            }
+        })
+        .collect();
+    Ok(Some(filters?))
+}
+
 fn validate_parameters(
-    around: &Option<String>,
+    around: &[String],
     from: &Option<String>,
     to: &Option<String>,
     lines: &u32,
@@ -121,35 +622,9 @@ fn validate_parameters(
         None
     };
 
-    let where_filters = if let Some(where_str) = where_clause {
-        if where_str.is_empty() {
-            return Err(ESQError::ValidationError(
-                "Where clause cannot be empty".to_string(),
-            ));
-        }
-        let filters: Result<Vec<WhereFilter>, ESQError> = where_str
-            .split(',')
-            .map(|pair| {
-                let parts: Vec<&str> = pair.split(':').collect();
-                if parts.len() != 2 || parts[0].trim().is_empty() || parts[1].trim().is_empty() {
-                    Err(ESQError::ValidationError(format!(
-                        "Invalid where clause format. Expected 'field:value', got '{}'",
-                        pair
-                    )))
-                } else {
-                    Ok(WhereFilter {
-                        field: parts[0].trim().to_string(),
-                        value: parts[1].trim().to_string(),
-                    })
-                }
-            })
-            .collect();
-        Some(filters?)
-    } else {
-        None
-    };
+    let where_filters = parse_where_clause(where_clause)?;
 
-    let mode = if around.is_some() {
+    let mode = if !around.is_empty() {
         if from.is_some() || to.is_some() {
             return Err(ESQError::ValidationError(
                 "The parameters --to and --from cannot be used at the same time as --around."
@@ -213,6 +688,103 @@ fn validate_parameters(
     })
 }
 
+// Batch size that halves under memory or load pressure and grows back gradually
+#[derive(Debug)]
+struct BatchSizer {
+    current: u32,
+    max: u32,
+    min: u32,
+}
+
+impl BatchSizer {
+    fn new(max: u32, min: u32) -> Self {
+        Self {
+            current: max,
+            max,
+            min,
+        }
+    }
+
+    fn current(&self) -> u32 {
+        self.current
+    }
+
+    fn can_shrink(&self) -> bool {
+        self.current > self.min
+    }
+
+    fn shrink(&mut self) {
+        self.current = cmp::max(self.current / 2, self.min);
+    }
+
+    fn grow(&mut self) {
+        self.current = cmp::min(self.current + self.current / 2, self.max);
+    }
+}
+
+// Follow latency sized from the ingest delays of the followed documents. It never goes below
+// twice the worst one seen, a shorter latency would skip the documents as late as that one
+#[derive(Debug, Default)]
+struct AdaptiveLatency {
+    worst: Duration,
+    samples: usize,
+}
+
+impl AdaptiveLatency {
+    fn observe(&mut self, delay: Duration) {
+        self.worst = cmp::max(self.worst, delay);
+        self.samples += 1;
+    }
+
+    // The fixed latency until enough delays were seen, e.g. without event.ingested
+    fn current(&self) -> Duration {
+        if self.samples < MIN_LATENCY_SAMPLES {
+            return MAX_FOLLOW_LATENCY;
+        }
+        (self.worst * 2).clamp(MIN_FOLLOW_LATENCY, MAX_FOLLOW_LATENCY)
+    }
+
+    // Date math only goes down to seconds
+    fn date_math(&self) -> String {
+        format!("{}s", self.current().as_secs_f64().ceil() as u64)
+    }
+}
+
+// Documents per second dated within the last minute, from a single count with no wait
+fn indexing_rate(es: &ElasticsearchClient) -> Option<f64> {
+    let recent = es
+        .count(Some(&json!({"range": {time_field(): {
+            "gte": format!("now-{}s", RATE_WINDOW_SECS)
+        }}})))
+        .ok()?;
+    Some(recent as f64 / RATE_WINDOW_SECS as f64)
+}
+
+fn firehose_warning(index: &str, docs_per_sec: f64) -> Option<String> {
+    (docs_per_sec >= FIREHOSE_DOCS_PER_SEC).then(|| {
+        format!(
+            "'{}' receives about {:.0} documents/s, following it unfiltered prints as many lines; \
+             narrow it with --where or --where-file",
+            index, docs_per_sec
+        )
+    })
+}
+
+// Time from the event to its indexing, None when the document has no event.ingested, e.g.
+// when --select leaves it out
+fn ingest_delay(hit: &Hit) -> Option<Duration> {
+    let ingested = lookup_field(hit.source(), INGESTED_FIELD)
+        .and_then(Value::as_str)
+        .and_then(|ingested| DateTime::parse_from_rfc3339(ingested).ok())?;
+    let timestamp = hit_timestamp(hit)?;
+    // Events dated after their ingestion come from clocks running ahead, read as no delay
+    Some(
+        (ingested.with_timezone(&Utc) - timestamp)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
 #[derive(Debug)]
 struct SeekOriginParameters {
     datetime: Option<String>,
@@ -228,13 +800,57 @@ struct ExtractionParameters {
     seek_origin: Option<SeekOriginParameters>,
     sort_order: Value,
     sleep_between_batches: bool,
+    // Set when following, the time range is then bounded again before each batch
+    latency: Option<AdaptiveLatency>,
+    limits: ExtractionLimits,
+}
+
+// Where an extraction ends before its natural end, e.g. an unattended follow session
+#[derive(Debug, Default)]
+struct ExtractionLimits {
+    until: Option<DateTime<Utc>>,
+    events_left: Option<u64>,
+}
+
+impl ExtractionLimits {
+    fn new(until: &Option<String>, max_events: Option<u64>) -> Result<Self, ESQError> {
+        let until = until.as_deref().map(parse_datetime).transpose()?;
+        if let Some(until) = until
+            && until <= Utc::now()
+        {
+            return Err(ESQError::ValidationError(format!(
+                "--until {} is already in the past",
+                until.to_rfc3339()
+            )));
+        }
+        Ok(Self {
+            until,
+            events_left: max_events,
+        })
+    }
+
+    fn expired(&self) -> bool {
+        self.until.is_some_and(|until| Utc::now() >= until)
+    }
+
+    // Keep the hits still allowed, the last ones of the batch going over --max-events
+    fn take(&mut self, hits: &mut Vec<Hit>) {
+        if let Some(left) = &mut self.events_left {
+            hits.truncate(usize::try_from(*left).unwrap_or(usize::MAX));
+            *left -= hits.len() as u64;
+        }
+    }
+
+    fn exhausted(&self) -> bool {
+        self.events_left == Some(0)
+    }
 }
 
 impl ExtractionParameters {
     fn from_mode(
         validation: &ValidationResult,
         lines: &u32,
-        around: &Option<String>,
+        around: Option<&String>,
         to: &Option<String>,
     ) -> Result<Self, ESQError> {
         match validation.mode {
@@ -244,11 +860,13 @@ impl ExtractionParameters {
                 query_match: gen_query_match(&validation.where_filters),
                 search_after: None,
                 seek_origin: Some(SeekOriginParameters {
-                    datetime: around.clone(),
+                    datetime: around.cloned(),
                     size: *lines / 2,
                 }),
-                sort_order: json!([{"@timestamp": {"order": "asc"}}, {"_shard_doc": {"order": "asc"}}]),
+                sort_order: json!([{time_field(): {"order": "asc"}}, {"_shard_doc": {"order": "asc"}}]),
                 sleep_between_batches: false,
+                latency: None,
+                limits: ExtractionLimits::default(),
             }),
             ParameterCombination::To => Ok(Self {
                 use_pit: true,
@@ -259,8 +877,10 @@ impl ExtractionParameters {
                     datetime: to.clone(),
                     size: *lines,
                 }),
-                sort_order: json!([{"@timestamp": {"order": "asc"}}, {"_shard_doc": {"order": "asc"}}]),
+                sort_order: json!([{time_field(): {"order": "asc"}}, {"_shard_doc": {"order": "asc"}}]),
                 sleep_between_batches: false,
+                latency: None,
+                limits: ExtractionLimits::default(),
             }),
             ParameterCombination::From => Ok(Self {
                 use_pit: false,
@@ -268,8 +888,10 @@ impl ExtractionParameters {
                 query_match: gen_query_match(&validation.where_filters),
                 search_after: None,
                 seek_origin: None,
-                sort_order: json!([{"@timestamp": {"order": "asc"}}]),
+                sort_order: json!([{time_field(): {"order": "asc"}}]),
                 sleep_between_batches: false,
+                latency: None,
+                limits: ExtractionLimits::default(),
             }),
             ParameterCombination::FromTo => Ok(Self {
                 use_pit: true,
@@ -277,8 +899,10 @@ impl ExtractionParameters {
                 query_match: gen_query_match(&validation.where_filters),
                 search_after: None,
                 seek_origin: None,
-                sort_order: json!([{"@timestamp": {"order": "asc"}}, {"_shard_doc": {"order": "asc"}}]),
+                sort_order: json!([{time_field(): {"order": "asc"}}, {"_shard_doc": {"order": "asc"}}]),
                 sleep_between_batches: false,
+                latency: None,
+                limits: ExtractionLimits::default(),
             }),
             ParameterCombination::Follow => Ok(Self {
                 use_pit: false,
@@ -289,8 +913,10 @@ impl ExtractionParameters {
                     datetime: None,
                     size: *lines,
                 }),
-                sort_order: json!([{"@timestamp": {"order": "asc"}}]),
+                sort_order: json!([{time_field(): {"order": "asc"}}]),
                 sleep_between_batches: true,
+                latency: Some(AdaptiveLatency::default()),
+                limits: ExtractionLimits::default(),
             }),
             ParameterCombination::None => Ok(Self {
                 use_pit: false,
@@ -301,8 +927,10 @@ impl ExtractionParameters {
                     datetime: None,
                     size: *lines,
                 }),
-                sort_order: json!([{"@timestamp": {"order": "asc"}}]),
+                sort_order: json!([{time_field(): {"order": "asc"}}]),
                 sleep_between_batches: false,
+                latency: None,
+                limits: ExtractionLimits::default(),
             }),
         }
     }
@@ -320,12 +948,18 @@ impl ExtractionParameters {
         }
     }
 
-    fn update_search_after(&mut self, val: Option<&Value>) {
-        self.search_after = val.cloned();
+    // Page descending through the PIT, each batch is printed as it arrives
+    fn reverse(&mut self) {
+        self.sort_order =
+            json!([{time_field(): {"order": "desc"}}, {"_shard_doc": {"order": "desc"}}]);
+    }
+
+    fn update_search_after(&mut self, sort: Option<&Vec<Value>>) {
+        self.search_after = sort.map(|sort| Value::from(sort.clone()));
     }
 }
 
-fn seek_origin(es: &ElasticsearchClient, params: &ExtractionParameters) -> Option<Value> {
+fn seek_origin(es: &impl SearchTransport, params: &ExtractionParameters) -> Option<Vec<Value>> {
     let seek_params = params.seek_origin.as_ref()?;
 
     let mut query_builder = SearchQueryBuilder::new()
@@ -337,14 +971,14 @@ fn seek_origin(es: &ElasticsearchClient, params: &ExtractionParameters) -> Optio
     // Set sort order based on whether we use PIT or not
     if params.use_pit {
         query_builder = query_builder.with_sort_order(
-            json!([{"@timestamp": {"order": "desc"}}, {"_shard_doc": {"order": "asc"}}]),
+            json!([{time_field(): {"order": "desc"}}, {"_shard_doc": {"order": "asc"}}]),
         );
     } else {
-        query_builder = query_builder.with_sort_order(json!([{"@timestamp": {"order": "desc"}}]));
+        query_builder = query_builder.with_sort_order(json!([{time_field(): {"order": "desc"}}]));
     }
 
     if let Some(dt) = &seek_params.datetime {
-        if let Ok(parsed_date) = parse(dt) {
+        if let Ok(parsed_date) = parse_datetime(dt) {
             let parsed_date = parsed_date.to_rfc3339();
             query_builder = query_builder
                 .with_time_range(None, Some(&parsed_date), LATENCY)
@@ -358,15 +992,12 @@ fn seek_origin(es: &ElasticsearchClient, params: &ExtractionParameters) -> Optio
 
     let search_query = query_builder.build();
 
-    es.search(&search_query).ok().and_then(|response| {
-        response["hits"]["hits"]
-            .as_array()
-            .and_then(|hits| hits.last())
-            .map(|last_hit| last_hit["sort"].clone())
-    })
+    es.search(&search_query)
+        .ok()
+        .and_then(|response| response.hits.hits.last()?.sort.clone())
 }
 
-fn gen_query_match(filters: &Option<Vec<WhereFilter>>) -> Option<Value> {
+pub fn gen_query_match(filters: &Option<Vec<WhereFilter>>) -> Option<Value> {
     filters.as_ref().map(|filters| match filters.len() {
         0 => json!({"match_all": {}}),
         1 => json!({
@@ -397,99 +1028,1394 @@ fn gen_query_match(filters: &Option<Vec<WhereFilter>>) -> Option<Value> {
     })
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn handle_cat_command(
-    config: Option<Config>,
-    index: &str,
-    from: &Option<String>,
-    to: &Option<String>,
-    select_clause: &Option<String>,
-    where_clause: &Option<String>,
-    follow: bool,
-    around: &Option<String>,
-    lines: &u32,
-) -> Result<(), ESQError> {
-    let config = config
-        .ok_or_else(|| {
-            ESQError::ConfigError("No configuration found. Please login first.".to_string())
-        })?
-        .clone();
+fn describe_range(from: &Option<String>, to: &Option<String>) -> String {
+    match (from, to) {
+        (Some(from), Some(to)) => format!("between {} and {}", from, to),
+        (Some(from), None) => format!("since {}", from),
+        (None, Some(to)) => format!("until {}", to),
+        (None, None) => "at all".to_string(),
+    }
+}
 
-    let validation =
-        validate_parameters(around, from, to, lines, follow, select_clause, where_clause)?;
+// Smallest number of clauses for which nothing matches, given that all of them match nothing
+// and none of them match something. Adding clauses only narrows the results, so the count
+// is monotonic and a binary search finds the clause that zeroes it out.
+fn first_zeroing_clause(
+    clauses: usize,
+    mut count: impl FnMut(usize) -> Result<u64, ESQError>,
+) -> Result<usize, ESQError> {
+    let (mut matching, mut empty) = (0, clauses);
+    while empty - matching > 1 {
+        let middle = (matching + empty) / 2;
+        if count(middle)? == 0 {
+            empty = middle;
+        } else {
+            matching = middle;
+        }
+    }
+    Ok(empty)
+}
 
-    let mut es = ElasticsearchClient::new(config)?;
-    es.set_index(index);
+fn with_enricher_fields(
+    mut fields: Vec<String>,
+    enrichers: &[Box<dyn Enricher>],
+    enricher_fields: impl Fn(&dyn Enricher) -> Vec<String>,
+) -> Vec<String> {
+    for field in enrichers
+        .iter()
+        .flat_map(|enricher| enricher_fields(enricher.as_ref()))
+    {
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    fields
+}
 
-    let mut params = ExtractionParameters::from_mode(&validation, lines, around, to)?;
+// A clause the explanation adds back one at a time, after the query string
+enum Candidate<'a> {
+    Where(&'a WhereFilter),
+    // The clause of a --where-file or of --dsl, named after its option
+    Filter(String, &'a Value),
+}
 
-    if params.use_pit {
-        es.create_pit()?;
+impl Candidate<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Candidate::Where(filter) => format!("'{}:{}'", filter.field, filter.value),
+            Candidate::Filter(option, _) => option.clone(),
+        }
+    }
+}
+
+// Index of the candidate after which nothing matches and the count without it, None when all
+// of them together still match something
+fn zeroing_candidate(
+    candidates: &[Candidate],
+    in_range: u64,
+    mut count: impl FnMut(&[Candidate]) -> Result<u64, ESQError>,
+) -> Result<Option<(usize, u64)>, ESQError> {
+    if candidates.is_empty() || count(candidates)? > 0 {
+        return Ok(None);
     }
+    let zeroing = first_zeroing_clause(candidates.len(), |n| count(&candidates[..n]))?;
+    let matching = if zeroing > 1 {
+        count(&candidates[..zeroing - 1])?
+    } else {
+        in_range
+    };
+    Ok(Some((zeroing - 1, matching)))
+}
+
+fn describe_zeroing(
+    index: &str,
+    matching: u64,
+    range: &str,
+    candidates: &[Candidate],
+    zeroing: usize,
+) -> String {
+    let kept: Vec<String> = candidates[..zeroing]
+        .iter()
+        .map(Candidate::describe)
+        .collect();
+    let kept = if kept.is_empty() {
+        String::new()
+    } else {
+        format!(" matching {}", kept.join(" and "))
+    };
+    format!(
+        "index '{}' has {} documents{}{}, none once {} is added",
+        index,
+        matching,
+        range,
+        kept,
+        candidates[zeroing].describe()
+    )
+}
 
-    if params.seek_origin.is_some() {
-        params.update_search_after(seek_origin(&es, &params).as_ref());
+// Count without the filters first, then add them back to find the one that excludes everything;
+// the alias filters belong to the target and are part of every count
+fn explain_no_match(
+    es: &ElasticsearchClient,
+    from: &Option<String>,
+    to: &Option<String>,
+    query_string: Option<&str>,
+    candidates: &[Candidate],
+    alias_clause: Option<&Value>,
+) -> Result<String, ESQError> {
+    let index = es.index().unwrap_or_default();
+    let Ok(total) = es.count(None) else {
+        return Ok(format!(
+            "index '{}' does not exist or cannot be read, see esq ls",
+            index
+        ));
+    };
+    if total == 0 {
+        return Ok(format!("index '{}' holds no documents", index));
     }
 
-    let query_builder = SearchQueryBuilder::new()
-        .with_sort_order(params.sort_order.clone())
-        .with_pit(params.use_pit)
-        .with_query_match(params.query_match.clone())
-        .with_source_fields(validation.select_fields.clone())
-        .with_time_range(from.as_deref(), to.as_deref(), LATENCY)?;
+    let count_matching = |query_string, candidates: &[Candidate]| -> Result<u64, ESQError> {
+        let where_filters: Vec<WhereFilter> = candidates
+            .iter()
+            .filter_map(|candidate| match candidate {
+                Candidate::Where(filter) => Some((*filter).clone()),
+                Candidate::Filter(..) => None,
+            })
+            .collect();
+        let clauses = candidates.iter().filter_map(|candidate| match candidate {
+            Candidate::Filter(_, clause) => Some(*clause),
+            Candidate::Where(_) => None,
+        });
+        let mut builder = SearchQueryBuilder::new()
+            .with_query_match(gen_query_match(&Some(where_filters)))
+            .with_query_string(query_string)
+            .with_time_range(from.as_deref(), to.as_deref(), LATENCY)?;
+        for clause in alias_clause.into_iter().chain(clauses) {
+            builder = builder.with_query(|query| query.filter(clause.clone()));
+        }
+        es.count(builder.build().get("query"))
+    };
+    let in_range = count_matching(None, &[])?;
+    if in_range == 0 {
+        return Ok(format!(
+            "index '{}' holds {} documents, none {}",
+            index,
+            total,
+            describe_range(from, to)
+        ));
+    }
+    let range = if from.is_some() || to.is_some() {
+        format!(" {}", describe_range(from, to))
+    } else {
+        String::new()
+    };
+    // The query string goes first, the other clauses are then added to it one by one
+    if let Some(lucene) = query_string
+        && count_matching(query_string, &[])? == 0
+    {
+        return Ok(format!(
+            "index '{}' has {} documents{}, none matching the query '{}'",
+            index, in_range, range, lucene
+        ));
+    }
+    let count = |candidates: &[Candidate]| count_matching(query_string, candidates);
+    let Some((zeroing, matching)) = zeroing_candidate(candidates, in_range, count)? else {
+        return Ok(format!(
+            "{} documents match the query, the extraction mode selected none of them",
+            in_range
+        ));
+    };
 
-    let mut remaining_docs = params.total_docs;
+    let mut explanation = describe_zeroing(index, matching, &range, candidates, zeroing);
+    let Candidate::Where(culprit) = &candidates[zeroing] else {
+        return Ok(explanation);
+    };
+    let field_caps = es.field_caps(std::slice::from_ref(&culprit.field))?;
+    if field_caps["fields"].get(&culprit.field).is_none() {
+        explanation.push_str(&format!(
+            " (no index of the target maps '{}')",
+            culprit.field
+        ));
+    }
+    Ok(explanation)
+}
 
-    // Fetch results in batches
-    loop {
-        let current_size = if !params.sleep_between_batches {
-            cmp::min(remaining_docs, BATCH_SIZE)
-        } else {
-            BATCH_SIZE
-        };
+fn document_timestamp(es: &ElasticsearchClient, id: &str) -> Result<String, ESQError> {
+    let response = es.search(&json!({
+        "size": 1,
+        "_source": [time_field()],
+        "sort": [{time_field(): {"order": "asc"}}],
+        "query": {"ids": {"values": [id]}}
+    }))?;
+
+    let hit = response
+        .hits
+        .hits
+        .first()
+        .ok_or_else(|| ESQError::ESError(format!("Document '{}' not found", id)))?;
+
+    hit_timestamp(hit)
+        .map(|timestamp| timestamp.to_rfc3339())
+        .ok_or_else(|| ESQError::ESError(format!("Document '{}' has no {}", id, time_field())))
+}
 
-        let mut current_builder = query_builder.clone().with_size(current_size);
+// Selected fields can be read from doc values when every mapping of every field
+// is aggregatable and of a numeric, date or keyword-like type
+fn docvalue_eligible(field_caps: &Value, fields: &[String]) -> bool {
+    let Some(caps) = field_caps["fields"].as_object() else {
+        return false;
+    };
+    let all_known = fields
+        .iter()
+        .all(|field| field.contains('*') || caps.contains_key(field));
+
+    all_known
+        && !caps.is_empty()
+        && caps.values().all(|types| {
+            types.as_object().is_some_and(|types| {
+                types.iter().all(|(field_type, cap)| {
+                    DOCVALUE_TYPES.contains(&field_type.as_str())
+                        && cap["aggregatable"].as_bool().unwrap_or(false)
+                })
+            })
+        })
+}
 
-        if let Some(ref last_sort) = params.search_after {
-            current_builder = current_builder.with_search_after(last_sort.clone());
+// Run the user's notification command, exposing the error through ESQ_ERROR
+pub fn run_error_hook(command: &str, error: &ESQError) {
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("ESQ_ERROR", error.to_string())
+        .status();
+
+    match status {
+        Ok(status) if !status.success() => {
+            eprintln!("Error hook exited with {}", status);
         }
+        Err(e) => eprintln!("Failed to run error hook: {}", e),
+        _ => {}
+    }
+}
 
-        let search_query = current_builder.build();
-        let response = es.search(&search_query)?;
-        let hits = response["hits"]["hits"].as_array().unwrap();
+// Names of the target that are neither patterns, date math nor remote indices
+fn concrete_names(pattern: &str) -> Vec<&str> {
+    pattern
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.contains(['*', '<', ':']) && !part.starts_with('-'))
+        .collect()
+}
 
-        if hits.is_empty() && !params.sleep_between_batches {
-            break;
+// A mistyped name only fails later with a bare 404, fail first with the closest index names
+fn check_concrete_indices(es: &ElasticsearchClient, pattern: &str) -> Result<(), ESQError> {
+    let names = concrete_names(pattern);
+    // Existence is best effort, a role may not be allowed to check it
+    if names.is_empty() || es.exists(&names.join(",")).unwrap_or(true) {
+        return Ok(());
+    }
+    let indices: Vec<String> = es
+        .index_names()
+        .map(|names| names.filter_map(Result::ok).collect())
+        .unwrap_or_default();
+    for name in names {
+        if es.exists(name).unwrap_or(true) {
+            continue;
         }
-
-        for hit in hits {
-            println!("{}", hit["_source"]);
+        let mut message = format!("Index '{}' does not exist", name);
+        let candidates = suggestions(name, &indices);
+        if !candidates.is_empty() {
+            message.push_str(&format!(", did you mean '{}'?", candidates.join("', '")));
         }
+        return Err(ESQError::ESError(message));
+    }
+    Ok(())
+}
 
-        if let Some(last_hit) = hits.last() {
-            params.update_search_after(last_hit.get("sort"));
-        }
+// Build the search target from the indices left after time-range pruning,
+// falling back to the original pattern when the list would make the URL too long
+fn pruned_target(pattern: &str, indices: &[String]) -> Option<String> {
+    if indices.is_empty() {
+        return None;
+    }
+    let target = indices.join(",");
+    if target.len() > MAX_PRUNED_TARGET_LEN {
+        Some(pattern.to_string())
+    } else {
+        Some(target)
+    }
+}
 
-        if params.should_stop(hits.len(), &mut remaining_docs) {
-            break;
+// Target indices whose searches run against slow storage
+#[derive(Default, Debug)]
+struct SlowIndices {
+    frozen_tier: Vec<String>,
+    cold_tier: Vec<String>,
+    throttled: Vec<String>,
+}
+
+impl SlowIndices {
+    fn from_settings(settings: &Value) -> Self {
+        let mut slow = Self::default();
+        let Some(indices) = settings.as_object() else {
+            return slow;
+        };
+        for (index, data) in indices {
+            let settings = &data["settings"];
+            if settings["index.search.throttled"].as_str() == Some("true") {
+                slow.throttled.push(index.clone());
+            }
+            // The first preferred tier is where the index lives
+            let tier = settings["index.routing.allocation.include._tier_preference"]
+                .as_str()
+                .and_then(|tiers| tiers.split(',').next());
+            match tier {
+                Some("data_frozen") => slow.frozen_tier.push(index.clone()),
+                Some("data_cold") => slow.cold_tier.push(index.clone()),
+                _ => {}
+            }
         }
+        slow.frozen_tier.sort();
+        slow.cold_tier.sort();
+        slow.throttled.sort();
+        slow
+    }
+
+    fn frozen(&self) -> Vec<&String> {
+        self.frozen_tier.iter().chain(&self.throttled).collect()
+    }
 
-        if params.sleep_between_batches {
-            thread::sleep(Duration::from_secs(1));
+    fn warning(&self) -> Option<String> {
+        let frozen = self.frozen();
+        let mut parts = Vec::new();
+        if !frozen.is_empty() {
+            parts.push(format!("{} frozen", frozen.len()));
+        }
+        if !self.cold_tier.is_empty() {
+            parts.push(format!("{} cold", self.cold_tier.len()));
         }
+        let example = frozen.first().copied().or(self.cold_tier.first())?;
+        let mut warning = format!(
+            "target includes {} indices (e.g. {}), searches may be slow",
+            parts.join(" and "),
+            example
+        );
+        if !frozen.is_empty() {
+            warning.push_str(", use --ignore-frozen to skip frozen data");
+        }
+        Some(warning)
     }
+}
 
-    Ok(())
+// Filters of the filtered aliases in the target, per index. Time-range pruning and --latest
+// resolve aliases to their indices, so the filters are added to the query explicitly.
+#[derive(Default, Debug)]
+struct AliasFilters {
+    indices: BTreeMap<String, Vec<(String, Value)>>,
+}
+
+impl AliasFilters {
+    fn from_aliases(aliases: &Value, target: &str) -> Self {
+        let mut filters = Self::default();
+        let Some(indices) = aliases.as_object() else {
+            return filters;
+        };
+        // An index the target names itself is searched whole, as Elasticsearch does
+        let named: Vec<&str> = target
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.starts_with('-'))
+            .collect();
+        for (index, data) in indices {
+            let Some(aliases) = data["aliases"].as_object() else {
+                continue;
+            };
+            if named.iter().any(|pattern| field_matches(pattern, index)) {
+                continue;
+            }
+            // Reached through an alias without filter, the whole index is visible
+            if aliases.values().any(|alias| alias.get("filter").is_none()) {
+                continue;
+            }
+            let mut index_filters: Vec<(String, Value)> = aliases
+                .iter()
+                .map(|(alias, data)| (alias.clone(), data["filter"].clone()))
+                .collect();
+            index_filters.sort_by(|a, b| a.0.cmp(&b.0));
+            if !index_filters.is_empty() {
+                filters.indices.insert(index.clone(), index_filters);
+            }
+        }
+        filters
+    }
+
+    // Each filtered index only matches the filters of its aliases, other indices match as is
+    fn clause(&self) -> Option<Value> {
+        if self.indices.is_empty() {
+            return None;
+        }
+        let mut should: Vec<Value> = self
+            .indices
+            .iter()
+            .map(|(index, filters)| {
+                json!({"bool": {
+                    "filter": [{"term": {"_index": index}}],
+                    "should": filters.iter().map(|(_, filter)| filter).collect::<Vec<_>>(),
+                    "minimum_should_match": 1
+                }})
+            })
+            .collect();
+        let filtered: Vec<&String> = self.indices.keys().collect();
+        should.push(json!({"bool": {"must_not": [{"terms": {"_index": filtered}}]}}));
+        Some(json!({"bool": {"should": should, "minimum_should_match": 1}}))
+    }
+
+    fn describe(&self) -> Vec<String> {
+        self.indices
+            .iter()
+            .flat_map(|(index, filters)| {
+                filters.iter().map(move |(alias, filter)| {
+                    format!("Alias {} filters {}: {}", alias, index, filter)
+                })
+            })
+            .collect()
+    }
+}
+
+impl CatArgs {
+    pub fn query_spec(&self) -> Result<QuerySpec, ESQError> {
+        Ok(QuerySpec {
+            index: require_index(&self.index)?.to_string(),
+            window: TimeWindow {
+                from: self.from.clone(),
+                to: self.to.clone(),
+                around: self.around.clone(),
+                around_ids: self.around_id.clone(),
+                lines: self.lines,
+                follow: self.follow,
+                latest: self.latest,
+            },
+            filters: QueryFilters {
+                where_clause: self.where_clause.clone(),
+                where_files: self.where_file.clone(),
+                query: self.query.clone(),
+                dsl: self.dsl.clone(),
+            },
+            projection: Projection {
+                // --raw needs nothing else of the documents
+                select_clause: self
+                    .select_clause
+                    .clone()
+                    .or_else(|| self.output.raw.clone()),
+                exclude_clause: self.exclude_clause.clone(),
+                fields_api: self.fields_api,
+                doc_values: self.doc_values,
+            },
+        })
+    }
+
+    pub fn extraction_options(&self) -> ExtractionOptions {
+        ExtractionOptions {
+            output: self.output.clone(),
+            max_events: self.max_events.into_iter().chain(self.max_count).min(),
+            until: self.until.clone(),
+            reverse: self.reverse,
+            parallel_windows: self.parallel_windows,
+            ignore_frozen: self.ignore_frozen,
+            allow_partial_results: self.allow_partial_results,
+            async_search: self.async_search,
+            terminate_after: self.terminate_after,
+            cache: self.cache,
+            cache_dir: self.cache_dir.clone(),
+            spool_dir: self.spool_dir.clone(),
+            record_session: self.record_session.clone(),
+            heartbeat: self.heartbeat,
+            stats_panel: self.stats_panel,
+            explain_empty: self.explain_empty,
+        }
+    }
+
+    pub fn query_target(&mut self) -> QueryTarget<'_> {
+        QueryTarget {
+            index: &mut self.index,
+            select: Some(&mut self.select_clause),
+            where_clause: &mut self.where_clause,
+            from: &mut self.from,
+            to: &mut self.to,
+        }
+    }
+}
+
+// Run cat with its command-line arguments, firing --on-error-exec on failure
+pub fn run_cat(
+    es: ElasticsearchClient,
+    args: &CatArgs,
+    sink: Option<Box<dyn Sink>>,
+) -> Result<(), ESQError> {
+    let sink = match sink {
+        Some(sink) => Some(sink),
+        None => args.sink.build()?,
+    };
+    let enrichers = args.enrich.build(es.config())?;
+    let snapshot = match args.snapshot_every {
+        Some(every) => Some(Snapshotter::create(
+            args.snapshot_dir.as_deref().unwrap_or(Path::new(".")),
+            every,
+        )?),
+        None => None,
+    };
+    handle_cat_command(
+        es,
+        &args.query_spec()?,
+        &args.extraction_options(),
+        enrichers,
+        sink,
+        snapshot,
+    )
+    .inspect_err(|e| {
+        if let Some(command) = &args.on_error_exec
+            && !matches!(e, ESQError::OutputClosed)
+        {
+            run_error_hook(command, e);
+        }
+    })
+}
+
+// Read what the spec describes, the other arguments deciding how: limits, search options
+// and output
+pub fn handle_cat_command(
+    mut es: ElasticsearchClient,
+    spec: &QuerySpec,
+    options: &ExtractionOptions,
+    mut enrichers: Vec<Box<dyn Enricher>>,
+    sink: Option<Box<dyn Sink>>,
+    snapshot: Option<Snapshotter>,
+) -> Result<(), ESQError> {
+    let index = spec.index.as_str();
+    let TimeWindow {
+        from,
+        to,
+        around_ids,
+        lines,
+        follow,
+        latest,
+        ..
+    } = &spec.window;
+    let (follow, latest, fields_api) = (*follow, *latest, spec.projection.fields_api);
+    let output = &options.output;
+    let (reverse, parallel_windows, ignore_frozen) = (
+        options.reverse,
+        options.parallel_windows,
+        options.ignore_frozen,
+    );
+
+    let mut validation = spec.validate()?;
+    // Document IDs stand in for their timestamps until they are resolved below
+    let mut around: Vec<String> = spec
+        .window
+        .around
+        .iter()
+        .chain(around_ids)
+        .cloned()
+        .collect();
+    if reverse && validation.mode != ParameterCombination::FromTo {
+        return Err(ESQError::ValidationError(
+            "The parameter --reverse needs a full time range (--from and --to).".to_string(),
+        ));
+    }
+    if options.cache.is_some() && validation.mode != ParameterCombination::FromTo {
+        return Err(ESQError::ValidationError(
+            "The parameter --cache needs a full time range (--from and --to).".to_string(),
+        ));
+    }
+    if parallel_windows.is_some() {
+        if validation.mode != ParameterCombination::FromTo {
+            return Err(ESQError::ValidationError(
+                "The parameter --parallel-windows needs a full time range (--from and --to)."
+                    .to_string(),
+            ));
+        }
+        if options.record_session.is_some() {
+            return Err(ESQError::ValidationError(
+                "--record-session cannot record the parallel queries of --parallel-windows"
+                    .to_string(),
+            ));
+        }
+        if options.spool_dir.is_none() {
+            return Err(ESQError::ConfigError(
+                "--no-config writes no file, --parallel-windows cannot spool its windows"
+                    .to_string(),
+            ));
+        }
+    }
+
+    validate_index_pattern(index)?;
+    let labeled_clauses = spec.labeled_filter_clauses()?;
+    let filter_clauses: Vec<Value> = labeled_clauses
+        .iter()
+        .map(|(_, clause)| clause.clone())
+        .collect();
+    check_concrete_indices(&es, index)?;
+
+    // Stop cleanly on Ctrl-C so the PIT is released and summaries get printed
+    install_interrupt_handler()?;
+
+    // Enrichers read their key fields, selected or not, the columns show what they add instead
+    let columns = validation
+        .select_fields
+        .clone()
+        .map(|columns| with_enricher_fields(columns, &enrichers, |e| e.output_fields()));
+    validation.select_fields = validation
+        .select_fields
+        .take()
+        .map(|fields| with_enricher_fields(fields, &enrichers, |e| e.source_fields()));
+
+    let recorder = match &options.record_session {
+        Some(path) => Some(Rc::new(SessionRecorder::create(path, es.config())?)),
+        None => None,
+    };
+
+    let mut printer = Printer::new(
+        output,
+        es.config().default.kibana_url.clone(),
+        recorder.clone(),
+    )?;
+    printer.set_abbreviations(&es.config().abbreviations);
+    if let Some(columns) = &columns {
+        printer.set_fields(columns);
+    }
+    if let Some(sink) = sink {
+        printer.set_sink(sink);
+    }
+    if let Some(snapshot) = snapshot {
+        printer.set_snapshot(snapshot);
+    }
+    if let Some(every) = options.heartbeat {
+        printer.set_heartbeat(every);
+    }
+    if let Some(recorder) = recorder {
+        es.set_recorder(recorder);
+    }
+
+    let max_events = options.max_events;
+    // A fresh cache stands in for the cluster
+    let cache = match options.cache {
+        Some(ttl) => Some(query_cache(
+            &es,
+            spec,
+            options,
+            &filter_clauses,
+            max_events,
+            ttl,
+        )?),
+        None => None,
+    };
+    if let Some(cache) = &cache
+        && let Some(batches) = cache.fresh(BATCH_SIZE as usize)?
+    {
+        if output.verbose {
+            eprintln!("Printing the documents kept by --cache");
+        }
+        let mut limits = ExtractionLimits::new(&options.until, max_events)?;
+        print_cached(batches, &mut limits, &mut printer, &mut enrichers)?;
+        return printer.finish();
+    }
+
+    es.set_allow_partial_results(options.allow_partial_results);
+    es.set_async_search(options.async_search);
+    // Like the other metadata, aliases may not be readable with every role
+    let alias_filters = es
+        .aliases(index)
+        .map(|aliases| AliasFilters::from_aliases(&aliases, index))
+        .unwrap_or_default();
+    if output.verbose {
+        for line in alias_filters.describe() {
+            eprintln!("{}", line);
+        }
+    }
+    if latest {
+        let latest_index = es.latest_index(index)?;
+        es.set_index(&latest_index);
+    } else if index.contains('*') && (from.is_some() || to.is_some()) && !follow {
+        // Not while following: the indices created after the start would never be read
+        let range = time_range(from.as_deref(), to.as_deref(), LATENCY)?;
+        let indices = es.indices_in_range(index, &range)?;
+        match pruned_target(index, &indices) {
+            Some(target) => es.set_index(&target),
+            // No index holds documents in the requested time range
+            None => {
+                if options.explain_empty {
+                    eprintln!(
+                        "Nothing matched: no index matching '{}' holds documents {}",
+                        index,
+                        describe_range(from, to)
+                    );
+                }
+                return Ok(());
+            }
+        }
+    } else {
+        es.set_index(index);
+    }
+
+    // Settings may not be readable with every role, the warning is best effort
+    let slow = es
+        .tier_settings()
+        .map(|settings| SlowIndices::from_settings(&settings))
+        .unwrap_or_default();
+    if !slow.throttled.is_empty() {
+        es.set_ignore_throttled(Some(ignore_frozen));
+    }
+    if ignore_frozen {
+        if output.verbose && !slow.frozen().is_empty() {
+            eprintln!("Skipping {} frozen indices", slow.frozen().len());
+        }
+    } else if let Some(warning) = slow.warning() {
+        eprintln!("Warning: {}", warning);
+    }
+
+    // Stats may not be readable with every role either, filters make the rate meaningless
+    let filtered = validation.where_filters.is_some()
+        || spec.filters.query.is_some()
+        || !filter_clauses.is_empty()
+        || alias_filters.clause().is_some();
+    if follow
+        && !filtered
+        && let Some(warning) = indexing_rate(&es).and_then(|rate| firehose_warning(index, rate))
+    {
+        eprintln!("Warning: {}", warning);
+    }
+
+    if !around_ids.is_empty() {
+        around.truncate(around.len() - around_ids.len());
+        for id in around_ids {
+            around.push(document_timestamp(&es, id)?);
+        }
+    }
+
+    // Doc values hold the selected fields whole, exclusions only apply to _source
+    let excluded_fields = spec.projection.excluded_fields();
+    let docvalue_fields = match &validation.select_fields {
+        Some(fields) if spec.projection.doc_values && excluded_fields.is_empty() => {
+            let field_caps = es.field_caps(fields)?;
+            docvalue_eligible(&field_caps, fields).then(|| fields.clone())
+        }
+        _ => None,
+    };
+    if docvalue_fields.is_some() && output.verbose {
+        eprintln!("Selected fields are read from doc values");
+    }
+
+    let mut params = ExtractionParameters::from_mode(&validation, lines, around.first(), to)?;
+    if reverse {
+        params.reverse();
+    }
+    params.limits = ExtractionLimits::new(&options.until, max_events)?;
+
+    // Each window opens its own PIT
+    if params.use_pit && parallel_windows.is_none() {
+        es.create_pit()?;
+    }
+
+    let query_builder = SearchQueryBuilder::new()
+        .with_sort_order(params.sort_order.clone())
+        .with_pit(params.use_pit)
+        .with_query_match(params.query_match.clone())
+        .with_source_fields(validation.select_fields.clone())
+        .with_source_excludes(excluded_fields)
+        .with_fields_api(fields_api)
+        .with_docvalue_fields(docvalue_fields)
+        .with_terminate_after(options.terminate_after);
+    let query_builder = if params.latency.is_some() {
+        query_builder
+    } else {
+        query_builder.with_time_range(from.as_deref(), to.as_deref(), LATENCY)?
+    };
+    let query_builder = if ignore_frozen {
+        query_builder
+            .with_query(|query| query.must_not(json!({"terms": {"_tier": ["data_frozen"]}})))
+    } else {
+        query_builder
+    };
+    let query_builder = match alias_filters.clause() {
+        Some(clause) => query_builder.with_query(|query| query.filter(clause)),
+        None => query_builder,
+    };
+    let mut query_builder = query_builder.with_query_string(spec.filters.query.as_deref());
+    for clause in filter_clauses {
+        query_builder = query_builder.with_query(|query| query.filter(clause));
+    }
+
+    let mut follow_stats = None;
+    let mut partial = PartialResults::default();
+
+    if let (Some(windows), Some(from), Some(to), Some(spool_dir)) =
+        (parallel_windows, from, to, &options.spool_dir)
+    {
+        print_windows(
+            &es,
+            &query_builder,
+            from,
+            to,
+            windows,
+            spool_dir,
+            reverse,
+            &mut params.limits,
+            &mut printer,
+            &mut enrichers,
+        )?;
+    } else {
+        let mut extractor = Extractor::new(
+            &mut es,
+            &query_builder,
+            &mut printer,
+            &mut enrichers,
+            params.sleep_between_batches,
+        );
+        extractor.stats_panel = options.stats_panel.then(StatsPanel::new);
+        extractor.cache = cache.as_ref().map(QueryCache::writer).transpose()?;
+        // Counted first, so the bar knows how far the extraction has to go. The bar is a
+        // nicety, a count that fails leaves it out rather than the documents
+        let bounded = validation.mode == ParameterCombination::FromTo
+            || (params.total_docs >= PROGRESS_MIN_LINES && !params.sleep_between_batches);
+        if bounded
+            && around.len() <= 1
+            && ProgressBar::wanted(output)
+            && let Ok(matching) = extractor
+                .transport
+                .count(query_builder.clone().build().get("query"))
+        {
+            extractor.progress = Some(ProgressBar::new(matching.min(params.total_docs as u64)));
+            extractor.printer.hide_file_progress();
+        }
+        if around.len() > 1 {
+            // One context block per requested instant
+            for datetime in &around {
+                if interrupted() || params.limits.exhausted() {
+                    break;
+                }
+                if let Some(seek_params) = &mut params.seek_origin {
+                    seek_params.datetime = Some(datetime.clone());
+                }
+                params.update_search_after(seek_origin(&*extractor.transport, &params).as_ref());
+                extractor
+                    .printer
+                    .start_block(&format!("around {}", datetime))?;
+                extractor.run(&mut params)?;
+            }
+        } else {
+            if params.seek_origin.is_some() {
+                params.update_search_after(seek_origin(&*extractor.transport, &params).as_ref());
+            }
+            extractor.run(&mut params)?;
+        }
+        // Incomplete results are not worth replaying, nor the ones --until cut short
+        if let Some(cache) = extractor.cache.take()
+            && !interrupted()
+            && !params.limits.expired()
+            && extractor.partial.summary().is_empty()
+        {
+            cache.commit()?;
+        }
+        follow_stats = extractor.follow_stats;
+        partial = extractor.partial;
+    }
+
+    printer.finish()?;
+
+    if options.explain_empty && printer.batch() == 0 && !interrupted() {
+        let where_filters = validation.where_filters.as_deref().unwrap_or_default();
+        let candidates: Vec<Candidate> = where_filters
+            .iter()
+            .map(Candidate::Where)
+            .chain(
+                labeled_clauses
+                    .iter()
+                    .map(|(option, clause)| Candidate::Filter(option.clone(), clause)),
+            )
+            .collect();
+        let explanation = explain_no_match(
+            &es,
+            from,
+            to,
+            spec.filters.query.as_deref(),
+            &candidates,
+            alias_filters.clause().as_ref(),
+        )?;
+        eprintln!("Nothing matched: {}", explanation);
+    }
+
+    for line in partial.summary() {
+        eprintln!("Warning: {}", line);
+    }
+
+    if let Some(stats) = &follow_stats {
+        for line in stats.summary() {
+            eprintln!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+// Batches that came back incomplete, reported once the extraction is over
+#[derive(Default)]
+struct PartialResults {
+    terminated_early: bool,
+    timed_out: usize,
+    shard_failures: usize,
+    reason: Option<String>,
+}
+
+impl PartialResults {
+    fn observe(&mut self, response: &SearchResponse) {
+        self.terminated_early |= response.terminated_early;
+        if response.timed_out {
+            self.timed_out += 1;
+        }
+        if response.shards.failed > 0 {
+            self.shard_failures += 1;
+            self.reason.get_or_insert_with(|| response.shards.reason());
+        }
+    }
+
+    fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.terminated_early {
+            lines.push("results were truncated by --terminate-after".to_string());
+        }
+        if self.timed_out > 0 {
+            lines.push(format!(
+                "{} batch(es) timed out, results may be incomplete",
+                self.timed_out
+            ));
+        }
+        if let Some(reason) = &self.reason {
+            lines.push(format!(
+                "{} batch(es) had shard failures, results may be incomplete ({})",
+                self.shard_failures, reason
+            ));
+        }
+        lines
+    }
+}
+
+// Enrich and print one batch of hits, whichever way it was fetched
+fn print_batch(
+    hits: &mut [Hit],
+    printer: &mut Printer,
+    follow_stats: &mut Option<FollowStats>,
+    enrichers: &mut [Box<dyn Enricher>],
+) -> Result<(), ESQError> {
+    // Hits fetched with the fields API carry no _source
+    for hit in hits.iter_mut() {
+        if hit.source.is_none() && hit.fields.is_some() {
+            *hit = fields_as_source(hit);
+        }
+    }
+    for enricher in enrichers.iter_mut() {
+        enricher.enrich(hits)?;
+    }
+
+    for hit in hits.iter() {
+        printer.print_hit(hit)?;
+        if let Some(stats) = follow_stats {
+            stats.observe(hit.source());
+        }
+    }
+
+    printer.flush_sink()
+}
+
+// Print the windows of a --parallel-windows extraction in order, each one as soon as it is complete
+#[allow(clippy::too_many_arguments)]
+fn print_windows(
+    es: &ElasticsearchClient,
+    query_builder: &SearchQueryBuilder,
+    from: &str,
+    to: &str,
+    windows: u32,
+    spool_dir: &Path,
+    reverse: bool,
+    limits: &mut ExtractionLimits,
+    printer: &mut Printer,
+    enrichers: &mut [Box<dyn Enricher>],
+) -> Result<(), ESQError> {
+    let mut ranges = split_range(parse_datetime(from)?, parse_datetime(to)?, windows);
+    if reverse {
+        ranges.reverse();
+    }
+    let extraction = spawn_windows(
+        &es.settings(),
+        query_builder,
+        &ranges,
+        BATCH_SIZE,
+        spool_dir,
+    )?;
+    for (i, spool) in extraction.enumerate() {
+        let spool = spool?;
+        if interrupted() || limits.exhausted() {
+            break;
+        }
+        for batch in spool.batches(BATCH_SIZE as usize)? {
+            let mut hits = batch?;
+            limits.take(&mut hits);
+            if hits.is_empty() {
+                break;
+            }
+            printer.start_batch();
+            if printer.verbose() {
+                eprintln!(
+                    "batch {}: {} hits from window {}/{}",
+                    printer.batch(),
+                    hits.len(),
+                    i + 1,
+                    ranges.len()
+                );
+            }
+            print_batch(&mut hits, printer, &mut None, enrichers)?;
+        }
+    }
+    Ok(())
+}
+
+// What decides the documents of a bounded extraction, the window as given, so that a relative
+// one such as --from 1h keeps reading the same documents until the cache expires
+fn query_cache(
+    es: &ElasticsearchClient,
+    spec: &QuerySpec,
+    options: &ExtractionOptions,
+    filter_clauses: &[Value],
+    max_events: Option<u64>,
+    ttl: Duration,
+) -> Result<QueryCache, ESQError> {
+    let dir = options.cache_dir.as_deref().ok_or_else(|| {
+        ESQError::ConfigError(
+            "--no-config keeps nothing on disk, --cache cannot be used".to_string(),
+        )
+    })?;
+    purge_expired(dir);
+    // Users of one cluster may not see the same documents
+    let key = json!({
+        "cluster": ClusterFingerprint::from_config(es.config()),
+        "username": es.config().default.username,
+        "index": spec.index,
+        // The field of the range and the sort, from .esq.toml
+        "time_field": time_field(),
+        "from": spec.window.from,
+        "to": spec.window.to,
+        "latest": spec.window.latest,
+        "where": spec.filters.where_clause,
+        "query": spec.filters.query,
+        "filters": filter_clauses,
+        "select": spec.projection.select_clause,
+        "exclude": spec.projection.exclude_clause,
+        "fields_api": spec.projection.fields_api,
+        "doc_values": spec.projection.doc_values,
+        "reverse": options.reverse,
+        "max_events": max_events,
+        "terminate_after": options.terminate_after,
+        "ignore_frozen": options.ignore_frozen,
+    });
+    Ok(QueryCache::new(dir, &key, ttl))
+}
+
+// The cached documents, printed in the batches they were fetched in
+fn print_cached(
+    batches: SpoolBatches,
+    limits: &mut ExtractionLimits,
+    printer: &mut Printer,
+    enrichers: &mut [Box<dyn Enricher>],
+) -> Result<(), ESQError> {
+    for batch in batches {
+        let mut hits = batch?;
+        limits.take(&mut hits);
+        if interrupted() || hits.is_empty() {
+            break;
+        }
+        printer.start_batch();
+        print_batch(&mut hits, printer, &mut None, enrichers)?;
+    }
+    Ok(())
+}
+
+// The fetch and print loop of cat, over any transport so it can be tested without a cluster
+struct Extractor<'a, T: SearchTransport> {
+    transport: &'a mut T,
+    query_builder: &'a SearchQueryBuilder,
+    printer: &'a mut Printer,
+    enrichers: &'a mut [Box<dyn Enricher>],
+    follow_stats: Option<FollowStats>,
+    stats_panel: Option<StatsPanel>,
+    progress: Option<ProgressBar>,
+    cache: Option<CacheWriter>,
+    partial: PartialResults,
+    // Waits between retries and followed batches, tests skip them
+    sleep: fn(Duration),
+}
+
+impl<'a, T: SearchTransport> Extractor<'a, T> {
+    fn new(
+        transport: &'a mut T,
+        query_builder: &'a SearchQueryBuilder,
+        printer: &'a mut Printer,
+        enrichers: &'a mut [Box<dyn Enricher>],
+        follow: bool,
+    ) -> Self {
+        Self {
+            transport,
+            query_builder,
+            printer,
+            enrichers,
+            follow_stats: follow.then(FollowStats::new),
+            stats_panel: None,
+            progress: None,
+            cache: None,
+            partial: PartialResults::default(),
+            sleep: thread::sleep,
+        }
+    }
+
+    // Drawn again under whatever was just printed, the next batch erases it first
+    fn draw_panel(&mut self) {
+        if let (Some(panel), Some(stats)) = (&mut self.stats_panel, &self.follow_stats) {
+            panel.draw(stats);
+        }
+    }
+
+    // Fetch results in batches from the current search_after position until the mode's stop condition
+    fn run(&mut self, params: &mut ExtractionParameters) -> Result<(), ESQError> {
+        let mut remaining_docs = params.total_docs;
+        let mut failures = 0;
+        let mut throttled = 0;
+        let mut batch_size = BatchSizer::new(BATCH_SIZE, MIN_BATCH_SIZE);
+
+        while !interrupted() && !params.limits.expired() && !params.limits.exhausted() {
+            // Whatever the batch prints goes where the panel was
+            if let Some(panel) = &mut self.stats_panel {
+                panel.clear();
+            }
+            let current_size = if !params.sleep_between_batches {
+                cmp::min(remaining_docs, batch_size.current())
+            } else {
+                batch_size.current()
+            };
+
+            let mut current_builder = self.query_builder.clone().with_size(current_size);
+
+            if let Some(latency) = &params.latency {
+                current_builder =
+                    current_builder.with_time_range(None, None, &latency.date_math())?;
+            }
+
+            if let Some(ref last_sort) = params.search_after {
+                current_builder = current_builder.with_search_after(last_sort.clone());
+            }
+
+            let search_query = current_builder.build();
+            let mut response = match self.transport.search(&search_query) {
+                Ok(response) => {
+                    failures = 0;
+                    throttled = 0;
+                    batch_size.grow();
+                    response
+                }
+                // Back off with smaller batches while the cluster sheds load
+                Err(ESQError::TooManyRequests(retry_after)) if throttled < THROTTLE_MAX_RETRIES => {
+                    throttled += 1;
+                    batch_size.shrink();
+                    let delay = retry_after
+                        .unwrap_or_else(|| Duration::from_secs(1 << cmp::min(throttled, 5)));
+                    eprintln!(
+                        "Elasticsearch is overloaded (429). Retrying in {}s with batches of {}...",
+                        delay.as_secs(),
+                        batch_size.current()
+                    );
+                    self.draw_panel();
+                    (self.sleep)(delay);
+                    continue;
+                }
+                // Retry the same cursor with a smaller batch until the response fits in memory
+                Err(ESQError::CircuitBreaking(reason)) if batch_size.can_shrink() => {
+                    batch_size.shrink();
+                    eprintln!(
+                        "Circuit breaker tripped ({}). Retrying with batches of {}...",
+                        reason,
+                        batch_size.current()
+                    );
+                    continue;
+                }
+                // Ride out transient connection errors while following
+                Err(ESQError::NetworkError(msg))
+                    if params.sleep_between_batches && failures < FOLLOW_MAX_RETRIES =>
+                {
+                    failures += 1;
+                    let delay = 1 << failures;
+                    eprintln!("Connection error: {}. Retrying in {}s...", msg, delay);
+                    self.draw_panel();
+                    (self.sleep)(Duration::from_secs(delay));
+                    continue;
+                }
+                Err(ESQError::Interrupted) => break,
+                Err(e) => return Err(e),
+            };
+            if let Some(pit_id) = &response.pit_id {
+                self.transport.refresh_pit(pit_id);
+            }
+            self.partial.observe(&response);
+            let hits = &mut response.hits.hits;
+            if let Some(latency) = &mut params.latency {
+                let previous = latency.current();
+                for delay in hits.iter().filter_map(ingest_delay) {
+                    latency.observe(delay);
+                }
+                if self.printer.verbose() && latency.current() != previous {
+                    eprintln!("Follow latency now {}", latency.date_math());
+                }
+            }
+
+            if hits.is_empty() {
+                if !params.sleep_between_batches {
+                    break;
+                }
+                self.printer.flush_repeats()?;
+                self.printer.heartbeat()?;
+            } else {
+                self.printer.start_batch();
+                if self.printer.verbose() {
+                    eprintln!(
+                        "batch {}: {} hits in {}ms",
+                        self.printer.batch(),
+                        hits.len(),
+                        response.took
+                    );
+                }
+            }
+
+            params.limits.take(hits);
+            // Kept before enrichment, a replay enriches the documents again
+            if let Some(cache) = &mut self.cache {
+                cache.write(hits)?;
+            }
+            print_batch(hits, self.printer, &mut self.follow_stats, self.enrichers)?;
+            if let Some(progress) = &mut self.progress {
+                progress.advance(hits.len() as u64);
+            }
+
+            if let Some(last_hit) = hits.last() {
+                params.update_search_after(last_hit.sort.as_ref());
+            }
+
+            // Later batches would be cut at the same per-shard limit, leaving holes in the output
+            if params.should_stop(hits.len(), &mut remaining_docs)
+                || params.limits.exhausted()
+                || (response.terminated_early && !params.sleep_between_batches)
+            {
+                break;
+            }
+
+            if params.sleep_between_batches {
+                self.draw_panel();
+                (self.sleep)(Duration::from_secs(1));
+            }
+        }
+        if let Some(panel) = &mut self.stats_panel {
+            panel.clear();
+        }
+        if let Some(progress) = &self.progress {
+            progress.finish();
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use clap::Parser;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::io::Read;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        cat: CatArgs,
+    }
+
+    fn cat_args(args: &[&str]) -> CatArgs {
+        TestCli::try_parse_from(std::iter::once("esq").chain(args.iter().copied()))
+            .unwrap()
+            .cat
+    }
+
+    #[test]
+    fn test_query_spec_from_cat_args() {
+        let spec = cat_args(&[
+            "logs-*",
+            "-F",
+            "1h",
+            "-T",
+            "5m",
+            "-w",
+            "level:ERROR",
+            "-s",
+            "message",
+        ])
+        .query_spec()
+        .unwrap();
+        assert_eq!(spec.index, "logs-*");
+        assert_eq!(spec.window.from.as_deref(), Some("1h"));
+        assert_eq!(spec.window.lines, DEFAULT_NUMBER_OF_LINES);
+        assert_eq!(spec.projection.select_clause.as_deref(), Some("message"));
+        assert!(spec.projection.excluded_fields().is_empty());
+        let validation = spec.validate().unwrap();
+        assert_eq!(validation.mode, ParameterCombination::FromTo);
+        assert_eq!(validation.select_fields, Some(vec!["message".to_string()]));
+
+        let spec = cat_args(&["logs", "-q", "status:>=500", "-w", "level:ERROR"])
+            .query_spec()
+            .unwrap();
+        assert_eq!(spec.filters.query.as_deref(), Some("status:>=500"));
+        assert_eq!(spec.filters.where_clause.as_deref(), Some("level:ERROR"));
+
+        let spec = cat_args(&["logs", "--exclude", "stack_trace, http.request.body"])
+            .query_spec()
+            .unwrap();
+        assert_eq!(
+            spec.projection.excluded_fields(),
+            vec!["stack_trace", "http.request.body"]
+        );
+
+        // Patterns go to _source.includes as given
+        let spec = cat_args(&["logs", "-s", "kubernetes.*, message"])
+            .query_spec()
+            .unwrap();
+        let fields = spec.validate().unwrap().select_fields;
+        assert_eq!(
+            fields,
+            Some(vec!["kubernetes.*".to_string(), "message".to_string()])
+        );
+        let query = SearchQueryBuilder::new().with_source_fields(fields).build();
+        assert_eq!(query["_source"], json!(["kubernetes.*", "message"]));
+
+        let spec = cat_args(&["logs", "--around-id", "x1", "-f"])
+            .query_spec()
+            .unwrap();
+        assert_eq!(spec.window.around_ids, vec!["x1"]);
+        assert!(spec.validate().is_err());
+        assert!(cat_args(&[]).query_spec().is_err());
+    }
+
+    #[test]
+    fn test_count_query_filters() {
+        let spec = QuerySpec {
+            index: "logs".to_string(),
+            filters: QueryFilters {
+                where_clause: Some("level:ERROR".to_string()),
+                query: Some("service:checkout".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let query = spec.count_query().unwrap().build();
+        let query = query["query"].to_string();
+        // Documents of the last minute count too
+        assert!(query.contains(r#""lt":"now""#), "{}", query);
+        assert!(!query.contains("now-1m"), "{}", query);
+        assert!(
+            query.contains(r#"{"query_string":{"query":"service:checkout"}}"#),
+            "{}",
+            query
+        );
+        assert!(query.contains("ERROR"), "{}", query);
+    }
 
     #[test]
     fn test_validate_around_with_from() {
         let result = validate_parameters(
-            &Some("2024-01-01".to_string()),
+            &["2024-01-01".to_string()],
             &Some("2024-01-01".to_string()),
             &None,
             &10,
@@ -503,7 +2429,7 @@ mod tests {
     #[test]
     fn test_validate_around_with_follow() {
         let result = validate_parameters(
-            &Some("2024-01-01".to_string()),
+            &["2024-01-01".to_string()],
             &None,
             &None,
             &10,
@@ -517,7 +2443,7 @@ mod tests {
     #[test]
     fn test_validate_to_with_follow() {
         let result = validate_parameters(
-            &None,
+            &[],
             &None,
             &Some("2024-01-01".to_string()),
             &10,
@@ -531,7 +2457,7 @@ mod tests {
     #[test]
     fn test_validate_valid_params() {
         let result = validate_parameters(
-            &None,
+            &[],
             &Some("2024-01-01".to_string()),
             &None,
             &10,
@@ -546,7 +2472,7 @@ mod tests {
     #[test]
     fn test_validate_select_clause() {
         let result = validate_parameters(
-            &None,
+            &[],
             &None,
             &None,
             &10,
@@ -568,22 +2494,15 @@ mod tests {
 
     #[test]
     fn test_validate_empty_select_clause() {
-        let result = validate_parameters(
-            &None,
-            &None,
-            &None,
-            &10,
-            false,
-            &Some("".to_string()),
-            &None,
-        );
+        let result =
+            validate_parameters(&[], &None, &None, &10, false, &Some("".to_string()), &None);
         assert!(result.is_err());
     }
 
     #[test]
     fn test_validate_where_clause() {
         let result = validate_parameters(
-            &None,
+            &[],
             &None,
             &None,
             &10,
@@ -604,7 +2523,7 @@ mod tests {
     #[test]
     fn test_validate_invalid_where_clause() {
         let result = validate_parameters(
-            &None,
+            &[],
             &None,
             &None,
             &10,
@@ -617,22 +2536,15 @@ mod tests {
 
     #[test]
     fn test_validate_empty_where_clause() {
-        let result = validate_parameters(
-            &None,
-            &None,
-            &None,
-            &10,
-            false,
-            &None,
-            &Some("".to_string()),
-        );
+        let result =
+            validate_parameters(&[], &None, &None, &10, false, &None, &Some("".to_string()));
         assert!(result.is_err());
     }
 
     #[test]
     fn test_validate_combined_select_and_where() {
         let result = validate_parameters(
-            &None,
+            &[],
             &None,
             &None,
             &10,
@@ -655,7 +2567,7 @@ mod tests {
     #[test]
     fn test_validate_around_only() {
         let result = validate_parameters(
-            &Some("2024-01-01".to_string()),
+            &["2024-01-01".to_string()],
             &None,
             &None,
             &10,
@@ -668,10 +2580,28 @@ mod tests {
     }
 
     #[test]
-    fn test_validate_to_only() {
+    fn test_validate_multiple_around() {
         let result = validate_parameters(
+            &[
+                "2024-01-01T10:00:00Z".to_string(),
+                "2024-01-01T12:00:00Z".to_string(),
+            ],
+            &None,
+            &None,
+            &10,
+            false,
             &None,
             &None,
+        );
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().mode, ParameterCombination::Around);
+    }
+
+    #[test]
+    fn test_validate_to_only() {
+        let result = validate_parameters(
+            &[],
+            &None,
             &Some("2024-01-01".to_string()),
             &10,
             false,
@@ -685,7 +2615,7 @@ mod tests {
     #[test]
     fn test_validate_from_only() {
         let result = validate_parameters(
-            &None,
+            &[],
             &Some("2024-01-01".to_string()),
             &None,
             &10,
@@ -700,7 +2630,7 @@ mod tests {
     #[test]
     fn test_validate_from_to() {
         let result = validate_parameters(
-            &None,
+            &[],
             &Some("2024-01-01".to_string()),
             &Some("2024-01-02".to_string()),
             &10,
@@ -711,10 +2641,36 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap().mode, ParameterCombination::FromTo);
     }
+    #[test]
+    fn test_reverse_sort_order() {
+        let validation = validate_parameters(
+            &[],
+            &Some("2024-01-01".to_string()),
+            &Some("2024-01-02".to_string()),
+            &DEFAULT_NUMBER_OF_LINES,
+            false,
+            &None,
+            &None,
+        )
+        .unwrap();
+        let mut params =
+            ExtractionParameters::from_mode(&validation, &DEFAULT_NUMBER_OF_LINES, None, &None)
+                .unwrap();
+        params.reverse();
+        let query = SearchQueryBuilder::new()
+            .with_sort_order(params.sort_order.clone())
+            .with_pit(params.use_pit)
+            .build();
+        assert_eq!(
+            query["sort"],
+            json!([{"@timestamp": {"order": "desc"}}, {"_shard_doc": {"order": "desc"}}])
+        );
+    }
+
     #[test]
     fn test_validate_from_to_invalid_n() {
         let result = validate_parameters(
-            &None,
+            &[],
             &Some("2024-01-01".to_string()),
             &Some("2024-01-02".to_string()),
             &20,
@@ -728,7 +2684,7 @@ mod tests {
     #[test]
     fn test_validate_around_invalid_n() {
         let result = validate_parameters(
-            &Some("2024-01-01".to_string()),
+            &["2024-01-01".to_string()],
             &None,
             &None,
             &10000,
@@ -742,7 +2698,7 @@ mod tests {
     #[test]
     fn test_validate_to_invalid_n() {
         let result = validate_parameters(
-            &None,
+            &[],
             &None,
             &Some("2024-01-01".to_string()),
             &10000,
@@ -756,7 +2712,7 @@ mod tests {
     #[test]
     fn test_validate_from_n() {
         let result = validate_parameters(
-            &None,
+            &[],
             &Some("2024-01-01".to_string()),
             &None,
             &20000,
@@ -770,7 +2726,7 @@ mod tests {
 
     #[test]
     fn test_validate_none() {
-        let result = validate_parameters(&None, &None, &None, &20, false, &None, &None);
+        let result = validate_parameters(&[], &None, &None, &20, false, &None, &None);
         assert!(result.is_ok());
         assert_eq!(result.unwrap().mode, ParameterCombination::None);
     }
@@ -838,4 +2794,665 @@ mod tests {
             }))
         );
     }
+
+    #[test]
+    fn test_pruned_target_no_indices() {
+        assert_eq!(pruned_target("logs-*", &[]), None);
+    }
+
+    #[test]
+    fn test_pruned_target_joins_indices() {
+        let indices = vec!["logs-2024.06.01".to_string(), "logs-2024.06.02".to_string()];
+        assert_eq!(
+            pruned_target("logs-*", &indices),
+            Some("logs-2024.06.01,logs-2024.06.02".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pruned_target_too_long() {
+        let indices: Vec<String> = (0..500).map(|i| format!("logs-{:04}", i)).collect();
+        assert_eq!(
+            pruned_target("logs-*", &indices),
+            Some("logs-*".to_string())
+        );
+    }
+
+    #[test]
+    fn test_docvalue_eligible() {
+        let field_caps = json!({
+            "fields": {
+                "status": {"long": {"type": "long", "aggregatable": true}},
+                "@timestamp": {"date": {"type": "date", "aggregatable": true}}
+            }
+        });
+        let fields = vec!["status".to_string(), "@timestamp".to_string()];
+        assert!(docvalue_eligible(&field_caps, &fields));
+    }
+
+    #[test]
+    fn test_docvalue_not_eligible_text() {
+        let field_caps = json!({
+            "fields": {
+                "status": {"long": {"type": "long", "aggregatable": true}},
+                "message": {"text": {"type": "text", "aggregatable": false}}
+            }
+        });
+        let fields = vec!["status".to_string(), "message".to_string()];
+        assert!(!docvalue_eligible(&field_caps, &fields));
+    }
+
+    #[test]
+    fn test_docvalue_not_eligible_unknown_field() {
+        let field_caps = json!({
+            "fields": {
+                "status": {"long": {"type": "long", "aggregatable": true}}
+            }
+        });
+        let fields = vec!["status".to_string(), "missing".to_string()];
+        assert!(!docvalue_eligible(&field_caps, &fields));
+    }
+
+    #[test]
+    fn test_batch_sizer_shrinks_to_min() {
+        let mut sizer = BatchSizer::new(1000, 50);
+        sizer.shrink();
+        assert_eq!(sizer.current(), 500);
+        for _ in 0..10 {
+            sizer.shrink();
+        }
+        assert_eq!(sizer.current(), 50);
+        assert!(!sizer.can_shrink());
+    }
+
+    #[test]
+    fn test_batch_sizer_grows_back_gradually() {
+        let mut sizer = BatchSizer::new(1000, 50);
+        sizer.shrink();
+        sizer.shrink();
+        assert_eq!(sizer.current(), 250);
+        sizer.grow();
+        assert_eq!(sizer.current(), 375);
+        for _ in 0..10 {
+            sizer.grow();
+        }
+        assert_eq!(sizer.current(), 1000);
+    }
+
+    #[test]
+    fn test_firehose_warning() {
+        assert_eq!(firehose_warning("logs", 20.0), None);
+        let warning = firehose_warning("logs", 12000.4).unwrap();
+        assert!(warning.starts_with("'logs' receives about 12000 documents/s"));
+    }
+
+    #[test]
+    fn test_adaptive_latency() {
+        let mut latency = AdaptiveLatency::default();
+        latency.observe(Duration::from_millis(1200));
+        assert_eq!(latency.date_math(), "60s");
+        for _ in 0..MIN_LATENCY_SAMPLES {
+            latency.observe(Duration::from_millis(300));
+        }
+        assert_eq!(latency.date_math(), "3s");
+        // Faster ingest later on does not bring it under the worst delay
+        for _ in 0..MIN_LATENCY_SAMPLES {
+            latency.observe(Duration::from_millis(100));
+        }
+        assert_eq!(latency.date_math(), "3s");
+        latency.observe(Duration::from_secs(300));
+        assert_eq!(latency.current(), MAX_FOLLOW_LATENCY);
+
+        let mut fast = AdaptiveLatency::default();
+        for _ in 0..MIN_LATENCY_SAMPLES {
+            fast.observe(Duration::from_millis(100));
+        }
+        assert_eq!(fast.current(), MIN_FOLLOW_LATENCY);
+    }
+
+    #[test]
+    fn test_ingest_delay() {
+        let hit = |source: Value| Hit {
+            source: Some(source),
+            ..Default::default()
+        };
+        assert_eq!(
+            ingest_delay(&hit(json!({
+                "@timestamp": "2024-06-01T12:00:00Z",
+                "event": {"ingested": "2024-06-01T12:00:04.500Z"}
+            }))),
+            Some(Duration::from_millis(4500))
+        );
+        assert_eq!(
+            ingest_delay(&hit(json!({
+                "@timestamp": "2024-06-01T12:00:05Z",
+                "event.ingested": "2024-06-01T12:00:00Z"
+            }))),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            ingest_delay(&hit(json!({"@timestamp": "2024-06-01T12:00:00Z"}))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extraction_limits() {
+        let mut limits = ExtractionLimits::new(&None, Some(3)).unwrap();
+        let mut hits: Vec<Hit> = (0..2)
+            .map(|i| serde_json::from_value(json!({"_id": i.to_string()})).unwrap())
+            .collect();
+        limits.take(&mut hits.clone());
+        assert!(!limits.exhausted());
+        limits.take(&mut hits);
+        assert_eq!(hits.len(), 1);
+        assert!(limits.exhausted());
+        assert!(!limits.expired());
+
+        assert!(ExtractionLimits::new(&Some("2020-01-01T00:00:00Z".to_string()), None).is_err());
+        let later = ExtractionLimits::new(&Some("2999-01-01T00:00:00Z".to_string()), None).unwrap();
+        assert!(!later.expired());
+    }
+
+    #[test]
+    fn test_partial_results_summary() {
+        let mut partial = PartialResults::default();
+        assert!(partial.summary().is_empty());
+
+        let response: SearchResponse = serde_json::from_value(json!({
+            "terminated_early": true,
+            "_shards": {
+                "total": 2,
+                "successful": 1,
+                "failed": 1,
+                "failures": [{"reason": {"type": "node_disconnected_exception"}}]
+            },
+            "hits": {"hits": []}
+        }))
+        .unwrap();
+        partial.observe(&response);
+        partial.observe(&response);
+
+        assert_eq!(
+            partial.summary(),
+            vec![
+                "results were truncated by --terminate-after".to_string(),
+                "2 batch(es) had shard failures, results may be incomplete (node_disconnected_exception)"
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slow_indices_from_settings() {
+        let settings = json!({
+            "logs-hot": {"settings": {
+                "index.routing.allocation.include._tier_preference": "data_hot"
+            }},
+            "partial-logs-old": {"settings": {
+                "index.routing.allocation.include._tier_preference": "data_frozen"
+            }},
+            "restored-logs": {"settings": {
+                "index.routing.allocation.include._tier_preference": "data_cold,data_warm,data_hot"
+            }},
+            "legacy-frozen": {"settings": {"index.search.throttled": "true"}}
+        });
+        let slow = SlowIndices::from_settings(&settings);
+        assert_eq!(slow.frozen_tier, vec!["partial-logs-old".to_string()]);
+        assert_eq!(slow.cold_tier, vec!["restored-logs".to_string()]);
+        assert_eq!(slow.throttled, vec!["legacy-frozen".to_string()]);
+        assert_eq!(
+            slow.warning().unwrap(),
+            "target includes 2 frozen and 1 cold indices (e.g. partial-logs-old), searches may be slow, use --ignore-frozen to skip frozen data"
+        );
+    }
+
+    #[test]
+    fn test_slow_indices_none() {
+        let settings = json!({"logs": {"settings": {}}});
+        assert!(SlowIndices::from_settings(&settings).warning().is_none());
+    }
+
+    #[test]
+    fn test_parse_where_file() {
+        assert_eq!(
+            parse_where_file("user.id@ids.txt"),
+            Ok(WhereFile {
+                field: "user.id".to_string(),
+                path: PathBuf::from("ids.txt"),
+            })
+        );
+        assert!(parse_where_file("ids.txt").is_err());
+    }
+
+    #[test]
+    fn test_dsl_query() {
+        let clause = json!({"bool": {"should": [{"match": {"level": "ERROR"}}]}});
+        assert_eq!(dsl_query(clause.clone()), Ok(clause.clone()));
+        assert_eq!(dsl_query(json!({"query": clause.clone()})), Ok(clause));
+        assert_eq!(
+            dsl_query(json!({"query": {"match_all": {}}, "sort": ["_doc"]})),
+            Err(
+                "'sort' is set by the extraction mode and --reverse, leave it out of the DSL"
+                    .to_string()
+            )
+        );
+        assert!(dsl_query(json!({"query": {"match_all": {}}, "aggs": {}})).is_err());
+        assert!(dsl_query(json!({"match": {}, "term": {}})).is_err());
+        assert!(dsl_query(json!({"query": {"search_after": [1]}})).is_err());
+        assert!(dsl_query(json!(["match_all"])).is_err());
+    }
+
+    #[test]
+    fn test_terms_clause_chunks() {
+        assert_eq!(
+            terms_clause("user.id", &["a", "b"]),
+            json!({"terms": {"user.id": ["a", "b"]}})
+        );
+        let values: Vec<String> = (0..MAX_TERMS_PER_CLAUSE + 1)
+            .map(|i| i.to_string())
+            .collect();
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        let clause = terms_clause("user.id", &values);
+        let chunks = clause["bool"]["should"].as_array().unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks[1]["terms"]["user.id"],
+            json!([MAX_TERMS_PER_CLAUSE.to_string()])
+        );
+    }
+
+    #[test]
+    fn test_concrete_names() {
+        assert_eq!(
+            concrete_names("app-logs, logs-*,-logs-debug-*,other:logs,<logs-{now/d}>"),
+            vec!["app-logs"]
+        );
+    }
+
+    #[test]
+    fn test_first_zeroing_clause() {
+        // Counts with the first n of five clauses, the third one excludes everything
+        let counts = [120, 80, 80, 0, 0, 0];
+        let mut calls = 0;
+        let zeroing = first_zeroing_clause(5, |n| {
+            calls += 1;
+            Ok(counts[n])
+        })
+        .unwrap();
+        assert_eq!(zeroing, 3);
+        assert!(calls <= 3);
+        assert_eq!(first_zeroing_clause(1, |_| Ok(0)).unwrap(), 1);
+    }
+
+    struct InventoryLookup;
+
+    impl Enricher for InventoryLookup {
+        fn enrich(&mut self, _hits: &mut [Hit]) -> Result<(), ESQError> {
+            Ok(())
+        }
+
+        fn source_fields(&self) -> Vec<String> {
+            vec!["host.name".to_string()]
+        }
+
+        fn output_fields(&self) -> Vec<String> {
+            vec!["owner".to_string(), "rack".to_string()]
+        }
+    }
+
+    #[test]
+    fn test_with_enricher_fields() {
+        let enrichers: Vec<Box<dyn Enricher>> = vec![Box::new(InventoryLookup)];
+        let selected = vec!["message".to_string(), "rack".to_string()];
+        // The key is fetched but only the fields the lookup adds become columns
+        assert_eq!(
+            with_enricher_fields(selected.clone(), &enrichers, |e| e.source_fields()),
+            ["message", "rack", "host.name"]
+        );
+        assert_eq!(
+            with_enricher_fields(selected, &enrichers, |e| e.output_fields()),
+            ["message", "rack", "owner"]
+        );
+    }
+
+    #[test]
+    fn test_zeroing_filter_clause() {
+        let level = WhereFilter {
+            field: "level".to_string(),
+            value: "ERROR".to_string(),
+        };
+        let dsl = json!({"term": {"service.name": "gone"}});
+        let ids = json!({"terms": {"user.id": ["u1"]}});
+        // Only the --dsl clause excludes everything, the where filter alone matches 40
+        let candidates = [
+            Candidate::Where(&level),
+            Candidate::Filter("--where-file user.id@ids.txt".to_string(), &ids),
+            Candidate::Filter("--dsl".to_string(), &dsl),
+        ];
+        let count = |candidates: &[Candidate]| {
+            let with_dsl = candidates.iter().any(
+                |candidate| matches!(candidate, Candidate::Filter(option, _) if option == "--dsl"),
+            );
+            Ok(if with_dsl { 0 } else { 40 })
+        };
+        let (zeroing, matching) = zeroing_candidate(&candidates, 100, count).unwrap().unwrap();
+        assert_eq!((zeroing, matching), (2, 40));
+        assert_eq!(
+            describe_zeroing("logs", matching, " since 1h", &candidates, zeroing),
+            "index 'logs' has 40 documents since 1h matching 'level:ERROR' and \
+             --where-file user.id@ids.txt, none once --dsl is added"
+        );
+
+        // A where file alone
+        let candidates = [Candidate::Filter(
+            "--where-file user.id@ids.txt".to_string(),
+            &ids,
+        )];
+        let (zeroing, matching) = zeroing_candidate(&candidates, 100, |candidates| {
+            Ok(if candidates.is_empty() { 100 } else { 0 })
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            describe_zeroing("logs", matching, "", &candidates, zeroing),
+            "index 'logs' has 100 documents, none once --where-file user.id@ids.txt is added"
+        );
+        assert!(
+            zeroing_candidate(&candidates, 100, |_| Ok(3))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_describe_range() {
+        assert_eq!(describe_range(&Some("1h".to_string()), &None), "since 1h");
+        assert_eq!(describe_range(&None, &None), "at all");
+    }
+
+    #[test]
+    fn test_alias_filters() {
+        let aliases = json!({
+            "error": "alias [logs-raw] missing",
+            "status": 404,
+            "logs-2024.06": {"aliases": {"team-a": {"filter": {"term": {"team": "a"}}}}},
+            "logs-2024.07": {"aliases": {
+                "team-a": {"filter": {"term": {"team": "a"}}},
+                "all-logs": {}
+            }}
+        });
+        let filters = AliasFilters::from_aliases(&aliases, "team-a");
+        assert_eq!(
+            filters.describe(),
+            vec![r#"Alias team-a filters logs-2024.06: {"term":{"team":"a"}}"#.to_string()]
+        );
+        assert_eq!(
+            filters.clause(),
+            Some(json!({"bool": {"should": [
+                {"bool": {
+                    "filter": [{"term": {"_index": "logs-2024.06"}}],
+                    "should": [{"term": {"team": "a"}}],
+                    "minimum_should_match": 1
+                }},
+                {"bool": {"must_not": [{"terms": {"_index": ["logs-2024.06"]}}]}}
+            ], "minimum_should_match": 1}}))
+        );
+        assert_eq!(
+            AliasFilters::from_aliases(&json!({}), "team-a").clause(),
+            None
+        );
+        // Also matched by the wildcard, the index is searched whole
+        assert_eq!(
+            AliasFilters::from_aliases(&aliases, "team-a,logs-*,-logs-debug").clause(),
+            None
+        );
+    }
+
+    // Replays scripted responses and records what the extraction asked for
+    #[derive(Default)]
+    struct MockTransport {
+        responses: RefCell<VecDeque<Result<SearchResponse, ESQError>>>,
+        queries: RefCell<Vec<Value>>,
+        pit_ids: Vec<String>,
+    }
+
+    impl MockTransport {
+        fn new(responses: Vec<Result<SearchResponse, ESQError>>) -> Self {
+            Self {
+                responses: RefCell::new(responses.into()),
+                ..Default::default()
+            }
+        }
+
+        fn queries(&self) -> Vec<Value> {
+            self.queries.borrow().clone()
+        }
+    }
+
+    impl SearchTransport for MockTransport {
+        fn search(&self, query: &Value) -> Result<SearchResponse, ESQError> {
+            self.queries.borrow_mut().push(query.clone());
+            // Once the script is over the index has nothing more
+            self.responses
+                .borrow_mut()
+                .pop_front()
+                .unwrap_or_else(|| Ok(page(&[], None)))
+        }
+
+        fn refresh_pit(&mut self, pit_id: &str) {
+            self.pit_ids.push(pit_id.to_string());
+        }
+    }
+
+    struct CaptureSink(Rc<RefCell<Vec<String>>>);
+
+    impl Sink for CaptureSink {
+        fn write(&mut self, hit: &Hit, _line: &str) -> Result<(), ESQError> {
+            self.0.borrow_mut().push(hit.id.clone().unwrap_or_default());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), ESQError> {
+            Ok(())
+        }
+    }
+
+    fn page(ids: &[u64], pit_id: Option<&str>) -> SearchResponse {
+        let hits: Vec<Value> = ids
+            .iter()
+            .map(|id| json!({"_id": id.to_string(), "_source": {"n": id}, "sort": [id]}))
+            .collect();
+        serde_json::from_value(json!({"hits": {"hits": hits}, "pit_id": pit_id})).unwrap()
+    }
+
+    fn params(total_docs: u32, follow: bool) -> ExtractionParameters {
+        ExtractionParameters {
+            use_pit: true,
+            total_docs,
+            query_match: None,
+            search_after: None,
+            seek_origin: None,
+            sort_order: json!([]),
+            sleep_between_batches: follow,
+            latency: None,
+            limits: ExtractionLimits::default(),
+        }
+    }
+
+    // Runs one extraction without waiting, and returns the ids of the printed hits
+    fn extract(
+        transport: &mut MockTransport,
+        params: &mut ExtractionParameters,
+    ) -> Result<Vec<String>, ESQError> {
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let mut printer = Printer::new(&cat_args(&["logs"]).output, None, None).unwrap();
+        printer.set_sink(Box::new(CaptureSink(printed.clone())));
+        let query_builder = SearchQueryBuilder::new();
+        let mut enrichers: Vec<Box<dyn Enricher>> = Vec::new();
+        let follow = params.sleep_between_batches;
+        let mut extractor = Extractor::new(
+            transport,
+            &query_builder,
+            &mut printer,
+            &mut enrichers,
+            follow,
+        );
+        extractor.sleep = |_| {};
+        extractor.run(params)?;
+        Ok(printed.take())
+    }
+
+    #[test]
+    fn test_extractor_writes_output_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.ndjson.gz");
+        let mut transport = MockTransport::new(vec![Ok(page(&[1, 2], None))]);
+        let args = cat_args(&["logs", "-o", path.to_str().unwrap()]);
+        let mut printer = Printer::new(&args.output, None, None).unwrap();
+        let query_builder = SearchQueryBuilder::new();
+        let mut enrichers: Vec<Box<dyn Enricher>> = Vec::new();
+        let mut extractor = Extractor::new(
+            &mut transport,
+            &query_builder,
+            &mut printer,
+            &mut enrichers,
+            false,
+        );
+        extractor.printer.start_block("around now").unwrap();
+        extractor.run(&mut params(u32::MAX, false)).unwrap();
+        printer.finish().unwrap();
+        assert!(Printer::new(&args.output, None, None).is_err());
+
+        let mut written = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&path).unwrap())
+            .read_to_string(&mut written)
+            .unwrap();
+        assert_eq!(written, "{\"n\":1}\n{\"n\":2}\n");
+    }
+
+    #[test]
+    fn test_extractor_fills_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = QueryCache::new(
+            dir.path(),
+            &json!({"index": "logs"}),
+            Duration::from_secs(60),
+        );
+        let mut transport = MockTransport::new(vec![Ok(page(&[1, 2], None)), Ok(page(&[3], None))]);
+        let mut printer = Printer::new(&cat_args(&["logs"]).output, None, None).unwrap();
+        let query_builder = SearchQueryBuilder::new();
+        let mut enrichers: Vec<Box<dyn Enricher>> = Vec::new();
+        let mut extractor = Extractor::new(
+            &mut transport,
+            &query_builder,
+            &mut printer,
+            &mut enrichers,
+            false,
+        );
+        extractor.cache = Some(cache.writer().unwrap());
+        extractor.run(&mut params(u32::MAX, false)).unwrap();
+        extractor.cache.take().unwrap().commit().unwrap();
+
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let mut printer = Printer::new(&cat_args(&["logs"]).output, None, None).unwrap();
+        printer.set_sink(Box::new(CaptureSink(printed.clone())));
+        let mut limits = ExtractionLimits::new(&None, Some(2)).unwrap();
+        let batches = cache.fresh(BATCH_SIZE as usize).unwrap().unwrap();
+        print_cached(batches, &mut limits, &mut printer, &mut enrichers).unwrap();
+        assert_eq!(printed.take(), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn test_extractor_pages_with_search_after() {
+        let mut transport = MockTransport::new(vec![
+            Ok(page(&[1, 2], Some("pit-2"))),
+            Ok(page(&[3], Some("pit-3"))),
+        ]);
+        let printed = extract(&mut transport, &mut params(u32::MAX, false)).unwrap();
+        assert_eq!(printed, vec!["1", "2", "3"]);
+
+        let queries = transport.queries();
+        assert_eq!(queries.len(), 3);
+        assert!(queries[0].get("search_after").is_none());
+        assert_eq!(queries[1]["search_after"], json!([2]));
+        assert_eq!(queries[2]["search_after"], json!([3]));
+        // Each search goes out with the PIT id of the response before it
+        assert_eq!(transport.pit_ids, vec!["pit-2", "pit-3"]);
+    }
+
+    #[test]
+    fn test_extractor_stop_conditions() {
+        // The requested number of lines
+        let mut transport = MockTransport::new(vec![Ok(page(&[1, 2, 3], None))]);
+        let printed = extract(&mut transport, &mut params(3, false)).unwrap();
+        assert_eq!(printed, vec!["1", "2", "3"]);
+        assert_eq!(transport.queries().len(), 1);
+        assert_eq!(transport.queries()[0]["size"], json!(3));
+
+        // A batch cut by --terminate-after
+        let mut truncated = page(&[1], None);
+        truncated.terminated_early = true;
+        let mut transport = MockTransport::new(vec![Ok(truncated), Ok(page(&[2], None))]);
+        let printed = extract(&mut transport, &mut params(u32::MAX, false)).unwrap();
+        assert_eq!(printed, vec!["1"]);
+
+        // --max-events, even while following
+        let mut follow = params(u32::MAX, true);
+        follow.limits.events_left = Some(3);
+        let mut transport = MockTransport::new(vec![
+            Ok(page(&[1, 2], None)),
+            Ok(page(&[], None)),
+            Ok(page(&[3, 4], None)),
+            Ok(page(&[5], None)),
+        ]);
+        let printed = extract(&mut transport, &mut follow).unwrap();
+        assert_eq!(printed, vec!["1", "2", "3"]);
+        assert_eq!(transport.queries().len(), 3);
+    }
+
+    #[test]
+    fn test_extractor_retries_with_smaller_batches() {
+        let mut transport = MockTransport::new(vec![
+            Err(ESQError::TooManyRequests(Some(Duration::from_secs(30)))),
+            Err(ESQError::CircuitBreaking("parent".to_string())),
+            Ok(page(&[1], None)),
+        ]);
+        let printed = extract(&mut transport, &mut params(u32::MAX, false)).unwrap();
+        assert_eq!(printed, vec!["1"]);
+        let sizes: Vec<Value> = transport
+            .queries()
+            .iter()
+            .map(|query| query["size"].clone())
+            .collect();
+        assert_eq!(sizes, vec![json!(1000), json!(500), json!(250), json!(375)]);
+    }
+
+    #[test]
+    fn test_extractor_network_errors() {
+        // Only followed extractions ride out connection errors
+        let mut transport = MockTransport::new(vec![
+            Err(ESQError::NetworkError("connection reset".to_string())),
+            Ok(page(&[1], None)),
+        ]);
+        assert!(matches!(
+            extract(&mut transport, &mut params(10, false)),
+            Err(ESQError::NetworkError(_))
+        ));
+
+        let mut follow = params(u32::MAX, true);
+        follow.limits.events_left = Some(1);
+        let mut transport = MockTransport::new(vec![
+            Err(ESQError::NetworkError("connection reset".to_string())),
+            Ok(page(&[1], None)),
+        ]);
+        assert_eq!(extract(&mut transport, &mut follow).unwrap(), vec!["1"]);
+
+        let mut follow = params(u32::MAX, true);
+        let errors = (0..=FOLLOW_MAX_RETRIES)
+            .map(|_| Err(ESQError::NetworkError("connection refused".to_string())))
+            .collect();
+        let mut transport = MockTransport::new(errors);
+        assert!(extract(&mut transport, &mut follow).is_err());
+    }
 }
@@ -0,0 +1,411 @@
+// src/commands/export.rs
+use crate::commands::cat::{CatArgs, run_cat};
+use crate::commands::sizeof::{estimate_transfer, format_bytes};
+use crate::elasticsearch::client::ElasticsearchClient;
+use crate::history::{ExportFingerprint, ExportRecord, find_recent, record_export};
+use crate::project::require_index;
+use crate::schema::SchemaSink;
+use crate::session::ClusterFingerprint;
+use crate::sink::Sink;
+use crate::sink::file::{FileSink, SplitBy};
+use crate::utils::*;
+use chrono::Utc;
+use clap::Args;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+const SCHEMA_FILE: &str = "schema.json";
+// Typical size reduction of gzipped NDJSON logs
+const GZIP_RATIO: u64 = 5;
+// Share of the free space an export may take before a warning
+const DISK_WARNING_RATIO: f64 = 0.9;
+
+pub const EXAMPLES: &str = "\
+Examples:
+  # One gzipped NDJSON file per day in out/
+  esq export my-logs --from 2024-06-01 --to 2024-06-08 --output-dir out --split-by day --gzip
+
+  # Files per source index, with a description of the exported fields
+  esq export 'logs-*' --from 1d --split-by index --schema-report
+
+  # Send the documents elsewhere instead of files
+  esq export my-logs --from 1d --sink webhook:https://collector.example.com/logs";
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub cat: CatArgs,
+
+    /// Directory receiving the NDJSON files and the schema report
+    #[arg(long, value_name = "dir", default_value = ".")]
+    pub output_dir: PathBuf,
+
+    /// Write one file per UTC day, UTC hour or source index
+    #[arg(long, value_name = "bucket")]
+    pub split_by: Option<SplitBy>,
+
+    /// Compress the files with gzip
+    #[arg(long)]
+    pub gzip: bool,
+
+    /// Also write schema.json describing the exported fields, types and null rates
+    #[arg(long)]
+    pub schema_report: bool,
+
+    /// Export even when an identical export completed recently or the disk looks too small
+    #[arg(long)]
+    pub force: bool,
+}
+
+// Only file exports of a bounded window can be reused, anything else is None
+fn export_fingerprint(
+    config: &Config,
+    args: &ExportArgs,
+) -> Result<Option<ExportFingerprint>, ESQError> {
+    let cat = &args.cat;
+    let (Some(from), Some(to)) = (&cat.from, &cat.to) else {
+        return Ok(None);
+    };
+    if cat.sink.sink.is_some() {
+        return Ok(None);
+    }
+    // Every option counts, one that leaves the files unchanged only makes reuse rarer. The
+    // window is resolved to instants, the files of --where-file are read for their values
+    let mut options = cat.clone();
+    options.from = None;
+    options.to = None;
+    let mut digest = Sha256::new();
+    digest.update(format!("{:?}", options));
+    for where_file in &cat.where_file {
+        digest.update(fs::read(where_file.path()).map_err(|e| {
+            ESQError::ValidationError(format!(
+                "Cannot read {}: {}",
+                where_file.path().display(),
+                e
+            ))
+        })?);
+    }
+    digest.update(format!(
+        "{:?} {:?} {:?} {}",
+        args.output_dir
+            .canonicalize()
+            .unwrap_or_else(|_| args.output_dir.clone()),
+        args.split_by,
+        args.gzip,
+        args.schema_report
+    ));
+    Ok(Some(ExportFingerprint {
+        cluster: ClusterFingerprint::from_config(config),
+        index: require_index(&cat.index)?.to_string(),
+        query: json!({
+            "from": parse_datetime(from)?.timestamp_millis(),
+            "to": parse_datetime(to)?.timestamp_millis(),
+            "options": format!("{:x}", digest.finalize()),
+        }),
+    }))
+}
+
+fn check_disk_space(estimate: u64, available: u64) -> Result<(), ESQError> {
+    if estimate > available {
+        return Err(ESQError::ValidationError(format!(
+            "The export is estimated at {} but only {} are free in the output directory, pass --force to export anyway",
+            format_bytes(estimate),
+            format_bytes(available)
+        )));
+    }
+    if estimate as f64 > available as f64 * DISK_WARNING_RATIO {
+        eprintln!(
+            "Warning: the export is estimated at {}, close to the {} free in the output directory",
+            format_bytes(estimate),
+            format_bytes(available)
+        );
+    }
+    Ok(())
+}
+
+// Estimate from the index statistics, like esq sizeof, against the free space of the target.
+// The check is best effort, the export itself reports a missing index or a refused request.
+fn preflight_disk_space(es: &mut ElasticsearchClient, args: &ExportArgs) -> Result<(), ESQError> {
+    let cat = &args.cat;
+    es.set_index(require_index(&cat.index)?);
+    let Ok(mut estimate) = estimate_transfer(es, &cat.query_spec()?) else {
+        return Ok(());
+    };
+    if args.gzip {
+        estimate /= GZIP_RATIO;
+    }
+    fs::create_dir_all(&args.output_dir)?;
+    match fs2::available_space(&args.output_dir) {
+        Ok(available) => check_disk_space(estimate, available),
+        Err(_) => Ok(()),
+    }
+}
+
+// Blocks of the target indices, from their index.blocks.* settings
+#[derive(Default, Debug)]
+struct IndexBlocks {
+    // Name of the block, e.g. read_only_allow_delete, to the indices it is set on
+    blocks: BTreeMap<String, Vec<String>>,
+}
+
+impl IndexBlocks {
+    fn from_settings(settings: &Value) -> Self {
+        let mut blocks = Self::default();
+        let Some(indices) = settings.as_object() else {
+            return blocks;
+        };
+        for (index, data) in indices {
+            let Some(settings) = data["settings"].as_object() else {
+                continue;
+            };
+            for (setting, value) in settings {
+                if let Some(block) = setting.strip_prefix("index.blocks.")
+                    && value.as_str() == Some("true")
+                {
+                    blocks
+                        .blocks
+                        .entry(block.to_string())
+                        .or_default()
+                        .push(index.clone());
+                }
+            }
+        }
+        for indices in blocks.blocks.values_mut() {
+            indices.sort();
+        }
+        blocks
+    }
+
+    // A read block fails every search of the export, better said before it starts
+    fn check(&self) -> Result<(), ESQError> {
+        let Some(indices) = self.blocks.get("read") else {
+            return Ok(());
+        };
+        Err(ESQError::ValidationError(format!(
+            "{} index.blocks.read set, Elasticsearch refuses to search them. Reset it with \
+             PUT {}/_settings {{\"index.blocks.read\": false}} or leave them out of the pattern",
+            describe_indices(indices),
+            indices[0]
+        )))
+    }
+
+    // The read-only blocks leave reading alone, they are told for what they say about the indices;
+    // a write block is routine (ILM sets it on rollover and shrink) and not worth a warning
+    fn warnings(&self) -> Vec<String> {
+        self.blocks
+            .iter()
+            .filter_map(|(block, indices)| {
+                let meaning = match block.as_str() {
+                    "read_only_allow_delete" => {
+                        "Elasticsearch set it when a node went over the flood-stage disk watermark, \
+                         indexing into them fails until disk space is freed"
+                    }
+                    "read_only" => "their documents and settings cannot change",
+                    _ => return None,
+                };
+                Some(format!(
+                    "{} index.blocks.{} set: {}; the export reads them as usual",
+                    describe_indices(indices),
+                    block,
+                    meaning
+                ))
+            })
+            .collect()
+    }
+}
+
+fn describe_indices(indices: &[String]) -> String {
+    match indices {
+        [index] => format!("Index {} has", index),
+        indices => format!("{} indices (e.g. {}) have", indices.len(), indices[0]),
+    }
+}
+
+// Whether to keep the files of the previous export, only when confirmed from a terminal
+fn reuse_previous(record: &ExportRecord) -> Result<bool, ESQError> {
+    eprintln!(
+        "An identical export completed at {} into {}",
+        record.completed_at,
+        record.output_dir.display()
+    );
+    if !io::stdin().is_terminal() {
+        eprintln!("Exporting again, reusing it takes a confirmation from a terminal");
+        return Ok(false);
+    }
+    eprint!("Reuse it instead of exporting again? [Y/n] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(!answer.trim().eq_ignore_ascii_case("n"))
+}
+
+pub fn handle_export_command(
+    mut es: ElasticsearchClient,
+    args: &ExportArgs,
+    history: Option<&Path>,
+) -> Result<(), ESQError> {
+    if args.cat.output.output_file.is_some() {
+        return Err(ESQError::ValidationError(
+            "export writes to --output-dir, --output-file is for cat".to_string(),
+        ));
+    }
+    if args.cat.cache.is_some() {
+        return Err(ESQError::ValidationError(
+            "export reuses identical exports through its history, --cache is for cat".to_string(),
+        ));
+    }
+    // Settings may not be readable with every role, the check is best effort
+    let blocks = es
+        .index_blocks(require_index(&args.cat.index)?)
+        .map(|settings| IndexBlocks::from_settings(&settings))
+        .unwrap_or_default();
+    blocks.check()?;
+    for warning in blocks.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    // Without a history, as with --no-config, every export runs in full
+    let fingerprint = match history {
+        Some(_) => export_fingerprint(es.config(), args)?,
+        None => None,
+    };
+    if let Some(fingerprint) = &fingerprint
+        && let Some(history) = history
+        && !args.force
+        && let Some(record) = find_recent(history, fingerprint, Utc::now())?
+        && record.output_available()
+        && reuse_previous(&record)?
+    {
+        return Ok(());
+    }
+    // Only a full --from/--to window reads every matching document, a sink writes nothing locally
+    if !args.force
+        && args.cat.from.is_some()
+        && args.cat.to.is_some()
+        && args.cat.sink.sink.is_none()
+    {
+        preflight_disk_space(&mut es, args)?;
+    }
+
+    // Files are the default destination of an export, --sink sends documents elsewhere
+    let mut files = None;
+    let mut sink: Box<dyn Sink> = match args.cat.sink.build()? {
+        Some(sink) => sink,
+        None => {
+            let sink = FileSink::create(&args.output_dir, args.split_by, args.gzip)?;
+            files = Some(sink.files());
+            Box::new(sink)
+        }
+    };
+    let schema_file = args.output_dir.join(SCHEMA_FILE);
+    if args.schema_report {
+        sink = Box::new(SchemaSink::new(sink, &schema_file));
+    }
+    run_cat(es, &args.cat, Some(sink))?;
+
+    if let Some(fingerprint) = fingerprint
+        && let Some(history) = history
+        && !interrupted()
+    {
+        let mut files = files
+            .and_then(|files| files.lock().ok().map(|files| files.clone()))
+            .unwrap_or_default();
+        if args.schema_report {
+            files.push(schema_file);
+        }
+        record_export(
+            history,
+            ExportRecord {
+                fingerprint,
+                output_dir: args
+                    .output_dir
+                    .canonicalize()
+                    .unwrap_or_else(|_| args.output_dir.clone()),
+                files: files
+                    .iter()
+                    .map(|file| file.canonicalize().unwrap_or_else(|_| file.clone()))
+                    .collect(),
+                completed_at: Utc::now().to_rfc3339(),
+            },
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        export: ExportArgs,
+    }
+
+    fn fingerprint(args: &[&str]) -> Option<ExportFingerprint> {
+        let cli =
+            TestCli::try_parse_from(std::iter::once("esq").chain(args.iter().copied())).unwrap();
+        export_fingerprint(&Config::default(), &cli.export).unwrap()
+    }
+
+    #[test]
+    fn test_export_fingerprint() {
+        let window = [
+            "logs",
+            "-F",
+            "2024-06-01T00:00:00Z",
+            "-T",
+            "2024-06-02T00:00:00Z",
+        ];
+        let base = fingerprint(&window).unwrap();
+        assert_eq!(fingerprint(&window), Some(base));
+        assert_eq!(fingerprint(&["logs", "-F", "2024-06-01"]), None);
+        for option in [
+            vec!["--reverse"],
+            vec!["--anonymize"],
+            vec!["--time-format", "epoch-ms"],
+            vec!["--output-dir", "elsewhere"],
+            vec!["--select", "message"],
+        ] {
+            let args: Vec<&str> = window.iter().copied().chain(option.clone()).collect();
+            assert_ne!(fingerprint(&args), fingerprint(&window), "{:?}", option);
+        }
+    }
+
+    #[test]
+    fn test_check_disk_space() {
+        assert!(check_disk_space(1 << 20, 1 << 30).is_ok());
+        assert!(check_disk_space(950, 1000).is_ok());
+        assert!(check_disk_space(2 << 30, 1 << 30).is_err());
+    }
+
+    #[test]
+    fn test_index_blocks() {
+        let blocks = IndexBlocks::from_settings(&json!({
+            "logs-2": {"settings": {"index.blocks.write": "true"}},
+            "logs-1": {"settings": {
+                "index.blocks.write": "true",
+                "index.blocks.read_only_allow_delete": "false"
+            }},
+            "logs-3": {"settings": {"index.blocks.read_only": "true"}}
+        }));
+        assert!(blocks.check().is_ok());
+        assert_eq!(
+            blocks.warnings(),
+            vec![
+                "Index logs-3 has index.blocks.read_only set: their documents and settings \
+                 cannot change; the export reads them as usual"
+            ]
+        );
+
+        let blocks = IndexBlocks::from_settings(&json!({
+            "logs-1": {"settings": {"index.blocks.read": "true"}}
+        }));
+        let error = blocks.check().unwrap_err().to_string();
+        assert!(error.contains("Index logs-1 has index.blocks.read set"));
+    }
+}
@@ -0,0 +1,227 @@
+// src/commands/get.rs
+use crate::elasticsearch::builder::SearchQueryBuilder;
+use crate::elasticsearch::client::ElasticsearchClient;
+use crate::output::{OutputArgs, Printer};
+use crate::sink::SinkArgs;
+use crate::utils::*;
+use clap::Args;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::io::{self, BufRead};
+
+// Values looked up per search, well below the default max_terms_count of 65536
+const LOOKUP_BATCH_SIZE: usize = 500;
+const PAGE_SIZE: u32 = 1000;
+
+pub const EXAMPLES: &str = "\
+Examples:
+  # Documents by ID
+  esq get my-logs xG3k2ZABcd yH4l3ZABef
+
+  # IDs piped from another tool, one per line
+  cut -d, -f1 alerts.csv | esq get my-logs --stdin
+
+  # Every document of some traces
+  esq get my-logs --field trace.id 4bf92f3577b34da6";
+
+#[derive(Args)]
+pub struct GetArgs {
+    /// Index name or pattern holding the documents
+    #[arg(value_name = "index")]
+    pub index: String,
+
+    /// Document IDs to fetch, or values of --field
+    #[arg(value_name = "id")]
+    pub ids: Vec<String>,
+
+    /// Also read IDs from stdin, one per line
+    #[arg(long)]
+    pub stdin: bool,
+
+    /// Match the values against this field instead of document IDs (e.g. trace.id)
+    #[arg(long, value_name = "field")]
+    pub field: Option<String>,
+
+    /// Select specific fields (comma-separated), wildcards included (e.g. kubernetes.*,message)
+    #[arg(long = "select", value_name = "field1,field2,..")]
+    #[arg(short = 's')]
+    pub select_clause: Option<String>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub sink: SinkArgs,
+}
+
+// Documents with one of the IDs, or whose field holds one of the values
+fn lookup_query(field: Option<&str>, values: &[String]) -> Value {
+    match field {
+        Some(field) => json!({"terms": {field: values}}),
+        None => json!({"ids": {"values": values}}),
+    }
+}
+
+// Next batch of distinct, non-blank values, empty once the input is exhausted
+fn next_batch(
+    values: &mut impl Iterator<Item = io::Result<String>>,
+    seen: &mut HashSet<String>,
+) -> Result<Vec<String>, ESQError> {
+    let mut batch = Vec::new();
+    while batch.len() < LOOKUP_BATCH_SIZE {
+        let Some(value) = values.next() else {
+            break;
+        };
+        let value = value?.trim().to_string();
+        if !value.is_empty() && seen.insert(value.clone()) {
+            batch.push(value);
+        }
+    }
+    Ok(batch)
+}
+
+// The query is the one of an alias, the documents outside of it are not part of the index
+pub fn handle_get_command(
+    mut es: ElasticsearchClient,
+    args: &GetArgs,
+    query: Option<&str>,
+) -> Result<(), ESQError> {
+    if args.ids.is_empty() && !args.stdin {
+        return Err(ESQError::ValidationError(
+            "Provide document IDs as arguments or with --stdin.".to_string(),
+        ));
+    }
+    validate_index_pattern(&args.index)?;
+    install_interrupt_handler()?;
+
+    let select_fields: Option<Vec<String>> = args.select_clause.as_ref().map(|select| {
+        select
+            .split(',')
+            .map(|field| field.trim().to_string())
+            .filter(|field| !field.is_empty())
+            .collect()
+    });
+
+    let mut printer = Printer::new(&args.output, es.config().default.kibana_url.clone(), None)?;
+    printer.set_abbreviations(&es.config().abbreviations);
+    if let Some(fields) = &select_fields {
+        printer.set_fields(fields);
+    }
+    if let Some(sink) = args.sink.build()? {
+        printer.set_sink(sink);
+    }
+
+    es.set_index(&args.index);
+    // A value can match many documents with --field, pages are read from a single PIT
+    es.create_pit()?;
+
+    let stdin = io::stdin();
+    let mut values = args.ids.iter().map(|id| Ok(id.clone())).chain(
+        args.stdin
+            .then(|| stdin.lock().lines())
+            .into_iter()
+            .flatten(),
+    );
+    let mut seen = HashSet::new();
+    let mut missing = Vec::new();
+
+    while !interrupted() {
+        let batch = next_batch(&mut values, &mut seen)?;
+        if batch.is_empty() {
+            break;
+        }
+        let query_builder = SearchQueryBuilder::new()
+            .with_pit(true)
+            .with_size(PAGE_SIZE)
+            .with_source_fields(select_fields.clone())
+            .with_query(|query| query.filter(lookup_query(args.field.as_deref(), &batch)))
+            .with_query_string(query);
+
+        let mut found = HashSet::new();
+        let mut search_after = None;
+        while !interrupted() {
+            let mut current_builder = query_builder.clone();
+            if let Some(sort) = search_after.take() {
+                current_builder = current_builder.with_search_after(sort);
+            }
+            let response = match es.search(&current_builder.build()) {
+                Ok(response) => response,
+                Err(ESQError::Interrupted) => break,
+                Err(e) => return Err(e),
+            };
+            let hits = &response.hits.hits;
+            for hit in hits {
+                printer.print_hit(hit)?;
+                found.extend(hit.id.clone());
+            }
+            printer.flush_sink()?;
+            if hits.len() < PAGE_SIZE as usize {
+                break;
+            }
+            search_after = hits
+                .last()
+                .and_then(|hit| hit.sort.clone())
+                .map(Value::from);
+        }
+
+        // Field values may legitimately match nothing, missing IDs are worth reporting
+        if args.field.is_none() {
+            missing.extend(batch.into_iter().filter(|id| !found.contains(id)));
+        }
+    }
+
+    printer.finish()?;
+
+    if let Some(first) = missing.first() {
+        eprintln!(
+            "Warning: {} document(s) not found (e.g. {})",
+            missing.len(),
+            first
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_query() {
+        let values = vec!["a1".to_string(), "b2".to_string()];
+        assert_eq!(
+            lookup_query(None, &values),
+            json!({"ids": {"values": ["a1", "b2"]}})
+        );
+        assert_eq!(
+            lookup_query(Some("trace.id"), &values),
+            json!({"terms": {"trace.id": ["a1", "b2"]}})
+        );
+    }
+
+    #[test]
+    fn test_next_batch() {
+        let input = "a1\n\n  b2 \na1\n".to_string() + &"x\n".repeat(3);
+        let mut values = io::Cursor::new(input).lines();
+        let mut seen = HashSet::new();
+        assert_eq!(
+            next_batch(&mut values, &mut seen).unwrap(),
+            vec!["a1", "b2", "x"]
+        );
+        assert!(next_batch(&mut values, &mut seen).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_next_batch_is_bounded() {
+        let input: String = (0..LOOKUP_BATCH_SIZE + 1)
+            .map(|i| format!("{}\n", i))
+            .collect();
+        let mut values = io::Cursor::new(input).lines();
+        let mut seen = HashSet::new();
+        assert_eq!(
+            next_batch(&mut values, &mut seen).unwrap().len(),
+            LOOKUP_BATCH_SIZE
+        );
+        assert_eq!(next_batch(&mut values, &mut seen).unwrap(), vec!["500"]);
+    }
+}
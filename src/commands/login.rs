@@ -1,22 +1,44 @@
 // src/commands/login.rs
 // Import necessary libraries
-use reqwest;
 use rpassword;
 use std::io::{self, Write};
 use std::path::PathBuf;
 
+use crate::elasticsearch::client::{
+    ElasticsearchClient, certificate_fingerprint, same_fingerprint,
+};
 use crate::utils::DefaultConfig;
 use crate::utils::*;
 
 // Structure to hold the login context
 struct LoginContext {
     config: DefaultConfig,
+    // Includes and profiles of the existing configuration, saved along the new settings
+    existing: Config,
+    // Profile the settings are saved under, [default] when none
+    profile: Option<String>,
 }
 
 impl LoginContext {
     // Create a new LoginContext with the provided configuration
-    fn new(config: DefaultConfig) -> Self {
-        Self { config }
+    fn new(config: DefaultConfig, existing: Config, profile: Option<&str>) -> Self {
+        Self {
+            config,
+            existing,
+            profile: profile.map(str::to_string),
+        }
+    }
+
+    // Configuration to save, keeping everything of the existing one but the logged in settings
+    fn to_config(&self) -> Config {
+        let mut config = self.existing.clone();
+        match &self.profile {
+            Some(name) => {
+                config.profiles.insert(name.clone(), self.config.clone());
+            }
+            None => config.default = self.config.clone(),
+        }
+        config
     }
 }
 
@@ -85,32 +107,51 @@ fn get_credentials(existing_config: &Option<Config>) -> Result<(String, String),
     Ok((username, password))
 }
 
-// Test the connection to the Elasticsearch server
-fn test_connection(url: &str, config: &DefaultConfig) -> Result<bool, ESQError> {
-    let client = reqwest::blocking::Client::new();
-    let es_test_url = format!("{}/_cat", url.trim_end_matches('/'));
-
-    let mut request = client.get(&es_test_url);
-
-    if let Some(ref password) = config.password {
-        if let Some(ref username) = config.username {
-            request = request.basic_auth(username, Some(password));
-        }
-    }
-
-    let response = request.send()?;
-    if !response.status().is_success() {
-        return Ok(false);
+// Trust on first use: the certificate is pinned once confirmed, a changed one has to be confirmed again
+fn pin_certificate(url: &str, pinned: Option<&str>) -> Result<Option<String>, ESQError> {
+    if !url.starts_with("https://") {
+        return Ok(None);
     }
-
-    let text = response.text()?;
-    if !text.contains("/_cat/") {
-        return Err(ESQError::ConfigError(
-            "The server doesn't appear to be an Elasticsearch instance".to_string(),
-        ));
+    let Some(fingerprint) = certificate_fingerprint(url)? else {
+        return Ok(pinned.map(str::to_string));
+    };
+    let prompt = match pinned {
+        Some(pinned) if same_fingerprint(pinned, &fingerprint) => return Ok(Some(fingerprint)),
+        Some(pinned) => {
+            println!("WARNING: the certificate of {} changed", url);
+            println!("  Pinned:  {}", pinned);
+            println!("  Current: {}", fingerprint);
+            "Pin the new certificate? [y/N]: "
+        }
+        None => {
+            println!("Certificate SHA-256 fingerprint: {}", fingerprint);
+            "Pin this certificate? [Y/n]: "
+        }
+    };
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim().to_lowercase();
+    match (pinned, input.as_str()) {
+        (_, "y" | "yes") | (None, "") => Ok(Some(fingerprint)),
+        (None, _) => Ok(None),
+        (Some(_), _) => Err(ESQError::ValidationError(
+            "The changed certificate was not trusted".to_string(),
+        )),
     }
+}
 
-    Ok(true)
+// Test the connection to the Elasticsearch server
+fn test_connection(url: &str, config: &DefaultConfig) -> Result<bool, ESQError> {
+    let candidate = Config {
+        default: DefaultConfig {
+            url: url.to_string(),
+            ..config.clone()
+        },
+        ..Default::default()
+    };
+    ElasticsearchClient::new(candidate)?.test_connection()
 }
 
 // Function to attempt a connection to the Elasticsearch server
@@ -124,12 +165,7 @@ fn attempt_connection(
         println!("Credentials are temporarily stored in ~/.esq/config.toml");
         println!("Remove them after use with the 'logout' command");
 
-        save_config(
-            &Config {
-                default: login_context.config.clone(),
-            },
-            config_file,
-        )?;
+        save_config(&login_context.to_config(), config_file)?;
         Ok(())
     } else {
         println!("Authentication failed with provided credentials.");
@@ -141,25 +177,54 @@ fn attempt_connection(
 pub fn handle_login_command(
     existing_config: Option<Config>,
     config_file: &PathBuf,
+    profile: Option<&str>,
 ) -> Result<(), ESQError> {
-    // Create a login context by calling the get_url function with existing_config if it exists
-    let url = get_url(&None, &existing_config)?;
-    let mut login_context = LoginContext::new(DefaultConfig {
+    // The settings of the profile being logged in, none yet for a new profile
+    let current = existing_config
+        .as_ref()
+        .and_then(|config| config.for_profile(profile).ok());
+    // Create a login context by calling the get_url function with the current settings if any
+    let url = get_url(&None, &current)?;
+    let default_config = DefaultConfig {
         url: url.clone(),
+        nodes: current
+            .as_ref()
+            .map(|config| config.default.nodes.clone())
+            .unwrap_or_default(),
+        sniff: current.as_ref().is_some_and(|config| config.default.sniff),
         username: None,
         password: None,
-    });
-
-    // If a username exists in existing_config, call the get_credentials method
-    if let Some(config) = &existing_config {
-        if let Some(_username) = &config.default.username {
-            let (username, password) = get_credentials(&existing_config)?;
-            login_context.config.username = Some(username);
-            login_context.config.password = Some(password);
-            // Attempt to connect with authentication
-            attempt_connection(&url, &mut login_context, config_file)?;
-            return Ok(());
-        }
+        kibana_url: current
+            .as_ref()
+            .and_then(|config| config.default.kibana_url.clone()),
+        geoip_db: current
+            .as_ref()
+            .and_then(|config| config.default.geoip_db.clone()),
+        // A pin only holds for the URL it was taken from
+        tls_fingerprint: pin_certificate(
+            &url,
+            current
+                .as_ref()
+                .filter(|config| config.default.url == url)
+                .and_then(|config| config.default.tls_fingerprint.as_deref()),
+        )?,
+    };
+    let mut login_context = LoginContext::new(
+        default_config,
+        existing_config.clone().unwrap_or_default(),
+        profile,
+    );
+
+    // If a username exists in the current settings, call the get_credentials method
+    if let Some(config) = &current
+        && let Some(_username) = &config.default.username
+    {
+        let (username, password) = get_credentials(&current)?;
+        login_context.config.username = Some(username);
+        login_context.config.password = Some(password);
+        // Attempt to connect with authentication
+        attempt_connection(&url, &mut login_context, config_file)?;
+        return Ok(());
     }
 
     // Attempt to connect to the server without authentication
@@ -168,20 +233,16 @@ pub fn handle_login_command(
         &DefaultConfig {
             username: None,
             password: None,
+            tls_fingerprint: login_context.config.tls_fingerprint.clone(),
             ..Default::default()
         },
     )? {
         println!("Successfully connected to Elasticsearch!");
-        save_config(
-            &Config {
-                default: login_context.config.clone(),
-            },
-            config_file,
-        )?;
+        save_config(&login_context.to_config(), config_file)?;
         Ok(())
     } else {
         // If an authentication error occurs (401 code)
-        let (username, password) = get_credentials(&existing_config)?;
+        let (username, password) = get_credentials(&current)?;
         login_context.config.username = Some(username);
         login_context.config.password = Some(password);
         // Attempt to connect with authentication
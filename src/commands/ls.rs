@@ -1,26 +1,18 @@
 // src/commands/ls.rs
 use crate::elasticsearch::client::ElasticsearchClient;
+use crate::output::{Spinner, stdout_error};
 use crate::utils::*;
-use serde_json::Value;
+use std::io::{BufWriter, Write};
 
-fn display_indices(indices: &[Value]) {
-    for index_data in indices {
-        if let Some(index_name) = index_data["index"].as_str() {
-            println!("{}", index_name);
-        }
-    }
-}
-
-pub fn handle_ls_command(existing_config: Option<Config>) -> Result<(), ESQError> {
-    let config = existing_config
-        .ok_or_else(|| {
-            ESQError::ConfigError("No configuration found. Please login first.".to_string())
-        })?
-        .clone();
+// Names are printed as they arrive, `esq ls | head` stops reading the rest
+pub fn handle_ls_command(es: &ElasticsearchClient) -> Result<(), ESQError> {
+    let spinner = Spinner::start("Listing indices...");
+    let names = es.index_names()?;
+    drop(spinner);
 
-    let es = ElasticsearchClient::new(config)?;
-    let indices = es.list_indices()?;
-    display_indices(&indices);
-
-    Ok(())
+    let mut stdout = BufWriter::new(std::io::stdout());
+    for name in names {
+        writeln!(stdout, "{}", name?).map_err(stdout_error)?;
+    }
+    stdout.flush().map_err(stdout_error)
 }
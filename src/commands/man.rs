@@ -0,0 +1,35 @@
+// src/commands/man.rs
+use crate::utils::*;
+use clap::Command;
+use clap_mangen::Man;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+fn render(command: &Command, out: &mut dyn Write) -> Result<(), ESQError> {
+    Man::new(command.clone()).render(out)?;
+    Ok(())
+}
+
+pub fn handle_man_command(mut command: Command, out_dir: Option<&Path>) -> Result<(), ESQError> {
+    // Building sets the display name of subcommands (esq-cat), used as page titles
+    command.build();
+    let Some(dir) = out_dir else {
+        return render(&command, &mut io::stdout().lock());
+    };
+
+    fs::create_dir_all(dir)?;
+    let mut pages = vec![("esq".to_string(), command.clone())];
+    for subcommand in command.get_subcommands() {
+        if subcommand.get_name() == "help" {
+            continue;
+        }
+        pages.push((format!("esq-{}", subcommand.get_name()), subcommand.clone()));
+    }
+    for (name, page) in pages {
+        let path = dir.join(format!("{}.1", name));
+        render(&page, &mut fs::File::create(&path)?)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
@@ -1,5 +1,13 @@
 pub mod alias;
+pub mod assert;
 pub mod cat;
+pub mod export;
+pub mod get;
 pub mod login;
 pub mod logout;
 pub mod ls;
+pub mod man;
+pub mod replay;
+pub mod self_update;
+pub mod sizeof;
+pub mod watch;
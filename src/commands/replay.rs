@@ -0,0 +1,29 @@
+// src/commands/replay.rs
+use crate::session::{SessionRecord, read_session};
+use crate::utils::*;
+use std::path::Path;
+
+pub fn handle_replay_command(file: &Path) -> Result<(), ESQError> {
+    let records = read_session(file)?;
+
+    for record in &records {
+        match record {
+            SessionRecord::Header {
+                version,
+                recorded_at,
+                command,
+                cluster,
+            } => {
+                eprintln!(
+                    "Session recorded at {} with esq {} against {}",
+                    recorded_at, version, cluster.url
+                );
+                eprintln!("Command: {}", command.join(" "));
+            }
+            SessionRecord::Output { line } => println!("{}", line),
+            SessionRecord::Request { .. } | SessionRecord::Response { .. } => {}
+        }
+    }
+
+    Ok(())
+}
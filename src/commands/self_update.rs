@@ -0,0 +1,233 @@
+// src/commands/self_update.rs
+use crate::utils::*;
+use reqwest::blocking::Client;
+use ring::signature::{ED25519, UnparsedPublicKey};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/jiel/esq/releases/latest";
+const USER_AGENT: &str = concat!("esq/", env!("CARGO_PKG_VERSION"));
+// Hex Ed25519 public key the release artifacts are signed with, set when the release is built
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("ESQ_RELEASE_PUBLIC_KEY");
+
+// Name of the release artifact built for this platform, e.g. esq-x86_64-linux
+fn asset_name() -> String {
+    format!(
+        "esq-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+// Numeric components of a version or tag, "v0.10.2" -> [0, 10, 2]
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+fn is_newer(tag: &str, current: &str) -> bool {
+    version_parts(tag) > version_parts(current)
+}
+
+fn asset_url<'a>(release: &'a Value, name: &str) -> Option<&'a str> {
+    release["assets"]
+        .as_array()?
+        .iter()
+        .find(|asset| asset["name"] == name)?["browser_download_url"]
+        .as_str()
+}
+
+// The checksum files hold "<hex digest>  <file name>", as written by sha256sum
+fn parse_checksum(content: &str) -> Option<String> {
+    content
+        .split_whitespace()
+        .next()
+        .filter(|digest| digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_lowercase)
+}
+
+fn hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+// The .sig files hold the hex Ed25519 signature of the artifact, made with the release key
+fn verify_signature(public_key: &str, binary: &[u8], signature: &str) -> bool {
+    let (Some(public_key), Some(signature)) = (hex_bytes(public_key), hex_bytes(signature)) else {
+        return false;
+    };
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(binary, &signature)
+        .is_ok()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn download(client: &Client, url: &str) -> Result<Vec<u8>, ESQError> {
+    let response = client.get(url).send()?.error_for_status()?;
+    Ok(response.bytes()?.to_vec())
+}
+
+// Written next to the binary then renamed over it, so it is never left half replaced
+fn replace_binary(current: &Path, binary: &[u8]) -> Result<(), ESQError> {
+    let staged = current.with_extension("new");
+    fs::write(&staged, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+    // A running executable cannot be overwritten on Windows, but it can be moved away
+    #[cfg(windows)]
+    let moved = current.with_extension("old");
+    #[cfg(windows)]
+    fs::rename(current, &moved)?;
+    fs::rename(&staged, current).inspect_err(|_| {
+        let _ = fs::remove_file(&staged);
+        // Put the running binary back rather than leave no esq at all
+        #[cfg(windows)]
+        let _ = fs::rename(&moved, current);
+    })?;
+    Ok(())
+}
+
+pub fn handle_self_update_command(check: bool) -> Result<(), ESQError> {
+    let current_version = env!("CARGO_PKG_VERSION");
+    let client = Client::builder().user_agent(USER_AGENT).build()?;
+    let release: Value = client
+        .get(RELEASES_URL)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let tag = release["tag_name"]
+        .as_str()
+        .ok_or_else(|| ESQError::ParseError("Release without a tag name".to_string()))?;
+
+    if !is_newer(tag, current_version) {
+        println!("esq {} is up to date", current_version);
+        return Ok(());
+    }
+    if check {
+        println!("esq {} is available (installed: {})", tag, current_version);
+        return Ok(());
+    }
+
+    // Checked before any download, a build without the key has nothing to trust a release with
+    let public_key = RELEASE_PUBLIC_KEY.ok_or_else(|| {
+        ESQError::ValidationError(format!(
+            "This build of esq has no release key to verify {} with, download it from the releases page",
+            tag
+        ))
+    })?;
+    let name = asset_name();
+    let missing =
+        |file: &str| ESQError::ESError(format!("Release {} has no {} artifact", tag, file));
+    let binary_url = asset_url(&release, &name).ok_or_else(|| missing(&name))?;
+    let checksum_name = format!("{}.sha256", name);
+    let checksum_url =
+        asset_url(&release, &checksum_name).ok_or_else(|| missing(&checksum_name))?;
+    let signature_name = format!("{}.sig", name);
+    let signature_url =
+        asset_url(&release, &signature_name).ok_or_else(|| missing(&signature_name))?;
+
+    let expected = parse_checksum(&String::from_utf8_lossy(&download(&client, checksum_url)?))
+        .ok_or_else(|| ESQError::ParseError(format!("Invalid checksum file {}", checksum_name)))?;
+    let binary = download(&client, binary_url)?;
+    let actual = sha256_hex(&binary);
+    if actual != expected {
+        return Err(ESQError::ValidationError(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            name, expected, actual
+        )));
+    }
+    let signature = String::from_utf8_lossy(&download(&client, signature_url)?).into_owned();
+    if !verify_signature(public_key, &binary, &signature) {
+        return Err(ESQError::ValidationError(format!(
+            "The signature of {} does not match the release key, it was not installed",
+            name
+        )));
+    }
+
+    let current: PathBuf = std::env::current_exe()?.canonicalize()?;
+    replace_binary(&current, &binary)?;
+    println!(
+        "Updated {} from {} to {}",
+        current.display(),
+        current_version,
+        tag
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("v0.3.0", "0.2.1"));
+        assert!(is_newer("0.10.0", "0.9.7"));
+        assert!(!is_newer("v0.2.1", "0.2.1"));
+        assert!(!is_newer("v0.2.0", "0.2.1"));
+    }
+
+    #[test]
+    fn test_asset_url() {
+        let release = json!({"assets": [
+            {"name": "esq-x86_64-linux", "browser_download_url": "https://example.com/esq"},
+            {"name": "esq-x86_64-linux.sha256", "browser_download_url": "https://example.com/esq.sha256"}
+        ]});
+        assert_eq!(
+            asset_url(&release, "esq-x86_64-linux.sha256"),
+            Some("https://example.com/esq.sha256")
+        );
+        assert_eq!(asset_url(&release, "esq-aarch64-macos"), None);
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let digest = sha256_hex(b"esq");
+        assert_eq!(
+            parse_checksum(&format!("{}  esq-x86_64-linux\n", digest.to_uppercase())),
+            Some(digest)
+        );
+        assert_eq!(parse_checksum("not a digest"), None);
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+        let key = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let public_key = hex(key.public_key().as_ref());
+        let signature = format!("{}\n", hex(key.sign(b"esq").as_ref()));
+        assert!(verify_signature(&public_key, b"esq", &signature));
+        assert!(!verify_signature(&public_key, b"esq!", &signature));
+        assert!(!verify_signature(&public_key, b"esq", "not a signature"));
+        let other = Ed25519KeyPair::from_seed_unchecked(&[8; 32]).unwrap();
+        assert!(!verify_signature(
+            &hex(other.public_key().as_ref()),
+            b"esq",
+            &signature
+        ));
+    }
+}
@@ -0,0 +1,304 @@
+// src/commands/sizeof.rs
+use crate::commands::cat::{QueryFilters, QuerySpec, TimeWindow};
+use crate::elasticsearch::builder::SearchQueryBuilder;
+use crate::elasticsearch::client::ElasticsearchClient;
+use crate::project::{QueryTarget, require_index};
+use crate::utils::*;
+use clap::Args;
+use serde_json::Value;
+use std::time::Instant;
+
+const SAMPLE_SIZE: u32 = 1000;
+// Languages writing a decimal comma, e.g. 1,2 GiB for de_DE
+const DECIMAL_COMMA_LANGUAGES: [&str; 22] = [
+    "cs", "da", "de", "el", "es", "fi", "fr", "hu", "id", "it", "nb", "nl", "nn", "no", "pl", "pt",
+    "ro", "ru", "sk", "sv", "tr", "uk",
+];
+
+pub const EXAMPLES: &str = "\
+Examples:
+  # Size of a day of errors before exporting it
+  esq sizeof my-logs --from 2024-06-01 --to 2024-06-02 --where level:ERROR";
+
+#[derive(Args)]
+pub struct SizeofArgs {
+    /// Index name or pattern to estimate
+    #[arg(value_name = "index")]
+    pub index: Option<String>,
+
+    /// Start time for filtering results
+    #[arg(long, value_name = "datetime")]
+    #[arg(short = 'F')]
+    pub from: Option<String>,
+
+    /// End time for filtering results
+    #[arg(long, value_name = "datetime")]
+    #[arg(short = 'T')]
+    pub to: Option<String>,
+
+    /// Swap --from and --to when given in reverse order instead of failing
+    #[arg(long)]
+    pub swap_ok: bool,
+
+    /// Filter results with specific values in fields
+    #[arg(long = "where", value_name = "field1:value1,field2:value2,..")]
+    #[arg(short = 'w')]
+    pub where_clause: Option<String>,
+
+    /// Print exact document counts and sizes in bytes, for scripts
+    #[arg(long, visible_alias = "bytes")]
+    pub raw_numbers: bool,
+}
+
+impl SizeofArgs {
+    pub fn query_spec(&self) -> Result<QuerySpec, ESQError> {
+        Ok(QuerySpec {
+            index: require_index(&self.index)?.to_string(),
+            window: TimeWindow {
+                from: self.from.clone(),
+                to: self.to.clone(),
+                ..Default::default()
+            },
+            filters: QueryFilters {
+                where_clause: self.where_clause.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    pub fn query_target(&mut self) -> QueryTarget<'_> {
+        QueryTarget {
+            index: &mut self.index,
+            select: None,
+            where_clause: &mut self.where_clause,
+            from: &mut self.from,
+            to: &mut self.to,
+        }
+    }
+}
+
+struct SizeEstimate {
+    matching_docs: u64,
+    avg_doc_size: u64,
+    docs_per_sec: Option<f64>,
+}
+
+impl SizeEstimate {
+    fn total_bytes(&self) -> u64 {
+        self.matching_docs.saturating_mul(self.avg_doc_size)
+    }
+
+    fn duration_secs(&self) -> Option<f64> {
+        self.docs_per_sec
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| self.matching_docs as f64 / rate)
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+// The decimal separator of LC_ALL, LC_NUMERIC or LANG, whichever is set first
+fn decimal_separator() -> char {
+    let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_default();
+    let language = locale.split(['_', '.', '@']).next().unwrap_or_default();
+    if DECIMAL_COMMA_LANGUAGES.contains(&language) {
+        ','
+    } else {
+        '.'
+    }
+}
+
+// Document counts and byte sizes, short for reading (1.2M, 3.4 GiB) or exact for scripts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberFormat {
+    Human(char),
+    Raw,
+}
+
+impl NumberFormat {
+    pub fn new(raw: bool) -> Self {
+        if raw {
+            NumberFormat::Raw
+        } else {
+            NumberFormat::Human(decimal_separator())
+        }
+    }
+
+    pub fn bytes(&self, bytes: u64) -> String {
+        match self {
+            NumberFormat::Raw => bytes.to_string(),
+            NumberFormat::Human(decimal) => format_bytes(bytes).replace('.', &decimal.to_string()),
+        }
+    }
+
+    pub fn count(&self, count: u64) -> String {
+        const UNITS: [&str; 4] = ["k", "M", "B", "T"];
+        let NumberFormat::Human(decimal) = self else {
+            return count.to_string();
+        };
+        if count < 1000 {
+            return count.to_string();
+        }
+        let mut value = count as f64 / 1000.0;
+        let mut unit = 0;
+        while value >= 999.95 && unit < UNITS.len() - 1 {
+            value /= 1000.0;
+            unit += 1;
+        }
+        format!("{:.1}{}", value, UNITS[unit]).replace('.', &decimal.to_string())
+    }
+}
+
+// Average primary store size per document, an approximation of the _source size
+fn average_doc_size(stats: &Value) -> u64 {
+    let primaries = &stats["_all"]["primaries"];
+    let docs = primaries["docs"]["count"].as_u64().unwrap_or(0);
+    let bytes = primaries["store"]["size_in_bytes"].as_u64().unwrap_or(0);
+    bytes.checked_div(docs).unwrap_or(0)
+}
+
+// Fetch one batch with the same query to measure the current throughput
+fn sample_throughput(
+    es: &ElasticsearchClient,
+    query_builder: &SearchQueryBuilder,
+    matching_docs: u64,
+) -> Result<Option<f64>, ESQError> {
+    if matching_docs == 0 {
+        return Ok(None);
+    }
+
+    let size = matching_docs.min(SAMPLE_SIZE as u64) as u32;
+    let search_query = query_builder.clone().with_size(size).build();
+
+    let start = Instant::now();
+    let response = es.search(&search_query)?;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let hits = response.hits.hits.len();
+
+    if hits == 0 || elapsed <= 0.0 {
+        Ok(None)
+    } else {
+        Ok(Some(hits as f64 / elapsed))
+    }
+}
+
+// Bytes the matching documents weigh, without the slower throughput probe
+pub fn estimate_transfer(es: &ElasticsearchClient, spec: &QuerySpec) -> Result<u64, ESQError> {
+    let query = spec.count_query()?.build();
+    let estimate = SizeEstimate {
+        matching_docs: es.count(query.get("query"))?,
+        avg_doc_size: average_doc_size(&es.index_stats()?),
+        docs_per_sec: None,
+    };
+    Ok(estimate.total_bytes())
+}
+
+pub fn handle_sizeof_command(
+    mut es: ElasticsearchClient,
+    spec: &QuerySpec,
+    numbers: NumberFormat,
+) -> Result<(), ESQError> {
+    validate_index_pattern(&spec.index)?;
+
+    es.set_index(&spec.index);
+
+    let query_builder = spec.count_query()?;
+    let query = query_builder.clone().build();
+
+    let matching_docs = es.count(query.get("query"))?;
+    let estimate = SizeEstimate {
+        matching_docs,
+        avg_doc_size: average_doc_size(&es.index_stats()?),
+        docs_per_sec: sample_throughput(&es, &query_builder, matching_docs)?,
+    };
+
+    println!(
+        "Matching documents:    {}",
+        numbers.count(estimate.matching_docs)
+    );
+    println!(
+        "Average document size: {}",
+        numbers.bytes(estimate.avg_doc_size)
+    );
+    println!(
+        "Estimated transfer:    {}",
+        numbers.bytes(estimate.total_bytes())
+    );
+    match (estimate.duration_secs(), estimate.docs_per_sec) {
+        (Some(secs), Some(rate)) => println!(
+            "Estimated duration:    {} (at {} docs/s)",
+            format_duration(secs),
+            numbers.count(rate.round() as u64)
+        ),
+        _ => println!("Estimated duration:    unknown"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn test_number_format() {
+        let human = NumberFormat::Human('.');
+        assert_eq!(human.count(999), "999");
+        assert_eq!(human.count(1_234_567), "1.2M");
+        assert_eq!(human.count(999_999), "1.0M");
+        assert_eq!(human.count(12_500), "12.5k");
+        assert_eq!(NumberFormat::Human(',').bytes(1536), "1,5 KiB");
+        assert_eq!(NumberFormat::Raw.count(1_234_567), "1234567");
+        assert_eq!(NumberFormat::Raw.bytes(1536), "1536");
+    }
+
+    #[test]
+    fn test_average_doc_size() {
+        let stats = json!({
+            "_all": {
+                "primaries": {
+                    "docs": {"count": 100},
+                    "store": {"size_in_bytes": 51200}
+                }
+            }
+        });
+        assert_eq!(average_doc_size(&stats), 512);
+        assert_eq!(average_doc_size(&json!({})), 0);
+    }
+
+    #[test]
+    fn test_size_estimate() {
+        let estimate = SizeEstimate {
+            matching_docs: 10_000,
+            avg_doc_size: 500,
+            docs_per_sec: Some(2000.0),
+        };
+        assert_eq!(estimate.total_bytes(), 5_000_000);
+        assert_eq!(estimate.duration_secs(), Some(5.0));
+    }
+}
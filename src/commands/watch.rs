@@ -0,0 +1,531 @@
+// src/commands/watch.rs
+use crate::commands::cat::{QueryFilters, QuerySpec, TimeWindow};
+use crate::elasticsearch::builder::{composite_agg, terms_agg};
+use crate::elasticsearch::client::ElasticsearchClient;
+use crate::project::{QueryTarget, require_index};
+use crate::utils::*;
+use chrono::Local;
+use clap::{Args, Parser, Subcommand};
+use serde_json::{Value, json};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a wait between two runs checks for Ctrl-C
+const INTERRUPT_POLL: Duration = Duration::from_millis(250);
+// Values per request once a top no longer fits in search.max_buckets
+const COMPOSITE_PAGE_SIZE: u32 = 1000;
+// Pages per run before a paged top settles for the values counted so far
+const MAX_COMPOSITE_PAGES: usize = 50;
+
+pub const EXAMPLES: &str = "\
+Examples:
+  # Counts per level over the last 15 minutes, the changes printed every 30 seconds
+  esq watch 'top my-logs level --from 15m' --interval 30s
+
+  # The same without quotes, values with spaces keep their own argument
+  esq watch --interval 1m top my-logs service.name --where 'level:ERROR'";
+
+#[derive(Args)]
+pub struct WatchArgs {
+    /// Time between two runs of the command
+    #[arg(long, value_name = "duration", value_parser = parse_interval, default_value = "30s")]
+    pub interval: Duration,
+
+    /// Command to re-run, e.g. 'top my-logs level'
+    #[arg(
+        value_name = "command",
+        required = true,
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    pub command: Vec<String>,
+}
+
+// The commands esq watch knows how to re-run
+#[derive(Parser)]
+#[command(name = "esq watch", no_binary_name = true)]
+struct WatchedCli {
+    #[command(subcommand)]
+    command: Watched,
+}
+
+#[derive(Subcommand)]
+pub enum Watched {
+    /// Most frequent values of a field and their counts
+    #[command(override_usage = "top [index] <field> [OPTIONS]")]
+    Top(TopArgs),
+}
+
+#[derive(Args)]
+pub struct TopArgs {
+    /// Index name or pattern to count in, then the keyword field whose values are counted
+    #[arg(value_name = "[index] field", num_args = 1..=2, required = true)]
+    targets: Vec<String>,
+
+    // Split from the targets, the index is left to the project when only the field is given
+    #[arg(skip)]
+    pub index: Option<String>,
+    #[arg(skip)]
+    pub field: String,
+
+    /// Number of values to report
+    #[arg(long, short = 'n', value_name = "count", default_value_t = 10)]
+    pub size: u32,
+
+    /// Start time for filtering results, relative ones slide with each run (e.g. 15m)
+    #[arg(long, value_name = "datetime")]
+    #[arg(short = 'F')]
+    pub from: Option<String>,
+
+    /// End time for filtering results
+    #[arg(long, value_name = "datetime")]
+    #[arg(short = 'T')]
+    pub to: Option<String>,
+
+    /// Filter results with specific values in fields
+    #[arg(long = "where", value_name = "field1:value1,field2:value2,..")]
+    #[arg(short = 'w')]
+    pub where_clause: Option<String>,
+}
+
+// A zero interval would re-run the command back to back
+fn parse_interval(input: &str) -> Result<Duration, String> {
+    match parse_duration_arg(input)? {
+        interval if interval.is_zero() => Err("the interval must be longer than 0s".to_string()),
+        interval => Ok(interval),
+    }
+}
+
+impl WatchArgs {
+    // A single argument holds the whole quoted command, split the way a shell would
+    pub fn watched(&self) -> Result<Watched, ESQError> {
+        let words: Vec<String> = match self.command.as_slice() {
+            [command] => shlex::split(command).ok_or_else(|| {
+                ESQError::ValidationError(format!("Unbalanced quotes in the command: {}", command))
+            })?,
+            words => words.to_vec(),
+        };
+        let Watched::Top(mut top) = WatchedCli::try_parse_from(words)
+            .map(|cli| cli.command)
+            .map_err(|e| {
+                let message = e.to_string();
+                let message = message.trim_start_matches("error: ").trim_end();
+                ESQError::ValidationError(message.to_string())
+            })?;
+        if let Some(field) = top.targets.pop() {
+            top.field = field;
+        }
+        top.index = top.targets.pop();
+        Ok(Watched::Top(top))
+    }
+}
+
+impl TopArgs {
+    pub fn query_spec(&self) -> Result<QuerySpec, ESQError> {
+        Ok(QuerySpec {
+            index: require_index(&self.index)?.to_string(),
+            window: TimeWindow {
+                from: self.from.clone(),
+                to: self.to.clone(),
+                ..Default::default()
+            },
+            filters: QueryFilters {
+                where_clause: self.where_clause.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    pub fn query_target(&mut self) -> QueryTarget<'_> {
+        QueryTarget {
+            index: &mut self.index,
+            select: None,
+            where_clause: &mut self.where_clause,
+            from: &mut self.from,
+            to: &mut self.to,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    New(u64),
+    Changed(u64, u64),
+    Gone,
+}
+
+fn bucket_key(key_as_string: &Value, key: &Value) -> Option<String> {
+    match (key_as_string, key) {
+        (Value::String(key), _) | (_, Value::String(key)) => Some(key.clone()),
+        (_, key) if !key.is_null() => Some(key.to_string()),
+        _ => None,
+    }
+}
+
+// Values and counts of a terms aggregation, most frequent first
+fn bucket_counts(agg: &Value) -> Vec<(String, u64)> {
+    agg["buckets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|bucket| {
+            let key = bucket_key(&bucket["key_as_string"], &bucket["key"])?;
+            Some((key, bucket["doc_count"].as_u64().unwrap_or_default()))
+        })
+        .collect()
+}
+
+// Values and counts of a composite aggregation page, in key order
+fn composite_counts(agg: &Value) -> Vec<(String, u64)> {
+    agg["buckets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|bucket| {
+            let key = bucket_key(&Value::Null, &bucket["key"]["value"])?;
+            Some((key, bucket["doc_count"].as_u64().unwrap_or_default()))
+        })
+        .collect()
+}
+
+fn most_frequent(mut counts: Vec<(String, u64)>, size: u32) -> Vec<(String, u64)> {
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(size as usize);
+    counts
+}
+
+// What changed since the previous run, in the order of the current one then the values gone
+fn changes(previous: &[(String, u64)], current: &[(String, u64)]) -> Vec<(String, Change)> {
+    let mut changes: Vec<(String, Change)> = current
+        .iter()
+        .filter_map(
+            |(key, count)| match previous.iter().find(|(previous, _)| previous == key) {
+                None => Some((key.clone(), Change::New(*count))),
+                Some((_, before)) if before != count => {
+                    Some((key.clone(), Change::Changed(*before, *count)))
+                }
+                Some(_) => None,
+            },
+        )
+        .collect();
+    for (key, _) in previous {
+        if !current.iter().any(|(current, _)| current == key) {
+            changes.push((key.clone(), Change::Gone));
+        }
+    }
+    changes
+}
+
+fn key_width<'a>(keys: impl Iterator<Item = &'a String>) -> usize {
+    keys.map(|key| key.chars().count())
+        .max()
+        .unwrap_or_default()
+}
+
+fn count_lines(counts: &[(String, u64)]) -> Vec<String> {
+    let width = key_width(counts.iter().map(|(key, _)| key));
+    counts
+        .iter()
+        .map(|(key, count)| format!("{:<width$}  {:>8}", key, count, width = width))
+        .collect()
+}
+
+fn change_lines(changes: &[(String, Change)], size: u32) -> Vec<String> {
+    let width = key_width(changes.iter().map(|(key, _)| key));
+    changes
+        .iter()
+        .map(|(key, change)| {
+            // A value that left the top has no count to show, it was only outranked
+            let (count, marker) = match change {
+                Change::New(count) => (count.to_string(), "new".to_string()),
+                Change::Changed(before, after) if after > before => {
+                    (after.to_string(), format!("↑ +{}", after - before))
+                }
+                Change::Changed(before, after) => {
+                    (after.to_string(), format!("↓ -{}", before - after))
+                }
+                Change::Gone => (String::new(), format!("left top {}", size)),
+            };
+            format!("{:<width$}  {:>8}  {}", key, count, marker, width = width)
+        })
+        .collect()
+}
+
+// How the counts of a top are fetched, a terms aggregation unless it outgrew search.max_buckets
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Counting {
+    Terms,
+    Paged,
+    // Paged, and already warned that the pages ran out before the values did
+    Capped,
+}
+
+fn top_counts(
+    es: &ElasticsearchClient,
+    spec: &QuerySpec,
+    field: &str,
+    size: u32,
+    counting: &mut Counting,
+) -> Result<Vec<(String, u64)>, ESQError> {
+    if *counting == Counting::Terms {
+        match terms_counts(es, spec, field, size) {
+            Err(ESQError::TooManyBuckets(limit)) => {
+                let limit = limit
+                    .map(|limit| format!(" ({})", limit))
+                    .unwrap_or_default();
+                eprintln!(
+                    "The top {} values of {} need more buckets than search.max_buckets{}, counting them page by page",
+                    size, field, limit
+                );
+                *counting = Counting::Paged;
+            }
+            result => return result,
+        }
+    }
+    let (counts, complete) = paged_counts(es, spec, field, size)?;
+    if !complete && *counting == Counting::Paged {
+        eprintln!(
+            "{} has more than {} values, the top ranks only the first {} pages of them",
+            field,
+            MAX_COMPOSITE_PAGES as u32 * COMPOSITE_PAGE_SIZE,
+            MAX_COMPOSITE_PAGES
+        );
+        *counting = Counting::Capped;
+    }
+    Ok(counts)
+}
+
+fn terms_counts(
+    es: &ElasticsearchClient,
+    spec: &QuerySpec,
+    field: &str,
+    size: u32,
+) -> Result<Vec<(String, u64)>, ESQError> {
+    // Rebuilt on each run, so relative times slide with the clock
+    let query = spec
+        .count_query()?
+        .with_size(0)
+        .with_aggs(json!({"top": terms_agg(field, size)}))
+        .build();
+    let response = es.search(&query)?;
+    Ok(response
+        .aggregations
+        .as_ref()
+        .map(|aggs| bucket_counts(&aggs["top"]))
+        .unwrap_or_default())
+}
+
+// Values through composite pages, each one well under the bucket limit, ranked once counted;
+// false along with them when MAX_COMPOSITE_PAGES ran out first
+fn paged_counts(
+    es: &ElasticsearchClient,
+    spec: &QuerySpec,
+    field: &str,
+    size: u32,
+) -> Result<(Vec<(String, u64)>, bool), ESQError> {
+    let mut counts = Vec::new();
+    let mut after: Option<Value> = None;
+    for _ in 0..MAX_COMPOSITE_PAGES {
+        let query = spec
+            .count_query()?
+            .with_size(0)
+            .with_aggs(json!({"top": composite_agg(field, COMPOSITE_PAGE_SIZE, after.as_ref())}))
+            .build();
+        let response = es.search(&query)?;
+        let Some(agg) = response.aggregations.as_ref().map(|aggs| &aggs["top"]) else {
+            return Ok((most_frequent(counts, size), true));
+        };
+        let page = composite_counts(agg);
+        let last_page = page.is_empty() || agg.get("after_key").is_none();
+        counts.extend(page);
+        if last_page {
+            return Ok((most_frequent(counts, size), true));
+        }
+        after = agg.get("after_key").cloned();
+    }
+    Ok((most_frequent(counts, size), false))
+}
+
+// Waits out the interval, cut short by Ctrl-C
+fn pause(interval: Duration) {
+    let start = Instant::now();
+    while !interrupted() {
+        let Some(left) = interval.checked_sub(start.elapsed()) else {
+            break;
+        };
+        thread::sleep(left.min(INTERRUPT_POLL));
+    }
+}
+
+pub fn handle_watch_command(
+    mut es: ElasticsearchClient,
+    spec: &QuerySpec,
+    top: &TopArgs,
+    interval: Duration,
+) -> Result<(), ESQError> {
+    validate_index_pattern(&spec.index)?;
+    install_interrupt_handler()?;
+    es.set_index(&spec.index);
+
+    let mut previous: Option<Vec<(String, u64)>> = None;
+    let mut counting = Counting::Terms;
+    while !interrupted() {
+        let now = Local::now().format("%H:%M:%S");
+        let current = match top_counts(&es, spec, &top.field, top.size, &mut counting) {
+            Ok(current) => current,
+            Err(ESQError::Interrupted) => break,
+            // A run lost to the network is skipped, the next one compares with the last printed
+            Err(ESQError::NetworkError(msg)) if previous.is_some() => {
+                eprintln!("{}  Connection error: {}", now, msg);
+                pause(interval);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let lines = match &previous {
+            None => {
+                println!(
+                    "Every {}s: top {} {}",
+                    interval.as_secs(),
+                    spec.index,
+                    top.field
+                );
+                count_lines(&current)
+            }
+            Some(previous) => change_lines(&changes(previous, &current), top.size),
+        };
+        if !lines.is_empty() {
+            println!("{}", now);
+            for line in lines {
+                println!("  {}", line);
+            }
+        }
+        previous = Some(current);
+        pause(interval);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(pairs: &[(&str, u64)]) -> Vec<(String, u64)> {
+        pairs
+            .iter()
+            .map(|(key, count)| (key.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn test_watched_command() {
+        let args = WatchArgs {
+            interval: Duration::from_secs(30),
+            command: vec!["top my-logs level -n 5 --from 15m".to_string()],
+        };
+        let Watched::Top(top) = args.watched().unwrap();
+        assert_eq!(top.index.as_deref(), Some("my-logs"));
+        assert_eq!((top.field.as_str(), top.size), ("level", 5));
+
+        let args = WatchArgs {
+            interval: Duration::from_secs(30),
+            command: [
+                "top",
+                "my-logs",
+                "service.name",
+                "--where",
+                "host.name:web 1",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+        };
+        let Watched::Top(top) = args.watched().unwrap();
+        assert_eq!(top.where_clause.as_deref(), Some("host.name:web 1"));
+
+        // Quotes inside the single argument group the words as a shell would
+        let args = WatchArgs {
+            interval: Duration::from_secs(30),
+            command: vec!["top my-logs service.name --where 'host.name:web 1'".to_string()],
+        };
+        let Watched::Top(top) = args.watched().unwrap();
+        assert_eq!(top.where_clause.as_deref(), Some("host.name:web 1"));
+        let args = WatchArgs {
+            interval: Duration::from_secs(30),
+            command: vec!["top my-logs level --where 'level:ERROR".to_string()],
+        };
+        assert!(args.watched().is_err());
+        assert!(parse_interval("0s").is_err());
+        assert_eq!(parse_interval("1m"), Ok(Duration::from_secs(60)));
+
+        let args = WatchArgs {
+            interval: Duration::from_secs(30),
+            command: vec!["hist my-logs".to_string()],
+        };
+        assert!(args.watched().is_err());
+
+        // The index of the project
+        let args = WatchArgs {
+            interval: Duration::from_secs(30),
+            command: vec!["top level".to_string()],
+        };
+        let Watched::Top(top) = args.watched().unwrap();
+        assert_eq!((top.index, top.field.as_str()), (None, "level"));
+    }
+
+    #[test]
+    fn test_bucket_counts() {
+        let agg = json!({"buckets": [
+            {"key": "ERROR", "doc_count": 12},
+            {"key": 404, "doc_count": 3},
+            {"key": 1717243293000i64, "key_as_string": "2024-06-01", "doc_count": 1}
+        ]});
+        assert_eq!(
+            bucket_counts(&agg),
+            counts(&[("ERROR", 12), ("404", 3), ("2024-06-01", 1)])
+        );
+    }
+
+    #[test]
+    fn test_composite_counts() {
+        let page = json!({
+            "after_key": {"value": "web-3"},
+            "buckets": [
+                {"key": {"value": "web-1"}, "doc_count": 4},
+                {"key": {"value": "web-2"}, "doc_count": 9},
+                {"key": {"value": "web-3"}, "doc_count": 4}
+            ]
+        });
+        assert_eq!(
+            most_frequent(composite_counts(&page), 2),
+            counts(&[("web-2", 9), ("web-1", 4)])
+        );
+    }
+
+    #[test]
+    fn test_changes() {
+        let previous = counts(&[("INFO", 100), ("WARN", 10), ("DEBUG", 5)]);
+        let current = counts(&[("INFO", 112), ("ERROR", 3), ("WARN", 10)]);
+        let changes = changes(&previous, &current);
+        assert_eq!(
+            changes,
+            vec![
+                ("INFO".to_string(), Change::Changed(100, 112)),
+                ("ERROR".to_string(), Change::New(3)),
+                ("DEBUG".to_string(), Change::Gone),
+            ]
+        );
+        assert_eq!(
+            change_lines(&changes, 10),
+            vec![
+                "INFO        112  ↑ +12",
+                "ERROR         3  new",
+                "DEBUG            left top 10"
+            ]
+        );
+        assert_eq!(
+            change_lines(&[("WARN".to_string(), Change::Changed(10, 7))], 10),
+            vec!["WARN         7  ↓ -3"]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+// src/context.rs
+use crate::cache::CACHE_DIR;
+use crate::commands::alias::{SHARED_ALIASES_FILE, shared_aliases};
+use crate::elasticsearch::client::ElasticsearchClient;
+use crate::history::HISTORY_FILE;
+use crate::project::ProjectConfig;
+use crate::redact::register_secret;
+use crate::usage::USAGE_FILE;
+use crate::utils::*;
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// State shared by every subcommand of a run: the configuration and the one place
+// where Elasticsearch clients get built
+pub struct AppContext {
+    pub config_file: PathBuf,
+    pub config: Option<Config>,
+    pub project: ProjectConfig,
+    profile: Option<String>,
+    no_sniff: bool,
+    // Settings from the environment only, nothing under ~/.esq is read or written
+    no_config: bool,
+    // Fetched on first use, most commands never look an alias up
+    shared_aliases: OnceCell<BTreeMap<String, LocalAlias>>,
+}
+
+impl AppContext {
+    // The --profile flag wins over the profile pinned by the project
+    pub fn load(profile: Option<&str>, no_sniff: bool, no_config: bool) -> Result<Self, ESQError> {
+        let config_dir = dirs::home_dir()
+            .ok_or(ESQError::ConfigError(
+                "Could not determine home directory".to_string(),
+            ))?
+            .join(".esq");
+        let config_file = config_dir.join("config.toml");
+        let config = if no_config {
+            Some(config_from_env(|name| std::env::var(name).ok())?)
+        } else {
+            load_config(&config_file)?
+        };
+        // Masked from every message of the run, whichever profile ends up used
+        for settings in config
+            .iter()
+            .flat_map(|config| std::iter::once(&config.default).chain(config.profiles.values()))
+        {
+            if let Some(password) = &settings.password {
+                register_secret(password);
+            }
+        }
+
+        let project = if no_config {
+            ProjectConfig::default()
+        } else {
+            match ProjectConfig::discover(&std::env::current_dir()?)? {
+                Some((_, project)) => project,
+                None => ProjectConfig::default(),
+            }
+        };
+        if let Some(field) = &project.time_field {
+            set_time_field(field);
+        }
+        let profile = profile
+            .map(str::to_string)
+            .or_else(|| project.profile.clone());
+
+        Ok(Self {
+            config_file,
+            config,
+            project,
+            profile,
+            no_sniff,
+            no_config,
+            shared_aliases: OnceCell::new(),
+        })
+    }
+
+    // Where login, logout and alias changes save the configuration
+    pub fn writable_config_file(&self) -> Result<&PathBuf, ESQError> {
+        if self.no_config {
+            Err(ESQError::ConfigError(format!(
+                "--no-config never writes {}, pass the settings through ESQ_URL and the other ESQ_ variables",
+                self.config_file.display()
+            )))
+        } else {
+            Ok(&self.config_file)
+        }
+    }
+
+    // Completed exports, next to the configuration, none kept with --no-config
+    pub fn history_file(&self) -> Option<PathBuf> {
+        (!self.no_config).then(|| self.config_file.with_file_name(HISTORY_FILE))
+    }
+
+    // Local usage stats, next to the configuration, none kept with --no-config
+    pub fn usage_file(&self) -> Option<PathBuf> {
+        (!self.no_config).then(|| self.config_file.with_file_name(USAGE_FILE))
+    }
+
+    // Hits kept by cat --cache, next to the configuration, none kept with --no-config
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        (!self.no_config).then(|| self.config_file.with_file_name(CACHE_DIR))
+    }
+
+    // Under which --parallel-windows makes its private spool directory, none with --no-config
+    pub fn spool_dir(&self) -> Option<PathBuf> {
+        (!self.no_config).then(std::env::temp_dir)
+    }
+
+    // Cached copy of the aliases of aliases_url, next to the configuration
+    pub fn shared_aliases_file(&self) -> PathBuf {
+        self.config_file.with_file_name(SHARED_ALIASES_FILE)
+    }
+
+    // Profile selected by --profile or the project, None for [default]
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    // Name the usage of this run is counted under
+    pub fn profile_name(&self) -> &str {
+        self.profile.as_deref().unwrap_or("default")
+    }
+
+    pub fn require_config(&self) -> Result<&Config, ESQError> {
+        self.config.as_ref().ok_or_else(|| {
+            ESQError::ConfigError("No configuration found. Please login first.".to_string())
+        })
+    }
+
+    // Configuration of a profile, the selected one when none is given
+    pub fn config_for(&self, profile: Option<&str>) -> Result<Config, ESQError> {
+        let mut config = self
+            .require_config()?
+            .for_profile(profile.or(self.profile.as_deref()))?;
+        config.default.sniff &= !self.no_sniff;
+        Ok(config)
+    }
+
+    pub fn client(&self) -> Result<ElasticsearchClient, ESQError> {
+        self.client_for(None)
+    }
+
+    pub fn client_for(&self, profile: Option<&str>) -> Result<ElasticsearchClient, ESQError> {
+        ElasticsearchClient::new(self.config_for(profile)?)
+    }
+
+    // Aliases of the team file at aliases_url, none when it is not set or cannot be read
+    pub fn shared_aliases(&self) -> &BTreeMap<String, LocalAlias> {
+        self.shared_aliases.get_or_init(|| {
+            let Some(url) = self
+                .config
+                .as_ref()
+                .and_then(|config| config.aliases_url.as_deref())
+            else {
+                return BTreeMap::new();
+            };
+            shared_aliases(url, &self.shared_aliases_file(), false).unwrap_or_else(|e| {
+                eprintln!("Warning: shared aliases are unavailable: {}", e);
+                BTreeMap::new()
+            })
+        })
+    }
+
+    // The aliases of the configuration, then the shared ones
+    pub fn local_alias(&self, name: &str) -> Option<&LocalAlias> {
+        self.config
+            .as_ref()?
+            .aliases
+            .get(name)
+            .or_else(|| self.shared_aliases().get(name))
+    }
+}
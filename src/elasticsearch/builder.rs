@@ -1,28 +1,89 @@
-use crate::utils::ESQError;
-use dateparser::parse;
+use crate::utils::{ESQError, parse_datetime, time_field};
 use serde_json::{Value, json};
 
+// Clauses of the top-level bool query, accumulated from every filter source
+#[derive(Clone, Default)]
+pub struct BoolQuery {
+    must: Vec<Value>,
+    must_not: Vec<Value>,
+    filter: Vec<Value>,
+}
+
+impl BoolQuery {
+    pub fn must(&mut self, clause: Value) {
+        self.must.push(clause);
+    }
+
+    pub fn must_not(&mut self, clause: Value) {
+        self.must_not.push(clause);
+    }
+
+    pub fn filter(&mut self, clause: Value) {
+        self.filter.push(clause);
+    }
+
+    // Outside of scoring, must clauses match the same documents in the cacheable filter context
+    fn into_filter_context(mut self) -> Self {
+        self.filter.append(&mut self.must);
+        self
+    }
+
+    // A lone must clause is sent as is, anything else becomes a bool query
+    pub fn build(self) -> Option<Value> {
+        if self.must_not.is_empty() && self.filter.is_empty() {
+            match self.must.len() {
+                0 => return None,
+                1 => return self.must.into_iter().next(),
+                _ => {}
+            }
+        }
+
+        let mut bool_query = json!({});
+        for (occur, clauses) in [
+            ("must", self.must),
+            ("must_not", self.must_not),
+            ("filter", self.filter),
+        ] {
+            if !clauses.is_empty() {
+                bool_query[occur] = Value::Array(clauses);
+            }
+        }
+        Some(json!({"bool": bool_query}))
+    }
+}
+
 #[derive(Clone)]
 pub struct SearchQueryBuilder {
     sort_order: Value,
     size: u32,
     source_fields: Option<Vec<String>>,
+    source_excludes: Vec<String>,
     search_after: Option<Value>,
-    query_range: Option<Value>,
-    query_match: Option<Value>,
+    query: BoolQuery,
+    // Lucene query strings, each a required clause of the bool query
+    query_strings: Vec<String>,
     use_pit: bool,
+    fields_api: bool,
+    docvalue_fields: Option<Vec<String>>,
+    aggs: Option<Value>,
+    terminate_after: Option<u32>,
 }
 
 impl Default for SearchQueryBuilder {
     fn default() -> Self {
         Self {
-            sort_order: json!([{"@timestamp": {"order": "asc"}}]),
+            sort_order: json!([{time_field(): {"order": "asc"}}]),
             size: 1000,
             source_fields: None,
+            source_excludes: Vec::new(),
             search_after: None,
-            query_range: None,
-            query_match: None,
+            query: BoolQuery::default(),
+            query_strings: Vec::new(),
             use_pit: false,
+            fields_api: false,
+            docvalue_fields: None,
+            aggs: None,
+            terminate_after: None,
         }
     }
 }
@@ -47,105 +108,385 @@ impl SearchQueryBuilder {
         self
     }
 
+    // Leave these fields out of _source, the others being kept
+    pub fn with_source_excludes(mut self, fields: Vec<String>) -> Self {
+        self.source_excludes = fields;
+        self
+    }
+
+    // Retrieve values through the fields API instead of _source
+    pub fn with_fields_api(mut self, fields_api: bool) -> Self {
+        self.fields_api = fields_api;
+        self
+    }
+
+    // Read values from doc values instead of loading _source
+    pub fn with_docvalue_fields(mut self, fields: Option<Vec<String>>) -> Self {
+        self.docvalue_fields = fields;
+        self
+    }
+
     pub fn with_search_after(mut self, search_after: Value) -> Self {
         self.search_after = Some(search_after);
         self
     }
 
-    pub fn with_query_match(mut self, query_match: Option<Value>) -> Self {
-        self.query_match = query_match;
+    pub fn with_query_match(self, query_match: Option<Value>) -> Self {
+        match query_match {
+            Some(query_match) => self.with_query(|query| query.must(query_match)),
+            None => self,
+        }
+    }
+
+    // Add clauses to the bool query, next to the where and time range clauses
+    pub fn with_query(mut self, compose: impl FnOnce(&mut BoolQuery)) -> Self {
+        compose(&mut self.query);
+        self
+    }
+
+    // Lucene syntax, e.g. from -q or a local alias, ANDed with the other clauses
+    pub fn with_query_string(mut self, query_string: Option<&str>) -> Self {
+        self.query_strings.extend(query_string.map(str::to_string));
         self
     }
 
     pub fn with_time_range(
-        mut self,
+        self,
         from: Option<&str>,
         to: Option<&str>,
         latency: &str,
     ) -> Result<Self, ESQError> {
-        let mut range = json!({
-            "@timestamp": {}
-        });
-
-        if let Some(from_str) = from {
-            if let Ok(from_dt) = parse(from_str) {
-                range["@timestamp"]["gte"] = json!(from_dt.to_rfc3339());
-            } else {
-                return Err(ESQError::DateParseError(format!(
-                    "Invalid from date: {}",
-                    from_str
-                )));
-            }
-        }
-
-        if let Some(to_str) = to {
-            if let Ok(to_dt) = parse(to_str) {
-                range["@timestamp"]["lt"] = json!(to_dt.to_rfc3339());
-            } else {
-                return Err(ESQError::DateParseError(format!(
-                    "Invalid to date: {}",
-                    to_str
-                )));
-            }
-        } else {
-            range["@timestamp"]["lt"] = json!(format!("now-{}", latency));
-        }
+        let range = time_range(from, to, latency)?;
+        Ok(self.with_query(|query| query.filter(range)))
+    }
 
-        self.query_range = Some(json!({
-            "range": range
-        }));
+    // Aggregations keyed by name, combine with with_size(0) when the hits are not needed
+    pub fn with_aggs(mut self, aggs: Value) -> Self {
+        self.aggs = Some(aggs);
+        self
+    }
 
-        Ok(self)
+    // Let each shard stop collecting after this many documents
+    pub fn with_terminate_after(mut self, terminate_after: Option<u32>) -> Self {
+        self.terminate_after = terminate_after;
+        self
     }
 
     pub fn with_pit(mut self, use_pit: bool) -> Self {
         self.use_pit = use_pit;
-        if use_pit {
+        // The PIT pages in time order unless a sort was given before
+        if use_pit && self.sort_order.is_null() {
             self.sort_order =
-                json!([{"@timestamp": {"order": "asc"}}, {"_shard_doc": {"order": "asc"}}]);
+                json!([{time_field(): {"order": "asc"}}, {"_shard_doc": {"order": "asc"}}]);
         }
         self
     }
 
     pub fn build(self) -> Value {
+        // Scores are only worth computing when they decide the order of the hits
+        let scored = self.size > 0 && sorts_by_score(&self.sort_order);
         let mut query = json!({
-            "sort": self.sort_order,
             "size": self.size,
         });
 
-        if let Some(fields) = self.source_fields {
+        // Without hits there is nothing to sort or page through
+        if self.size > 0 {
+            query["sort"] = self.sort_order;
+            if let Some(search_after) = self.search_after {
+                query["search_after"] = search_after;
+            }
+        }
+
+        if self.fields_api {
+            query["_source"] = json!(false);
+            query["fields"] = json!(self.source_fields.unwrap_or_else(|| vec!["*".to_string()]));
+        } else if let Some(fields) = self.docvalue_fields {
+            query["_source"] = json!(false);
+            query["docvalue_fields"] = json!(fields);
+        } else if let Some(fields) = self.source_fields {
             if fields.is_empty() {
                 query["_source"] = json!(false);
-            } else {
+            } else if self.source_excludes.is_empty() {
                 query["_source"] = json!(fields);
+            } else {
+                query["_source"] = json!({"includes": fields, "excludes": self.source_excludes});
             }
+        } else if !self.source_excludes.is_empty() {
+            query["_source"] = json!({"excludes": self.source_excludes});
         }
 
-        if let Some(search_after) = self.search_after {
-            query["search_after"] = search_after;
+        if let Some(aggs) = self.aggs {
+            query["aggs"] = aggs;
         }
 
-        // Combine query_range and query_match if both are present
-        match (self.query_range, self.query_match) {
-            (Some(range), Some(match_query)) => {
-                query["query"] = json!({
-                    "bool": {
-                        "must": [
-                            range,
-                            match_query
-                        ]
-                    }
-                });
-            }
-            (Some(range), None) => {
-                query["query"] = range;
-            }
-            (None, Some(match_query)) => {
-                query["query"] = match_query;
-            }
-            (None, None) => {}
+        if let Some(terminate_after) = self.terminate_after {
+            query["terminate_after"] = json!(terminate_after);
+        }
+
+        // Must clauses, so that hits sorted by _score are ranked by the query string
+        let mut bool_query = self.query;
+        for query_string in self.query_strings {
+            bool_query.must(json!({"query_string": {"query": query_string}}));
+        }
+        let bool_query = if scored {
+            bool_query
+        } else {
+            bool_query.into_filter_context()
+        };
+        if let Some(bool_query) = bool_query.build() {
+            query["query"] = bool_query;
         }
 
         query
     }
 }
+
+fn sorts_by_score(sort_order: &Value) -> bool {
+    sort_order.as_array().is_some_and(|sorts| {
+        sorts.iter().any(|sort| match sort {
+            Value::String(field) => field == "_score",
+            Value::Object(sort) => sort.contains_key("_score"),
+            _ => false,
+        })
+    })
+}
+
+pub fn time_range(from: Option<&str>, to: Option<&str>, latency: &str) -> Result<Value, ESQError> {
+    let field = time_field();
+    let mut range = json!({
+        field: {}
+    });
+
+    if let Some(from_str) = from {
+        if let Ok(from_dt) = parse_datetime(from_str) {
+            range[field]["gte"] = json!(from_dt.to_rfc3339());
+        } else {
+            return Err(ESQError::DateParseError(format!(
+                "Invalid from date: {}",
+                from_str
+            )));
+        }
+    }
+
+    if let Some(to_str) = to {
+        if let Ok(to_dt) = parse_datetime(to_str) {
+            range[field]["lt"] = json!(to_dt.to_rfc3339());
+        } else {
+            return Err(ESQError::DateParseError(format!(
+                "Invalid to date: {}",
+                to_str
+            )));
+        }
+    } else if latency.is_empty() {
+        range[field]["lt"] = json!("now");
+    } else {
+        range[field]["lt"] = json!(format!("now-{}", latency));
+    }
+
+    Ok(json!({
+        "range": range
+    }))
+}
+
+// Bucket documents by the most frequent values of a field
+pub fn terms_agg(field: &str, size: u32) -> Value {
+    json!({"terms": {"field": field, "size": size}})
+}
+
+// One page of the values of a field, in key order, resumed after the after_key of the previous page
+pub fn composite_agg(field: &str, size: u32, after: Option<&Value>) -> Value {
+    let mut composite = json!({
+        "size": size,
+        "sources": [{"value": {"terms": {"field": field}}}]
+    });
+    if let Some(after) = after {
+        composite["after"] = after.clone();
+    }
+    json!({"composite": composite})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_source_fields() {
+        let query = SearchQueryBuilder::new()
+            .with_source_fields(Some(vec!["message".to_string()]))
+            .build();
+        assert_eq!(query["_source"], json!(["message"]));
+        assert!(query.get("fields").is_none());
+    }
+
+    #[test]
+    fn test_build_source_excludes() {
+        let excludes = vec!["stack_trace".to_string()];
+        let query = SearchQueryBuilder::new()
+            .with_source_excludes(excludes.clone())
+            .build();
+        assert_eq!(query["_source"], json!({"excludes": ["stack_trace"]}));
+        let query = SearchQueryBuilder::new()
+            .with_source_fields(Some(vec!["error".to_string()]))
+            .with_source_excludes(excludes)
+            .build();
+        assert_eq!(
+            query["_source"],
+            json!({"includes": ["error"], "excludes": ["stack_trace"]})
+        );
+    }
+
+    #[test]
+    fn test_build_fields_api() {
+        let query = SearchQueryBuilder::new()
+            .with_source_fields(Some(vec!["message".to_string()]))
+            .with_fields_api(true)
+            .build();
+        assert_eq!(query["_source"], json!(false));
+        assert_eq!(query["fields"], json!(["message"]));
+    }
+
+    #[test]
+    fn test_build_docvalue_fields() {
+        let query = SearchQueryBuilder::new()
+            .with_source_fields(Some(vec!["status".to_string()]))
+            .with_docvalue_fields(Some(vec!["status".to_string()]))
+            .build();
+        assert_eq!(query["_source"], json!(false));
+        assert_eq!(query["docvalue_fields"], json!(["status"]));
+    }
+
+    #[test]
+    fn test_build_fields_api_all_fields() {
+        let query = SearchQueryBuilder::new().with_fields_api(true).build();
+        assert_eq!(query["fields"], json!(["*"]));
+    }
+
+    #[test]
+    fn test_build_aggs_without_hits() {
+        let query = SearchQueryBuilder::new()
+            .with_size(0)
+            .with_search_after(json!([1]))
+            .with_aggs(json!({"levels": terms_agg("level", 5)}))
+            .build();
+        assert_eq!(query["size"], json!(0));
+        assert!(query.get("sort").is_none());
+        assert!(query.get("search_after").is_none());
+        assert_eq!(
+            query["aggs"],
+            json!({"levels": {"terms": {"field": "level", "size": 5}}})
+        );
+    }
+
+    #[test]
+    fn test_aggregation_helpers() {
+        assert_eq!(
+            composite_agg("level", 100, Some(&json!({"value": "INFO"})))["composite"],
+            json!({
+                "size": 100,
+                "sources": [{"value": {"terms": {"field": "level"}}}],
+                "after": {"value": "INFO"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_build_single_clause_unwrapped() {
+        let query = SearchQueryBuilder::new()
+            .with_sort_order(json!(["_score"]))
+            .with_query_match(Some(json!({"match": {"level": "ERROR"}})))
+            .build();
+        assert_eq!(query["query"], json!({"match": {"level": "ERROR"}}));
+    }
+
+    #[test]
+    fn test_build_filter_context() {
+        let query = SearchQueryBuilder::new()
+            .with_query_match(Some(json!({"match": {"level": "ERROR"}})))
+            .with_time_range(None, Some("2024-06-01T00:00:00Z"), "1m")
+            .unwrap()
+            .build();
+        assert_eq!(
+            query["query"],
+            json!({
+                "bool": {
+                    "filter": [
+                        {"range": {"@timestamp": {"lt": "2024-06-01T00:00:00+00:00"}}},
+                        {"match": {"level": "ERROR"}}
+                    ]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_build_scored_match_stays_in_must() {
+        let query = SearchQueryBuilder::new()
+            .with_sort_order(json!([{"_score": {"order": "desc"}}]))
+            .with_query_match(Some(json!({"match": {"message": "timeout"}})))
+            .with_time_range(None, None, "1m")
+            .unwrap()
+            .build();
+        assert_eq!(
+            query["query"]["bool"]["must"],
+            json!([{"match": {"message": "timeout"}}])
+        );
+        assert_eq!(
+            query["query"]["bool"]["filter"][0]["range"]["@timestamp"]["lt"],
+            json!("now-1m")
+        );
+    }
+
+    #[test]
+    fn test_build_query_strings() {
+        let query = SearchQueryBuilder::new()
+            .with_query_string(Some("status:[500 TO 599]"))
+            .with_query_string(None)
+            .build();
+        assert_eq!(
+            query["query"],
+            json!({"bool": {"filter": [{"query_string": {"query": "status:[500 TO 599]"}}]}})
+        );
+
+        let query = SearchQueryBuilder::new()
+            .with_query_match(Some(json!({"match": {"level": "ERROR"}})))
+            .with_query_string(Some("service:checkout"))
+            .with_query_string(Some("NOT method:OPTIONS"))
+            .build();
+        assert_eq!(
+            query["query"]["bool"]["filter"],
+            json!([
+                {"match": {"level": "ERROR"}},
+                {"query_string": {"query": "service:checkout"}},
+                {"query_string": {"query": "NOT method:OPTIONS"}}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_build_composed_bool_query() {
+        let query = SearchQueryBuilder::new()
+            .with_sort_order(json!(["_score"]))
+            .with_query_match(Some(json!({"match": {"level": "ERROR"}})))
+            .with_query(|query| {
+                query.must_not(json!({"match": {"service": "noisy"}}));
+                query.filter(json!({"exists": {"field": "trace.id"}}));
+            })
+            .build();
+        assert_eq!(
+            query["query"],
+            json!({
+                "bool": {
+                    "must": [{"match": {"level": "ERROR"}}],
+                    "must_not": [{"match": {"service": "noisy"}}],
+                    "filter": [{"exists": {"field": "trace.id"}}]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_build_without_clauses() {
+        let query = SearchQueryBuilder::new().build();
+        assert!(query.get("query").is_none());
+    }
+}
@@ -1,12 +1,71 @@
+use crate::elasticsearch::model::SearchResponse;
+use crate::elasticsearch::transport::SearchTransport;
+use crate::redact::redact;
+use crate::session::SessionRecorder;
+use crate::usage;
 use crate::utils::*;
+use reqwest::blocking::Response;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderValue};
 use serde_json::Value;
 use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+const ASYNC_POLL_INTERVAL: &str = "2s";
+const OPAQUE_ID_HEADER: &str = "x-opaque-id";
+const ASYNC_KEEP_ALIVE: &str = "5m";
+// REST API major version the requests of esq are written against
+const API_VERSION: u64 = 8;
+// Compatibility headers of the clusters asked this run, by configured URL
+static DETECTED_COMPATIBILITY: Mutex<BTreeMap<String, HeaderMap>> = Mutex::new(BTreeMap::new());
 
 pub struct ElasticsearchClient {
     client: reqwest::blocking::Client,
     config: Config,
     index: Option<String>,
     pit_id: Option<String>,
+    recorder: Option<Rc<SessionRecorder>>,
+    allow_partial_results: Option<bool>,
+    ignore_throttled: Option<bool>,
+    async_search: bool,
+    // REST compatibility headers matching the server version, empty when it has none. Asked
+    // on the first request, once per cluster and run
+    compatibility: OnceCell<HeaderMap>,
+    // Node of the configuration requests go to, the next one after a connection error
+    node: Cell<usize>,
+    // Data nodes found by sniffing, searches take turns on them, unreachable ones are dropped
+    data_nodes: RefCell<Vec<String>>,
+    next_data_node: Cell<usize>,
+    // Shard failures already warned about, a followed search would repeat them every batch
+    reported_failures: RefCell<HashSet<String>>,
+    // Fingerprint of the last certificate presented, to explain a connection refused by the pin
+    presented_certificate: Arc<Mutex<Option<String>>>,
+}
+
+// What it takes to open an equivalent client on another thread, the session recorder aside
+#[derive(Clone)]
+pub struct ClientSettings {
+    config: Config,
+    index: Option<String>,
+    allow_partial_results: Option<bool>,
+    ignore_throttled: Option<bool>,
+    async_search: bool,
+}
+
+impl ClientSettings {
+    pub fn connect(self) -> Result<ElasticsearchClient, ESQError> {
+        let mut es = ElasticsearchClient::new(self.config)?;
+        es.index = self.index;
+        es.allow_partial_results = self.allow_partial_results;
+        es.ignore_throttled = self.ignore_throttled;
+        es.async_search = self.async_search;
+        Ok(es)
+    }
 }
 
 impl Drop for ElasticsearchClient {
@@ -19,30 +78,285 @@ impl Drop for ElasticsearchClient {
 
 impl ElasticsearchClient {
     pub fn new(config: Config) -> Result<Self, ESQError> {
-        let client = reqwest::blocking::Client::builder().build()?;
-        Ok(Self {
+        // Tags every request so the tasks of this run can be found and cancelled
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            OPAQUE_ID_HEADER,
+            reqwest::header::HeaderValue::from_str(&opaque_id())
+                .map_err(|e| ESQError::ConfigError(e.to_string()))?,
+        );
+        let mut builder = reqwest::blocking::Client::builder()
+            .user_agent(concat!("esq/", env!("CARGO_PKG_VERSION")))
+            .default_headers(headers);
+        let presented_certificate = Arc::new(Mutex::new(None));
+        if let Some(pinned) = &config.default.tls_fingerprint {
+            // Every node is held to the pin, there is no unpinned way around it
+            if let Some(url) = config
+                .default
+                .node_urls()
+                .into_iter()
+                .find(|url| !url.starts_with("https://"))
+            {
+                return Err(ESQError::ConfigError(format!(
+                    "tls_fingerprint is set but {} is not an https URL",
+                    redact(url)
+                )));
+            }
+            builder = builder.use_preconfigured_tls(fingerprint_tls(
+                Some(pinned.clone()),
+                presented_certificate.clone(),
+            ));
+        }
+        let client = builder.build()?;
+        let mut es = Self {
             client,
             config,
             index: None,
             pit_id: None,
+            recorder: None,
+            allow_partial_results: None,
+            ignore_throttled: None,
+            async_search: false,
+            compatibility: OnceCell::new(),
+            node: Cell::new(0),
+            data_nodes: RefCell::new(Vec::new()),
+            next_data_node: Cell::new(0),
+            reported_failures: RefCell::new(HashSet::new()),
+            presented_certificate,
+        };
+        if es.config.default.sniff {
+            match es.sniff() {
+                Ok(nodes) => es.data_nodes = RefCell::new(nodes),
+                Err(e) => eprintln!(
+                    "Warning: could not sniff the cluster nodes, searching through {}: {}",
+                    redact(es.base_url()),
+                    e
+                ),
+            }
+        }
+        Ok(es)
+    }
+
+    // HTTP addresses of the data nodes, reached with the scheme of the configured URL
+    fn sniff(&self) -> Result<Vec<String>, ESQError> {
+        let response = self.send(self.authorized(self.client.get(self.url("_nodes/http"))))?;
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to fetch node addresses. Status code: {}",
+                response.status()
+            )));
+        }
+        let nodes: Value = read_json(response, "node addresses")?;
+        let scheme = reqwest::Url::parse(self.base_url())
+            .map(|url| url.scheme().to_string())
+            .unwrap_or_else(|_| "http".to_string());
+        Ok(data_node_urls(&nodes, &scheme))
+    }
+
+    // The error of a connection the pinned fingerprint refused, None for any other failure
+    fn pin_refusal(&self) -> Option<ESQError> {
+        let pinned = self.config.default.tls_fingerprint.as_deref()?;
+        let presented = self.presented_certificate.lock().ok()?.clone()?;
+        (!same_fingerprint(&presented, pinned)).then(|| {
+            ESQError::NetworkError(format!(
+                "The certificate of {} changed, its SHA-256 fingerprint is {} instead of the pinned {}. Run esq login to pin it if the change is expected",
+                redact(self.base_url()),
+                presented,
+                pinned
+            ))
+        })
+    }
+
+    fn server_info(&self) -> Result<Value, ESQError> {
+        let url = self.url("");
+        let response = self.send(add_auth(self.client.get(&url), &self.config))?;
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to fetch server information. Status code: {}",
+                response.status()
+            )));
+        }
+        read_json(response, "server information")
+    }
+
+    fn base_url(&self) -> &str {
+        self.config.default.node_urls()[self.node.get()]
+    }
+
+    fn url(&self, path: &str) -> String {
+        endpoint_url(self.base_url(), path)
+    }
+
+    // Fails over to the other nodes of the configuration, in order, when one cannot be
+    // reached, so that a node restart does not end a long follow
+    fn send(&self, request: reqwest::blocking::RequestBuilder) -> Result<Response, ESQError> {
+        let mut request = request.build()?;
+        let mut failovers = self.config.default.node_urls().len() - 1;
+        loop {
+            let Some(attempt) = request.try_clone() else {
+                break;
+            };
+            let response = self.client.execute(attempt);
+            if response.is_err()
+                && let Some(e) = self.pin_refusal()
+            {
+                return Err(e);
+            }
+            match response {
+                Err(e) if e.is_connect() && !interrupted() => {
+                    let Some((from, to)) = self.next_node(request.url(), &mut failovers) else {
+                        return Err(e.into());
+                    };
+                    *request.url_mut() = rebase(request.url(), &from, &to)?;
+                }
+                response => return Ok(response?),
+            }
+        }
+        let response = self.client.execute(request);
+        if response.is_err()
+            && let Some(e) = self.pin_refusal()
+        {
+            return Err(e);
+        }
+        Ok(response?)
+    }
+
+    // Where a request that could not connect goes instead, None once every node was tried. A
+    // sniffed data node is left out of the searches for the next one, or the configured node
+    // when none is left
+    fn next_node(&self, url: &reqwest::Url, failovers: &mut usize) -> Option<(String, String)> {
+        let mut data_nodes = self.data_nodes.borrow_mut();
+        if let Some(position) = data_nodes
+            .iter()
+            .position(|node| url.as_str().starts_with(&format!("{}/", node)))
+        {
+            let from = data_nodes.remove(position);
+            let to = match data_nodes.len() {
+                0 => self.base_url().to_string(),
+                len => data_nodes[position % len].clone(),
+            };
+            eprintln!(
+                "Warning: data node {} is unreachable, searching without it through {}",
+                redact(&from),
+                redact(&to)
+            );
+            return Some((from, to));
+        }
+        if *failovers == 0 {
+            return None;
+        }
+        *failovers -= 1;
+        let nodes = self.config.default.node_urls();
+        let (from, to) = (self.node.get(), (self.node.get() + 1) % nodes.len());
+        eprintln!(
+            "Warning: {} is unreachable, failing over to {}",
+            redact(nodes[from]),
+            redact(nodes[to])
+        );
+        self.node.set(to);
+        Some((nodes[from].to_string(), nodes[to].to_string()))
+    }
+
+    // Credentials and compatibility headers, replacing the Content-Type set by .json()
+    fn authorized(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::blocking::RequestBuilder {
+        add_auth(request, &self.config).headers(self.compatibility().clone())
+    }
+
+    // The clients of other threads, lookups and alias targets of the same cluster share the
+    // answer. Without a version the requests go out plain, the first one reports the error
+    fn compatibility(&self) -> &HeaderMap {
+        self.compatibility.get_or_init(|| {
+            let url = &self.config.default.url;
+            if let Some(headers) = DETECTED_COMPATIBILITY
+                .lock()
+                .ok()
+                .and_then(|detected| detected.get(url).cloned())
+            {
+                return headers;
+            }
+            let Ok(info) = self.server_info() else {
+                return HeaderMap::new();
+            };
+            let headers = compatible_version(&info)
+                .map(compatibility_headers)
+                .unwrap_or_default();
+            if let Ok(mut detected) = DETECTED_COMPATIBILITY.lock() {
+                detected.insert(url.clone(), headers.clone());
+            }
+            headers
         })
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn settings(&self) -> ClientSettings {
+        ClientSettings {
+            config: self.config.clone(),
+            index: self.index.clone(),
+            allow_partial_results: self.allow_partial_results,
+            ignore_throttled: self.ignore_throttled,
+            async_search: self.async_search,
+        }
+    }
+
+    // Check that the configured URL answers like an Elasticsearch instance,
+    // Ok(false) meaning the credentials were refused
+    pub fn test_connection(&self) -> Result<bool, ESQError> {
+        let url = self.url("_cat");
+
+        let response = self.send(add_auth(self.client.get(&url), &self.config))?;
+        if !response.status().is_success() {
+            return Ok(false);
+        }
+
+        let text = response.text()?;
+        if !text.contains("/_cat/") {
+            return Err(ESQError::ConfigError(
+                "The server doesn't appear to be an Elasticsearch instance".to_string(),
+            ));
+        }
+
+        Ok(true)
+    }
+
     pub fn set_index(&mut self, index: &str) {
         self.index = Some(index.to_string());
     }
 
+    pub fn index(&self) -> Option<&str> {
+        self.index.as_deref()
+    }
+
+    pub fn set_recorder(&mut self, recorder: Rc<SessionRecorder>) {
+        self.recorder = Some(recorder);
+    }
+
+    // Override the cluster's search.default_allow_partial_results for searches
+    pub fn set_allow_partial_results(&mut self, allow: Option<bool>) {
+        self.allow_partial_results = allow;
+    }
+
+    // Run searches through _async_search, for queries slower than the gateway timeouts
+    pub fn set_async_search(&mut self, async_search: bool) {
+        self.async_search = async_search;
+    }
+
+    // Whether searches skip legacy frozen (search-throttled) indices, the cluster skips them by default
+    pub fn set_ignore_throttled(&mut self, ignore: Option<bool>) {
+        self.ignore_throttled = ignore;
+    }
+
     pub fn create_pit(&mut self) -> Result<(), ESQError> {
-        let pit_response = add_auth(
-            self.client.post(format!(
-                "{}/{}/_pit?keep_alive=1m",
-                self.config.default.url,
-                self.index.as_ref().unwrap()
-            )),
-            &self.config,
-        )
-        .send()?
-        .json::<Value>()?;
+        let pit_response = self.send(self.authorized(self.client.post(self.url(&format!(
+            "{}/_pit?keep_alive=1m",
+            self.index.as_ref().unwrap()
+        )))))?;
+        let pit_response: Value = read_json(pit_response, "PIT response")?;
 
         self.pit_id = Some(
             pit_response["id"]
@@ -55,19 +369,19 @@ impl ElasticsearchClient {
 
     pub fn delete_pit(&mut self) -> Result<(), ESQError> {
         if let Some(pit_id) = &self.pit_id {
-            add_auth(
-                self.client
-                    .delete(format!("{}/_pit", self.config.default.url))
-                    .json(&json!({"id": pit_id})),
-                &self.config,
-            )
-            .send()?;
+            self.send(
+                self.authorized(
+                    self.client
+                        .delete(self.url("_pit"))
+                        .json(&json!({"id": pit_id})),
+                ),
+            )?;
             self.pit_id = None;
         }
         Ok(())
     }
 
-    pub fn search(&self, query: &Value) -> Result<Value, ESQError> {
+    pub fn search(&self, query: &Value) -> Result<SearchResponse, ESQError> {
         let mut final_query = query.clone();
 
         // Inject PIT if available
@@ -78,30 +392,313 @@ impl ElasticsearchClient {
             });
         }
 
-        let url = if self.pit_id.is_some() {
-            format!("{}/_search", self.config.default.url)
+        let mut params = Vec::new();
+        if let Some(allow) = self.allow_partial_results {
+            params.push(("allow_partial_search_results", allow.to_string()));
+        }
+        if let Some(ignore) = self.ignore_throttled {
+            params.push(("ignore_throttled", ignore.to_string()));
+        }
+
+        if self.async_search {
+            return self
+                .async_search(&final_query, params)
+                .inspect(count_documents)
+                .inspect(|response| self.warn_shard_failures(response));
+        }
+
+        let endpoint = self.search_endpoint("_search");
+        if let Some(recorder) = &self.recorder {
+            recorder.record_request(&endpoint, &final_query)?;
+        }
+
+        let url = self.search_url(&endpoint);
+        let (status, response) =
+            self.send_search(self.client.post(url).query(&params).json(&final_query))?;
+        if let Some(recorder) = &self.recorder {
+            recorder.record_response(status.as_u16())?;
+        }
+
+        parse_search_response(response)
+            .inspect(count_documents)
+            .inspect(|response| self.warn_shard_failures(response))
+    }
+
+    // Hits of a search some shards failed on are incomplete, which would go unnoticed otherwise
+    fn warn_shard_failures(&self, response: &SearchResponse) {
+        let shards = &response.shards;
+        if shards.failed == 0 {
+            return;
+        }
+        let mut reported = self.reported_failures.borrow_mut();
+        for failure in shards.descriptions() {
+            if reported.insert(failure.clone()) {
+                eprintln!(
+                    "Warning: {} of {} shards failed, results are incomplete: {}",
+                    shards.failed, shards.total, failure
+                );
+            }
+        }
+    }
+
+    // Submit the search to _async_search and long-poll it until the cluster is done
+    fn async_search(
+        &self,
+        query: &Value,
+        mut params: Vec<(&str, String)>,
+    ) -> Result<SearchResponse, ESQError> {
+        let endpoint = self.search_endpoint("_async_search");
+        if let Some(recorder) = &self.recorder {
+            recorder.record_request(&endpoint, query)?;
+        }
+
+        params.push((
+            "wait_for_completion_timeout",
+            ASYNC_POLL_INTERVAL.to_string(),
+        ));
+        params.push(("keep_on_completion", "true".to_string()));
+        params.push(("keep_alive", ASYNC_KEEP_ALIVE.to_string()));
+        let url = self.search_url(&endpoint);
+        let (mut status, mut response) =
+            self.send_search(self.client.post(url).query(&params).json(query))?;
+
+        let mut reported = false;
+        while response["is_running"].as_bool().unwrap_or(false) {
+            let id = response["id"]
+                .as_str()
+                .ok_or_else(|| ESQError::ESError("Invalid async search response".to_string()))?
+                .to_string();
+            let shards = &response["response"]["_shards"];
+            eprint!(
+                "\rAsync search running: {}/{} shards done",
+                shards["successful"].as_u64().unwrap_or(0),
+                shards["total"].as_u64().unwrap_or(0)
+            );
+            reported = true;
+
+            if interrupted() {
+                eprintln!();
+                self.delete_async_search(&id)?;
+                return Err(ESQError::Interrupted);
+            }
+
+            let url = self.url(&format!("_async_search/{}", id));
+            (status, response) = self.send_search(
+                self.client
+                    .get(url)
+                    .query(&[("wait_for_completion_timeout", ASYNC_POLL_INTERVAL)]),
+            )?;
+        }
+        if reported {
+            eprintln!();
+        }
+
+        if let Some(recorder) = &self.recorder {
+            recorder.record_response(status.as_u16())?;
+        }
+        if let Some(id) = response["id"].as_str() {
+            self.delete_async_search(id)?;
+        }
+        if response.get("error").is_some() {
+            return Err(ESQError::ESError(format!(
+                "Async search failed: {}",
+                response["error"]["reason"]
+                    .as_str()
+                    .unwrap_or("unknown error")
+            )));
+        }
+
+        parse_search_response(response["response"].clone())
+    }
+
+    // The next data node for a search, the configured node when none were sniffed
+    fn search_url(&self, endpoint: &str) -> String {
+        let data_nodes = self.data_nodes.borrow();
+        if data_nodes.is_empty() {
+            return self.url(endpoint);
+        }
+        let turn = self.next_data_node.get();
+        self.next_data_node.set(turn.wrapping_add(1));
+        endpoint_url(&data_nodes[turn % data_nodes.len()], endpoint)
+    }
+
+    fn delete_async_search(&self, id: &str) -> Result<(), ESQError> {
+        self.send(
+            self.authorized(
+                self.client
+                    .delete(self.url(&format!("_async_search/{}", id))),
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn search_endpoint(&self, api: &str) -> String {
+        if self.pit_id.is_some() {
+            format!("/{}", api)
         } else {
-            format!(
-                "{}/{}/_search",
-                self.config.default.url,
-                self.index.as_ref().unwrap()
-            )
+            format!("/{}/{}", self.index.as_ref().unwrap(), api)
+        }
+    }
+
+    // Send a search request, turning error statuses into the matching ESQError
+    fn send_search(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> Result<(reqwest::StatusCode, Value), ESQError> {
+        // Ctrl-C cannot interrupt the blocking call, cancel the search on the cluster instead
+        let client = self.client.clone();
+        let config = self.config.clone();
+        let base_url = self.base_url().to_string();
+        let compatibility = self.compatibility().clone();
+        let hook = add_interrupt_hook(Box::new(move || {
+            if let Err(e) = cancel_search_tasks(&client, &config, &base_url, &compatibility) {
+                eprintln!("Failed to cancel the running search: {}", e);
+            }
+        }));
+        let response = self.send(self.authorized(request));
+        drop(hook);
+        let response = match response {
+            Err(_) if interrupted() => return Err(ESQError::Interrupted),
+            response => response?,
         };
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(ESQError::AuthError);
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
 
-        let response = add_auth(self.client.post(url).json(&final_query), &self.config)
-            .send()?
-            .json::<Value>()?;
+        let body = response.bytes()?;
+        usage::record_bytes(body.len() as u64);
+        let response: Value =
+            parse_json(status, content_type.as_deref(), &body, "search response")?;
+        if let Err(e) = check_search_status(status, retry_after, &response) {
+            if let Some(recorder) = &self.recorder {
+                recorder.record_response(status.as_u16())?;
+            }
+            // The failure is most likely the cancellation requested on Ctrl-C
+            if interrupted() {
+                return Err(ESQError::Interrupted);
+            }
+            return Err(e);
+        }
+        Ok((status, response))
+    }
+
+    pub fn count(&self, query: Option<&Value>) -> Result<u64, ESQError> {
+        let url = self.url(&format!("{}/_count", self.index.as_ref().unwrap()));
+
+        let body = match query {
+            Some(query) => json!({"query": query}),
+            None => json!({}),
+        };
+
+        let response = self.send(self.authorized(self.client.post(&url).json(&body)))?;
+
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to count documents. Status code: {}",
+                response.status()
+            )));
+        }
 
-        Ok(response)
+        let count_response: Value = read_json(response, "count")?;
+
+        count_response["count"]
+            .as_u64()
+            .ok_or_else(|| ESQError::ESError("Invalid count response".to_string()))
     }
 
-    pub fn list_indices(&self) -> Result<Vec<Value>, ESQError> {
-        let url = format!(
-            "{}/_cat/indices?format=json",
-            self.config.default.url.trim_end_matches('/')
-        );
+    pub fn field_caps(&self, fields: &[String]) -> Result<Value, ESQError> {
+        let url = self.url(&format!(
+            "{}/_field_caps?fields={}",
+            self.index.as_ref().unwrap(),
+            fields.join(",")
+        ));
+
+        let response = self.send(self.authorized(self.client.get(&url)))?;
+
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to fetch field capabilities. Status code: {}",
+                response.status()
+            )));
+        }
+
+        read_json(response, "field caps")
+    }
+
+    // Tier preference and legacy frozen flag of every index behind the current target
+    pub fn tier_settings(&self) -> Result<Value, ESQError> {
+        let url = self.url(&format!(
+            "{}/_settings/index.routing.allocation.include._tier_preference,index.search.throttled?flat_settings=true",
+            self.index.as_ref().unwrap()
+        ));
+
+        let response = self.send(self.authorized(self.client.get(&url)))?;
+
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to fetch index settings. Status code: {}",
+                response.status()
+            )));
+        }
+
+        read_json(response, "index settings")
+    }
+
+    // Blocks set on the indices of the pattern, e.g. by the flood-stage disk watermark or by ILM
+    pub fn index_blocks(&self, pattern: &str) -> Result<Value, ESQError> {
+        let url = self.url(&format!(
+            "{}/_settings/index.blocks.*?flat_settings=true",
+            pattern
+        ));
+
+        let response = self.send(self.authorized(self.client.get(&url)))?;
+
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to fetch index blocks. Status code: {}",
+                response.status()
+            )));
+        }
+
+        read_json(response, "index settings")
+    }
+
+    pub fn index_stats(&self) -> Result<Value, ESQError> {
+        let url = self.url(&format!(
+            "{}/_stats/docs,store",
+            self.index.as_ref().unwrap()
+        ));
+
+        let response = self.send(self.authorized(self.client.get(&url)))?;
+
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to fetch index stats. Status code: {}",
+                response.status()
+            )));
+        }
+
+        read_json(response, "index stats")
+    }
+
+    // Names only, sorted by the server and read line by line, so that clusters with tens of
+    // thousands of indices neither send their stats nor wait for the whole body to be parsed
+    pub fn index_names(&self) -> Result<impl Iterator<Item = Result<String, ESQError>>, ESQError> {
+        let url = self.url("_cat/indices?h=index&s=index&format=txt");
 
-        let response = add_auth(self.client.get(&url), &self.config).send()?;
+        let response = self.send(self.authorized(self.client.get(&url)))?;
 
         if !response.status().is_success() {
             return Err(ESQError::NetworkError(format!(
@@ -110,8 +707,795 @@ impl ElasticsearchClient {
             )));
         }
 
-        response
-            .json()
-            .map_err(|e| ESQError::ParseError(format!("Failed to parse indices: {}", e)))
+        Ok(BufReader::new(response)
+            .lines()
+            .map(|line| Ok(line?.trim().to_string()))
+            .filter(|name| !matches!(name, Ok(name) if name.is_empty())))
+    }
+
+    // Whether every name of the target is an index, alias or data stream
+    pub fn exists(&self, target: &str) -> Result<bool, ESQError> {
+        let url = self.url(target);
+
+        let response = self.send(self.authorized(self.client.head(&url)))?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(ESQError::NetworkError(format!(
+                "Failed to check index existence. Status code: {}",
+                status
+            ))),
+        }
+    }
+
+    // Aliases matching the pattern per index, with their filters. Names of the pattern
+    // that are not aliases make the cluster answer 404 next to the aliases it found.
+    pub fn aliases(&self, pattern: &str) -> Result<Value, ESQError> {
+        let url = self.url(&format!("_alias/{}", pattern));
+
+        let response = self.send(self.authorized(self.client.get(&url)))?;
+
+        let status = response.status();
+        if !status.is_success() && status != reqwest::StatusCode::NOT_FOUND {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to fetch aliases. Status code: {}",
+                status
+            )));
+        }
+
+        read_json(response, "aliases")
+    }
+
+    pub fn latest_index(&self, pattern: &str) -> Result<String, ESQError> {
+        let url = self.url(&format!(
+            "_cat/indices/{}?format=json&h=index,creation.date&s=creation.date:desc",
+            pattern
+        ));
+
+        let response = self.send(self.authorized(self.client.get(&url)))?;
+
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to resolve index pattern '{}'. Status code: {}",
+                pattern,
+                response.status()
+            )));
+        }
+
+        let indices: Vec<Value> = read_json(response, "indices")?;
+
+        indices
+            .first()
+            .and_then(|index_data| index_data["index"].as_str())
+            .map(|index_name| index_name.to_string())
+            .ok_or_else(|| ESQError::ESError(format!("No index matches '{}'", pattern)))
+    }
+
+    // Elasticsearch skips indices whose time field min/max cannot satisfy the
+    // index_filter, so only indices that may hold matching documents are returned
+    pub fn indices_in_range(&self, pattern: &str, range: &Value) -> Result<Vec<String>, ESQError> {
+        let url = self.url(&format!("{}/_field_caps?fields={}", pattern, time_field()));
+
+        let response = self
+            .send(self.authorized(self.client.post(&url).json(&json!({"index_filter": range}))))?;
+
+        if !response.status().is_success() {
+            return Err(ESQError::NetworkError(format!(
+                "Failed to resolve indices in time range for '{}'. Status code: {}",
+                pattern,
+                response.status()
+            )));
+        }
+
+        let field_caps: Value = read_json(response, "field caps")?;
+
+        Ok(field_caps["indices"]
+            .as_array()
+            .map(|indices| {
+                indices
+                    .iter()
+                    .filter_map(|index| index.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+impl SearchTransport for ElasticsearchClient {
+    fn search(&self, query: &Value) -> Result<SearchResponse, ESQError> {
+        ElasticsearchClient::search(self, query)
+    }
+
+    fn refresh_pit(&mut self, pit_id: &str) {
+        if self.pit_id.is_some() {
+            self.pit_id = Some(pit_id.to_string());
+        }
+    }
+}
+
+// Longest part of an unexpected response body quoted in the error
+const BODY_SNIPPET_LEN: usize = 200;
+
+// Text of a body for an error message, HTML tags and repeated whitespace removed
+fn body_snippet(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in body.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > BODY_SNIPPET_LEN {
+        format!(
+            "{}…",
+            text.chars().take(BODY_SNIPPET_LEN).collect::<String>()
+        )
+    } else {
+        text
+    }
+}
+
+// Proxies and load balancers in front of the cluster may answer with their own HTML pages
+fn parse_json<T: serde::de::DeserializeOwned>(
+    status: reqwest::StatusCode,
+    content_type: Option<&str>,
+    body: &[u8],
+    what: &str,
+) -> Result<T, ESQError> {
+    let parsed = serde_json::from_slice(body);
+    match (parsed, content_type) {
+        (Ok(value), _) => Ok(value),
+        (Err(e), Some(content_type)) if content_type.contains("json") => Err(ESQError::ParseError(
+            format!("Failed to parse {}: {}", what, e),
+        )),
+        (Err(_), content_type) => Err(ESQError::NetworkError(format!(
+            "Expected JSON from Elasticsearch but got {} ({}): {}\nCheck the URL and any proxy or load balancer in front of the cluster",
+            status,
+            content_type.unwrap_or("no content type"),
+            body_snippet(body)
+        ))),
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(
+    response: reqwest::blocking::Response,
+    what: &str,
+) -> Result<T, ESQError> {
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response.bytes()?;
+    parse_json(status, content_type.as_deref(), &body, what)
+}
+
+// SHA-256 of the certificate a server presents, None over plain HTTP. The request carries
+// no credentials, so any certificate is accepted to be shown
+pub fn certificate_fingerprint(url: &str) -> Result<Option<String>, ESQError> {
+    if !url.starts_with("https://") {
+        return Ok(None);
+    }
+    let presented = Arc::new(Mutex::new(None));
+    reqwest::blocking::Client::builder()
+        .use_preconfigured_tls(fingerprint_tls(None, presented.clone()))
+        .build()?
+        .get(endpoint_url(url, ""))
+        .send()?;
+    Ok(presented
+        .lock()
+        .ok()
+        .and_then(|presented| presented.clone()))
+}
+
+// TLS of a client trusting certificates for their fingerprint rather than a CA
+fn fingerprint_tls(
+    pinned: Option<String>,
+    presented: Arc<Mutex<Option<String>>>,
+) -> rustls::ClientConfig {
+    rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(FingerprintVerifier { pinned, presented }))
+        .with_no_client_auth()
+}
+
+// Accepts the certificate whose SHA-256 fingerprint is pinned, whatever node presents it, or
+// any certificate when none is, and keeps the fingerprint of the last one presented
+struct FingerprintVerifier {
+    pinned: Option<String>,
+    presented: Arc<Mutex<Option<String>>>,
+}
+
+impl rustls::client::ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let fingerprint = format_fingerprint(&end_entity.0);
+        let trusted = self
+            .pinned
+            .as_deref()
+            .is_none_or(|pinned| same_fingerprint(pinned, &fingerprint));
+        if let Ok(mut presented) = self.presented.lock() {
+            *presented = Some(fingerprint);
+        }
+        if trusted {
+            Ok(rustls::client::ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+}
+
+// Colon-separated uppercase hex, as openssl x509 -fingerprint prints it
+fn format_fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// Fingerprints pasted from other tools may come without colons or in lowercase
+pub fn same_fingerprint(a: &str, b: &str) -> bool {
+    let normalize = |fingerprint: &str| fingerprint.replace(':', "").to_ascii_uppercase();
+    normalize(a) == normalize(b)
+}
+
+// The base URL may carry a path prefix, e.g. of a gateway routing https://gateway.corp/es/prod
+// to the cluster, that every API path goes under
+fn endpoint_url(base_url: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+// The publish address reads "host/ip:port" when the node has a publish host, which is kept
+// for certificates to match
+fn data_node_urls(nodes: &Value, scheme: &str) -> Vec<String> {
+    let mut urls: Vec<String> = nodes["nodes"]
+        .as_object()
+        .into_iter()
+        .flat_map(|nodes| nodes.values())
+        .filter(|node| {
+            node["roles"].as_array().is_some_and(|roles| {
+                roles
+                    .iter()
+                    .any(|role| role.as_str().is_some_and(|role| role.starts_with("data")))
+            })
+        })
+        .filter_map(|node| {
+            let address = node["http"]["publish_address"].as_str()?;
+            let address = match address.split_once('/') {
+                Some((host, ip)) if !host.is_empty() => {
+                    format!("{}:{}", host, ip.rsplit_once(':')?.1)
+                }
+                Some((_, ip)) => ip.to_string(),
+                None => address.to_string(),
+            };
+            Some(format!("{}://{}", scheme, address))
+        })
+        .collect();
+    urls.sort();
+    urls
+}
+
+// The same request sent to another node, under that node's path prefix
+fn rebase(url: &reqwest::Url, from: &str, to: &str) -> Result<reqwest::Url, ESQError> {
+    let parse = |node: &str| {
+        reqwest::Url::parse(node)
+            .map_err(|e| ESQError::ConfigError(format!("Invalid node URL '{}': {}", node, e)))
+    };
+    let from = parse(from)?;
+    let mut rebased = parse(to)?;
+    let path = url
+        .path()
+        .strip_prefix(from.path().trim_end_matches('/'))
+        .unwrap_or(url.path());
+    let path = endpoint_url(rebased.path(), path);
+    rebased.set_path(&path);
+    rebased.set_query(url.query());
+    Ok(rebased)
+}
+
+fn opaque_id() -> String {
+    format!("esq-{}", std::process::id())
+}
+
+// Cancel the top-level search tasks started by this process
+fn cancel_search_tasks(
+    client: &reqwest::blocking::Client,
+    config: &Config,
+    base_url: &str,
+    compatibility: &HeaderMap,
+) -> Result<(), ESQError> {
+    let tasks = add_auth(
+        client.get(endpoint_url(
+            base_url,
+            "_tasks?actions=*search*&detailed=true&group_by=none",
+        )),
+        config,
+    )
+    .headers(compatibility.clone())
+    .send()?;
+    let tasks: Value = read_json(tasks, "tasks")?;
+
+    for task_id in owned_tasks(&tasks, &opaque_id()) {
+        add_auth(
+            client.post(endpoint_url(
+                base_url,
+                &format!("_tasks/{}/_cancel", task_id),
+            )),
+            config,
+        )
+        .headers(compatibility.clone())
+        .send()?;
+    }
+    Ok(())
+}
+
+// IDs of the parent tasks tagged with the given X-Opaque-Id, cancelling them cancels their children
+fn owned_tasks(tasks: &Value, opaque_id: &str) -> Vec<String> {
+    tasks["tasks"]
+        .as_array()
+        .map(|tasks| {
+            tasks
+                .iter()
+                .filter(|task| task.get("parent_task_id").is_none())
+                .filter(|task| task["headers"]["X-Opaque-Id"].as_str() == Some(opaque_id))
+                .filter_map(|task| {
+                    Some(format!(
+                        "{}:{}",
+                        task["node"].as_str()?,
+                        task["id"].as_u64()?
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Compatibility level to request from the server described by GET /, None when it has no
+// REST compatibility (before 8, or OpenSearch). A server accepts its own major and the
+// previous one: esq asks for the API_VERSION it was written for while the server accepts it,
+// then for the oldest one the server still does, e.g. 9 from a 10.x server.
+fn compatible_version(info: &Value) -> Option<u64> {
+    if info["version"]["distribution"].as_str() == Some("opensearch") {
+        return None;
+    }
+    let major = info["version"]["number"]
+        .as_str()?
+        .split('.')
+        .next()?
+        .parse::<u64>()
+        .ok()?;
+    (major >= API_VERSION).then(|| API_VERSION.max(major - 1))
+}
+
+fn compatibility_headers(version: u64) -> HeaderMap {
+    let media_type = HeaderValue::from_str(&format!(
+        "application/vnd.elasticsearch+json; compatible-with={}",
+        version
+    ))
+    .expect("media type is a valid header value");
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, media_type.clone());
+    headers.insert(CONTENT_TYPE, media_type);
+    headers
+}
+
+fn check_search_status(
+    status: reqwest::StatusCode,
+    retry_after: Option<Duration>,
+    response: &Value,
+) -> Result<(), ESQError> {
+    // Both rejections are reported as 429, the error type tells them apart
+    match response["error"]["type"].as_str() {
+        Some("circuit_breaking_exception") => {
+            return Err(ESQError::CircuitBreaking(
+                response["error"]["reason"]
+                    .as_str()
+                    .unwrap_or("data too large")
+                    .to_string(),
+            ));
+        }
+        Some("es_rejected_execution_exception") => {
+            return Err(ESQError::TooManyRequests(retry_after));
+        }
+        // The reason names the block, e.g. "index [logs] blocked by: [FORBIDDEN/7/index read (api)];"
+        Some("cluster_block_exception") => {
+            return Err(ESQError::ESError(format!(
+                "Search refused by a block, {} Index blocks are the index.blocks.* settings of the index",
+                response["error"]["reason"]
+                    .as_str()
+                    .unwrap_or("index blocked;")
+            )));
+        }
+        _ if status == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            return Err(ESQError::TooManyRequests(retry_after));
+        }
+        _ => {}
+    }
+    if let Some(limit) = too_many_buckets(&response["error"]) {
+        return Err(ESQError::TooManyBuckets(limit));
+    }
+    if !status.is_success() {
+        return Err(ESQError::ESError(format!(
+            "Search failed with status {}: {}",
+            status,
+            response["error"]["reason"]
+                .as_str()
+                .unwrap_or("unknown error")
+        )));
+    }
+    Ok(())
+}
+
+// The bucket limit hit by an aggregation, reported at the top or among the causes of a search phase failure
+fn too_many_buckets(error: &Value) -> Option<Option<u64>> {
+    std::iter::successors(Some(error), |error| error.get("caused_by"))
+        .chain(error["root_cause"].as_array().into_iter().flatten())
+        .find(|cause| cause["type"] == "too_many_buckets_exception")
+        .map(|cause| cause["max_buckets"].as_u64())
+}
+
+// Hits received count towards the local usage stats
+fn count_documents(response: &SearchResponse) {
+    usage::record_documents(response.hits.hits.len() as u64);
+}
+
+fn parse_search_response(response: Value) -> Result<SearchResponse, ESQError> {
+    let response: SearchResponse = serde_json::from_value(response)?;
+    if response.shards.all_failed() {
+        return Err(ESQError::ESError(format!(
+            "Search failed on all {} shards: {}",
+            response.shards.total,
+            response.shards.reason()
+        )));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprints() {
+        let fingerprint = format_fingerprint(b"certificate");
+        assert_eq!(fingerprint.len(), 32 * 3 - 1);
+        assert!(fingerprint.starts_with("03:"));
+        assert!(same_fingerprint(
+            &fingerprint,
+            &fingerprint.replace(':', "").to_lowercase()
+        ));
+        assert!(!same_fingerprint(
+            &fingerprint,
+            &format_fingerprint(b"other")
+        ));
+    }
+
+    #[test]
+    fn test_fingerprint_verifier() {
+        use rustls::client::ServerCertVerifier;
+        let verify = |verifier: &FingerprintVerifier, der: &[u8]| {
+            verifier
+                .verify_server_cert(
+                    &rustls::Certificate(der.to_vec()),
+                    &[],
+                    &rustls::ServerName::try_from("es.internal").unwrap(),
+                    &mut std::iter::empty(),
+                    &[],
+                    SystemTime::now(),
+                )
+                .is_ok()
+        };
+        let presented = Arc::new(Mutex::new(None));
+        let pinned = FingerprintVerifier {
+            pinned: Some(format_fingerprint(b"certificate")),
+            presented: presented.clone(),
+        };
+        assert!(verify(&pinned, b"certificate"));
+        assert!(!verify(&pinned, b"other"));
+        assert_eq!(
+            presented.lock().unwrap().as_deref(),
+            Some(format_fingerprint(b"other").as_str())
+        );
+
+        let unpinned = FingerprintVerifier {
+            pinned: None,
+            presented: presented.clone(),
+        };
+        assert!(verify(&unpinned, b"anything"));
+        assert_eq!(
+            presented.lock().unwrap().as_deref(),
+            Some(format_fingerprint(b"anything").as_str())
+        );
+    }
+
+    #[test]
+    fn test_check_search_status() {
+        let ok = json!({"hits": {"hits": []}});
+        assert!(check_search_status(reqwest::StatusCode::OK, None, &ok).is_ok());
+
+        let breaker = json!({"error": {"type": "circuit_breaking_exception", "reason": "too big"}});
+        assert!(matches!(
+            check_search_status(reqwest::StatusCode::TOO_MANY_REQUESTS, None, &breaker),
+            Err(ESQError::CircuitBreaking(reason)) if reason == "too big"
+        ));
+
+        let rejected = json!({"error": {"type": "es_rejected_execution_exception"}});
+        assert!(matches!(
+            check_search_status(
+                reqwest::StatusCode::TOO_MANY_REQUESTS,
+                Some(Duration::from_secs(3)),
+                &rejected
+            ),
+            Err(ESQError::TooManyRequests(Some(delay))) if delay == Duration::from_secs(3)
+        ));
+
+        let buckets = json!({"error": {
+            "type": "search_phase_execution_exception",
+            "caused_by": {"type": "too_many_buckets_exception", "max_buckets": 65536}
+        }});
+        assert!(matches!(
+            check_search_status(reqwest::StatusCode::BAD_REQUEST, None, &buckets),
+            Err(ESQError::TooManyBuckets(Some(65536)))
+        ));
+
+        let failed = json!({"error": {"type": "search_phase_execution_exception", "reason": "all shards failed"}});
+        assert!(matches!(
+            check_search_status(reqwest::StatusCode::BAD_REQUEST, None, &failed),
+            Err(ESQError::ESError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_json_html_error_page() {
+        let page = b"<html><head><title>502 Bad Gateway</title></head>\n<body><center><h1>502 Bad Gateway</h1></center>\n<hr><center>nginx</center></body></html>";
+        let error = parse_json::<Value>(
+            reqwest::StatusCode::BAD_GATEWAY,
+            Some("text/html"),
+            page,
+            "search response",
+        )
+        .unwrap_err();
+        match error {
+            ESQError::NetworkError(message) => {
+                assert!(message.contains(
+                    "502 Bad Gateway (text/html): 502 Bad Gateway 502 Bad Gateway nginx"
+                ));
+                assert!(message.contains("Check the URL"));
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        // JSON that fails to parse is still a parse error
+        assert!(matches!(
+            parse_json::<Value>(
+                reqwest::StatusCode::OK,
+                Some("application/vnd.elasticsearch+json; compatible-with=8"),
+                b"{\"took\":",
+                "search response"
+            ),
+            Err(ESQError::ParseError(_))
+        ));
+        let value: Value = parse_json(
+            reqwest::StatusCode::OK,
+            None,
+            b"{\"took\":3}",
+            "search response",
+        )
+        .unwrap();
+        assert_eq!(value["took"], 3);
+    }
+
+    #[test]
+    fn test_body_snippet_truncates() {
+        let snippet = body_snippet("x".repeat(500).as_bytes());
+        assert_eq!(snippet.chars().count(), BODY_SNIPPET_LEN + 1);
+        assert!(snippet.ends_with('…'));
+    }
+
+    #[test]
+    fn test_endpoint_url() {
+        for base in ["http://localhost:9200", "http://localhost:9200/"] {
+            assert_eq!(endpoint_url(base, "_cat"), "http://localhost:9200/_cat");
+            assert_eq!(endpoint_url(base, ""), "http://localhost:9200/");
+        }
+        for base in [
+            "https://gateway.corp/es/prod",
+            "https://gateway.corp/es/prod//",
+        ] {
+            assert_eq!(
+                endpoint_url(base, "/logs-*/_search"),
+                "https://gateway.corp/es/prod/logs-*/_search"
+            );
+            assert_eq!(
+                endpoint_url(base, "_pit?keep_alive=1m"),
+                "https://gateway.corp/es/prod/_pit?keep_alive=1m"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rebase() {
+        let url = reqwest::Url::parse("http://es-1:9200/logs-*/_search?size=10").unwrap();
+        assert_eq!(
+            rebase(&url, "http://es-1:9200", "http://es-2:9201/")
+                .unwrap()
+                .as_str(),
+            "http://es-2:9201/logs-*/_search?size=10"
+        );
+        let url = reqwest::Url::parse("https://gateway.corp/es/prod/_pit").unwrap();
+        assert_eq!(
+            rebase(
+                &url,
+                "https://gateway.corp/es/prod/",
+                "https://backup.corp/es"
+            )
+            .unwrap()
+            .as_str(),
+            "https://backup.corp/es/_pit"
+        );
+    }
+
+    #[test]
+    fn test_send_fails_over_to_next_node() {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let node = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).unwrap();
+            let body = r#"{"version":{"number":"7.17.0"}}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8_lossy(&request[..read]).to_string()
+        });
+
+        // Nothing listens on port 1, the client moves on to the second node when connecting
+        let config = Config {
+            default: DefaultConfig {
+                url: "http://127.0.0.1:1".to_string(),
+                nodes: vec![node.clone()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let es = ElasticsearchClient::new(config).unwrap();
+        assert!(es.server_info().is_ok());
+        assert!(server.join().unwrap().starts_with("GET / HTTP/1.1"));
+        assert_eq!(es.base_url(), node);
+        assert_eq!(es.url("_cat"), format!("{}/_cat", node));
+    }
+
+    #[test]
+    fn test_send_drops_unreachable_data_node() {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let seed = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).unwrap();
+            let body = r#"{"hits":{"hits":[]}}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8_lossy(&request[..read]).to_string()
+        });
+
+        // A single seed and a sniffed data node nothing listens on
+        let config = Config {
+            default: DefaultConfig {
+                url: seed.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let es = ElasticsearchClient::new(config).unwrap();
+        *es.data_nodes.borrow_mut() = vec!["http://127.0.0.1:1".to_string()];
+        let url = es.search_url("_search");
+        assert_eq!(url, "http://127.0.0.1:1/_search");
+        let response = es.send(es.client.get(&url)).unwrap();
+        assert!(response.status().is_success());
+        assert!(server.join().unwrap().starts_with("GET /_search HTTP/1.1"));
+        assert!(es.data_nodes.borrow().is_empty());
+        assert_eq!(es.search_url("_search"), format!("{}/_search", seed));
+    }
+
+    #[test]
+    fn test_data_node_urls() {
+        let nodes = json!({"nodes": {
+            "a": {"roles": ["master"], "http": {"publish_address": "10.0.0.1:9200"}},
+            "b": {"roles": ["data_hot", "ingest"], "http": {"publish_address": "es-2.internal/10.0.0.2:9200"}},
+            "c": {"roles": ["data"], "http": {"publish_address": "10.0.0.3:9201"}},
+            "d": {"roles": ["data_cold"]}
+        }});
+        assert_eq!(
+            data_node_urls(&nodes, "https"),
+            vec!["https://10.0.0.3:9201", "https://es-2.internal:9200"]
+        );
+    }
+
+    #[test]
+    fn test_compatible_version() {
+        let info = |number: &str| json!({"version": {"number": number}});
+        assert_eq!(compatible_version(&info("7.17.21")), None);
+        assert_eq!(compatible_version(&info("8.13.4")), Some(8));
+        assert_eq!(compatible_version(&info("9.0.1")), Some(8));
+        assert_eq!(compatible_version(&info("10.1.0")), Some(9));
+        assert_eq!(
+            compatible_version(
+                &json!({"version": {"number": "2.11.0", "distribution": "opensearch"}})
+            ),
+            None
+        );
+        assert_eq!(compatible_version(&json!({})), None);
+    }
+
+    #[test]
+    fn test_compatibility_headers() {
+        let headers = compatibility_headers(8);
+        assert_eq!(
+            headers[ACCEPT],
+            "application/vnd.elasticsearch+json; compatible-with=8"
+        );
+        assert_eq!(headers[CONTENT_TYPE], headers[ACCEPT]);
+    }
+
+    #[test]
+    fn test_owned_tasks() {
+        let tasks = json!({
+            "tasks": [
+                {"node": "n1", "id": 10, "action": "indices:data/read/search",
+                 "headers": {"X-Opaque-Id": "esq-42"}},
+                {"node": "n1", "id": 11, "action": "indices:data/read/search[phase/query]",
+                 "parent_task_id": "n1:10", "headers": {"X-Opaque-Id": "esq-42"}},
+                {"node": "n2", "id": 7, "action": "indices:data/read/search",
+                 "headers": {"X-Opaque-Id": "someone-else"}}
+            ]
+        });
+        assert_eq!(owned_tasks(&tasks, "esq-42"), vec!["n1:10".to_string()]);
+        assert!(owned_tasks(&json!({}), "esq-42").is_empty());
+    }
+
+    #[test]
+    fn test_parse_search_response_all_shards_failed() {
+        let response = json!({
+            "_shards": {"total": 2, "successful": 0, "failed": 2},
+            "hits": {"hits": []}
+        });
+        assert!(parse_search_response(response).is_err());
     }
 }
@@ -1,2 +1,4 @@
 pub mod builder;
 pub mod client;
+pub mod model;
+pub mod transport;
@@ -0,0 +1,182 @@
+// src/elasticsearch/model.rs
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+static NULL: Value = Value::Null;
+
+// Body of a successful _search response, only the parts esq relies on
+#[derive(Deserialize, Debug, Clone)]
+pub struct SearchResponse {
+    #[serde(default)]
+    pub took: u64,
+    #[serde(default)]
+    pub timed_out: bool,
+    #[serde(default)]
+    pub terminated_early: bool,
+    #[serde(rename = "_shards", default)]
+    pub shards: ShardFailures,
+    pub hits: Hits,
+    #[serde(default)]
+    pub aggregations: Option<Value>,
+    // Set on searches through a PIT, the id to use from now on
+    #[serde(default)]
+    pub pit_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Hits {
+    #[serde(default)]
+    pub hits: Vec<Hit>,
+}
+
+// Serialized back to the same shape when hits are spooled to disk
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Hit {
+    #[serde(rename = "_index", default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "_source", default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<Vec<Value>>,
+}
+
+// The _shards section, with the per-shard failures when some shards could not answer
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ShardFailures {
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub successful: u64,
+    #[serde(default)]
+    pub failed: u64,
+    #[serde(default)]
+    pub failures: Vec<ShardFailure>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ShardFailure {
+    #[serde(default)]
+    pub index: Option<String>,
+    #[serde(default)]
+    pub shard: Option<u64>,
+    #[serde(default)]
+    pub reason: Value,
+}
+
+impl Hit {
+    // The document source, or null when it was not fetched
+    pub fn source(&self) -> &Value {
+        self.source.as_ref().unwrap_or(&NULL)
+    }
+}
+
+impl ShardFailures {
+    // Every shard failed, so the hits say nothing about the data
+    pub fn all_failed(&self) -> bool {
+        self.failed > 0 && self.successful == 0
+    }
+
+    // First failure reason, as reported by Elasticsearch
+    pub fn reason(&self) -> String {
+        self.failures
+            .first()
+            .map(ShardFailure::describe)
+            .unwrap_or_else(|| "unknown error".to_string())
+    }
+
+    // Every failure reported, Elasticsearch groups the shards failing for the same reason
+    pub fn descriptions(&self) -> Vec<String> {
+        if self.failures.is_empty() {
+            vec![self.reason()]
+        } else {
+            self.failures.iter().map(ShardFailure::describe).collect()
+        }
+    }
+}
+
+impl ShardFailure {
+    // index[shard]: reason, or the reason alone when the shard is not named
+    fn describe(&self) -> String {
+        let reason = self.reason["reason"]
+            .as_str()
+            .or_else(|| self.reason["type"].as_str())
+            .unwrap_or("unknown error");
+        match (&self.index, self.shard) {
+            (Some(index), Some(shard)) => format!("{}[{}]: {}", index, shard, reason),
+            _ => reason.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_deserialize_search_response() {
+        let response: SearchResponse = serde_json::from_value(json!({
+            "took": 12,
+            "timed_out": false,
+            "_shards": {"total": 2, "successful": 2, "skipped": 0, "failed": 0},
+            "hits": {
+                "hits": [
+                    {"_index": "logs", "_id": "x1", "_source": {"message": "hi"}, "sort": [1, 2]},
+                    {"_id": "x2", "fields": {"level": ["INFO"]}}
+                ]
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(response.took, 12);
+        assert_eq!(response.hits.hits.len(), 2);
+        assert_eq!(response.hits.hits[0].source()["message"], "hi");
+        assert_eq!(response.hits.hits[0].sort, Some(vec![json!(1), json!(2)]));
+        assert!(response.hits.hits[1].source().is_null());
+        assert!(!response.terminated_early);
+        assert_eq!(response.shards.failed, 0);
+    }
+
+    #[test]
+    fn test_missing_hits_is_an_error() {
+        assert!(serde_json::from_value::<SearchResponse>(json!({"took": 1})).is_err());
+    }
+
+    #[test]
+    fn test_shard_failure_reason() {
+        let response: SearchResponse = serde_json::from_value(json!({
+            "timed_out": true,
+            "_shards": {
+                "total": 3,
+                "successful": 2,
+                "failed": 1,
+                "failures": [{
+                    "index": "logs",
+                    "shard": 1,
+                    "reason": {"type": "query_shard_exception", "reason": "No mapping found"}
+                }, {
+                    "shard": 2,
+                    "reason": {"type": "node_disconnected_exception"}
+                }]
+            },
+            "hits": {"hits": []}
+        }))
+        .unwrap();
+
+        assert!(response.timed_out);
+        assert!(!response.shards.all_failed());
+        assert_eq!(response.shards.reason(), "logs[1]: No mapping found");
+        assert_eq!(
+            response.shards.descriptions(),
+            vec!["logs[1]: No mapping found", "node_disconnected_exception"]
+        );
+        assert_eq!(
+            ShardFailures::default().descriptions(),
+            vec!["unknown error"]
+        );
+    }
+}
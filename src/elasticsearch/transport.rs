@@ -0,0 +1,12 @@
+// src/elasticsearch/transport.rs
+use crate::elasticsearch::model::SearchResponse;
+use crate::utils::ESQError;
+use serde_json::Value;
+
+// What an extraction needs from the cluster, so its loop can run against a mock in tests
+pub trait SearchTransport {
+    fn search(&self, query: &Value) -> Result<SearchResponse, ESQError>;
+
+    // The cluster may hand back a new PIT id with each response, the next search must use it
+    fn refresh_pit(&mut self, pit_id: &str);
+}
@@ -0,0 +1,125 @@
+// src/enrich/anonymize.rs
+use crate::elasticsearch::model::Hit;
+use crate::enrich::Enricher;
+use crate::output::lookup_field_mut;
+use crate::utils::*;
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+// ECS fields holding IPs, user names and emails
+pub const DEFAULT_ANONYMIZED_FIELDS: &str = "source.ip,destination.ip,client.ip,server.ip,host.ip,\
+    related.ip,user.name,user.id,user.email,user.full_name,related.user,source.user.name,\
+    destination.user.name,email.from.address,email.to.address";
+
+// Replaces identifiers with salted hashes, equal values stay equal within a run
+pub struct Anonymizer {
+    // Randomly keyed per process, the salt never leaves memory
+    salt: RandomState,
+    fields: Vec<String>,
+}
+
+impl Anonymizer {
+    pub fn new(fields: &str) -> Self {
+        Self {
+            salt: RandomState::new(),
+            fields: fields
+                .split(',')
+                .map(|field| field.trim().to_string())
+                .filter(|field| !field.is_empty())
+                .collect(),
+        }
+    }
+
+    fn pseudonym(&self, value: &str) -> String {
+        format!("anon-{:016x}", self.salt.hash_one(value))
+    }
+
+    // Hash every scalar below the value, keeping the structure
+    fn anonymize(&self, value: &mut Value) {
+        match value {
+            Value::Null => {}
+            Value::String(text) => *value = Value::String(self.pseudonym(text)),
+            Value::Number(_) | Value::Bool(_) => {
+                *value = Value::String(self.pseudonym(&value.to_string()))
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.anonymize(value)),
+            Value::Object(object) => object.values_mut().for_each(|value| self.anonymize(value)),
+        }
+    }
+}
+
+impl Enricher for Anonymizer {
+    fn enrich(&mut self, hits: &mut [Hit]) -> Result<(), ESQError> {
+        for hit in hits {
+            let Some(source) = &mut hit.source else {
+                continue;
+            };
+            for field in &self.fields {
+                if let Some(value) = lookup_field_mut(source, field) {
+                    self.anonymize(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn source_fields(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn output_fields(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn anonymized(anonymizer: &mut Anonymizer, source: Value) -> Value {
+        let mut hits = vec![serde_json::from_value::<Hit>(json!({"_source": source})).unwrap()];
+        anonymizer.enrich(&mut hits).unwrap();
+        hits[0].source().clone()
+    }
+
+    #[test]
+    fn test_anonymize_is_consistent() {
+        let mut anonymizer = Anonymizer::new("source.ip, user.name");
+        let first = anonymized(
+            &mut anonymizer,
+            json!({"source": {"ip": "10.0.0.1"}, "user.name": "alice", "message": "hi"}),
+        );
+        let second = anonymized(
+            &mut anonymizer,
+            json!({"source": {"ip": "10.0.0.1"}, "user": {"name": "bob"}}),
+        );
+
+        let ip = first["source"]["ip"].as_str().unwrap();
+        assert!(ip.starts_with("anon-"));
+        assert_eq!(second["source"]["ip"], json!(ip));
+        assert_ne!(first["user.name"], second["user"]["name"]);
+        assert_eq!(first["message"], json!("hi"));
+    }
+
+    #[test]
+    fn test_anonymize_arrays_and_numbers() {
+        let mut anonymizer = Anonymizer::new("related.ip,user.id");
+        let source = anonymized(
+            &mut anonymizer,
+            json!({"related": {"ip": ["10.0.0.1", "10.0.0.2"]}, "user": {"id": 42}, "n": null}),
+        );
+        let ips = source["related"]["ip"].as_array().unwrap();
+        assert_eq!(ips.len(), 2);
+        assert_ne!(ips[0], ips[1]);
+        assert!(source["user"]["id"].as_str().unwrap().starts_with("anon-"));
+    }
+
+    #[test]
+    fn test_salt_differs_between_anonymizers() {
+        let first = Anonymizer::new("user.name");
+        let second = Anonymizer::new("user.name");
+        assert_ne!(first.pseudonym("alice"), second.pseudonym("alice"));
+    }
+}
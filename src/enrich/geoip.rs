@@ -0,0 +1,128 @@
+// src/enrich/geoip.rs
+use crate::elasticsearch::model::Hit;
+use crate::enrich::{Enricher, insert_field};
+use crate::output::lookup_field;
+use crate::utils::*;
+use maxminddb::{Reader, geoip2};
+use serde_json::{Map, Value, json};
+use std::net::IpAddr;
+use std::path::Path;
+
+// Adds ECS geo fields next to IP fields, source.ip gets source.geo
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+    fields: Vec<String>,
+}
+
+impl GeoIp {
+    pub fn open(database: &Path, fields: &[String]) -> Result<Self, ESQError> {
+        let reader = Reader::open_readfile(database).map_err(|e| {
+            ESQError::ConfigError(format!(
+                "Failed to open GeoIP database {}: {}",
+                database.display(),
+                e
+            ))
+        })?;
+        Ok(Self {
+            reader,
+            fields: fields.to_vec(),
+        })
+    }
+
+    fn lookup(&self, ip: IpAddr) -> Option<Map<String, Value>> {
+        let city: geoip2::City = self.reader.lookup(ip).ok()?.decode().ok()??;
+        let geo = geo_fields(&city);
+        (!geo.is_empty()).then_some(geo)
+    }
+}
+
+fn geo_target(field: &str) -> String {
+    match field.rsplit_once('.') {
+        Some((parent, _)) => format!("{}.geo", parent),
+        None => "geo".to_string(),
+    }
+}
+
+// City databases fill every field, country databases the country and continent only
+fn geo_fields(city: &geoip2::City) -> Map<String, Value> {
+    let mut geo = Map::new();
+    let mut set = |name: &str, value: Option<&str>| {
+        if let Some(value) = value {
+            geo.insert(name.to_string(), json!(value));
+        }
+    };
+    set("continent_name", city.continent.names.english);
+    set("country_iso_code", city.country.iso_code);
+    set("country_name", city.country.names.english);
+    set(
+        "region_name",
+        city.subdivisions
+            .first()
+            .and_then(|region| region.names.english),
+    );
+    set("city_name", city.city.names.english);
+    set("timezone", city.location.time_zone);
+    if let (Some(lat), Some(lon)) = (city.location.latitude, city.location.longitude) {
+        geo.insert("location".to_string(), json!({"lat": lat, "lon": lon}));
+    }
+    geo
+}
+
+impl Enricher for GeoIp {
+    fn enrich(&mut self, hits: &mut [Hit]) -> Result<(), ESQError> {
+        for hit in hits {
+            for field in &self.fields {
+                let Some(ip) = lookup_field(hit.source(), field)
+                    .and_then(Value::as_str)
+                    .and_then(|ip| ip.parse().ok())
+                else {
+                    continue;
+                };
+                if let Some(geo) = self.lookup(ip) {
+                    let source = hit.source.get_or_insert_with(|| json!({}));
+                    insert_field(source, &geo_target(field), Value::Object(geo));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn source_fields(&self) -> Vec<String> {
+        self.fields.clone()
+    }
+
+    fn output_fields(&self) -> Vec<String> {
+        self.fields.iter().map(|field| geo_target(field)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geo_target() {
+        assert_eq!(geo_target("source.ip"), "source.geo");
+        assert_eq!(geo_target("client_ip"), "geo");
+    }
+
+    #[test]
+    fn test_geo_fields() {
+        let mut city = geoip2::City::default();
+        city.country.iso_code = Some("SE");
+        city.country.names.english = Some("Sweden");
+        city.city.names.english = Some("Linköping");
+        city.location.latitude = Some(58.4167);
+        city.location.longitude = Some(15.6167);
+        assert_eq!(
+            Value::Object(geo_fields(&city)),
+            json!({
+                "country_iso_code": "SE",
+                "country_name": "Sweden",
+                "city_name": "Linköping",
+                "location": {"lat": 58.4167, "lon": 15.6167}
+            })
+        );
+        assert!(geo_fields(&geoip2::City::default()).is_empty());
+    }
+}
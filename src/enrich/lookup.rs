@@ -0,0 +1,208 @@
+// src/enrich/lookup.rs
+use crate::elasticsearch::client::ElasticsearchClient;
+use crate::elasticsearch::model::Hit;
+use crate::enrich::{Enricher, insert_field, key_value};
+use crate::output::lookup_field;
+use crate::utils::*;
+use serde_json::{Map, Value, json};
+use std::collections::{HashMap, HashSet};
+
+// Keys resolved per search against the lookup index
+const LOOKUP_BATCH_SIZE: usize = 500;
+
+// --enrich 'host.name->inventory:hostname:owner,rack'
+#[derive(Clone, Debug, PartialEq)]
+pub struct LookupSpec {
+    pub field: String,
+    pub index: String,
+    pub key: String,
+    pub fields: Vec<String>,
+}
+
+pub fn parse_lookup_spec(input: &str) -> Result<LookupSpec, String> {
+    let invalid = || {
+        format!(
+            "invalid enrichment '{}', expected field->index:key_field:field1,field2,..",
+            input
+        )
+    };
+    let (field, target) = input.split_once("->").ok_or_else(invalid)?;
+    let mut parts = target.splitn(3, ':');
+    let (Some(index), Some(key), Some(fields)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let fields: Vec<String> = fields
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    if field.trim().is_empty()
+        || index.trim().is_empty()
+        || key.trim().is_empty()
+        || fields.is_empty()
+    {
+        return Err(invalid());
+    }
+    Ok(LookupSpec {
+        field: field.trim().to_string(),
+        index: index.trim().to_string(),
+        key: key.trim().to_string(),
+        fields,
+    })
+}
+
+// Client-side join: merges fields of the lookup index document whose key matches a field of the hit
+pub struct IndexLookup {
+    es: ElasticsearchClient,
+    spec: LookupSpec,
+    // None for keys known to have no lookup document
+    cache: HashMap<String, Option<Map<String, Value>>>,
+}
+
+impl IndexLookup {
+    pub fn new(config: &Config, spec: &LookupSpec) -> Result<Self, ESQError> {
+        let mut es = ElasticsearchClient::new(config.clone())?;
+        es.set_index(&spec.index);
+        Ok(Self {
+            es,
+            spec: spec.clone(),
+            cache: HashMap::new(),
+        })
+    }
+
+    fn resolve(&mut self, keys: Vec<String>) -> Result<(), ESQError> {
+        for chunk in keys.chunks(LOOKUP_BATCH_SIZE) {
+            let response = self.es.search(&lookup_query(&self.spec, chunk))?;
+            let mut found =
+                lookup_documents(&self.spec, response.hits.hits.iter().map(Hit::source));
+            for key in chunk {
+                self.cache.insert(key.clone(), found.remove(key));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Lookup indices may hold several documents per key, collapsing keeps one per key so that a
+// key with many documents cannot crowd the others out of the hits
+fn lookup_query(spec: &LookupSpec, keys: &[String]) -> Value {
+    let mut source_fields = vec![spec.key.clone()];
+    source_fields.extend(spec.fields.iter().cloned());
+    json!({
+        "size": keys.len(),
+        "_source": source_fields,
+        "query": {"terms": {&spec.key: keys}},
+        "collapse": {"field": &spec.key}
+    })
+}
+
+// Selected fields of each lookup document, by key
+fn lookup_documents<'a>(
+    spec: &LookupSpec,
+    documents: impl Iterator<Item = &'a Value>,
+) -> HashMap<String, Map<String, Value>> {
+    let mut found = HashMap::new();
+    for document in documents {
+        let Some(key) = lookup_field(document, &spec.key).and_then(key_value) else {
+            continue;
+        };
+        found.entry(key).or_insert_with(|| {
+            spec.fields
+                .iter()
+                .filter_map(|field| Some((field.clone(), lookup_field(document, field)?.clone())))
+                .collect()
+        });
+    }
+    found
+}
+
+impl Enricher for IndexLookup {
+    fn enrich(&mut self, hits: &mut [Hit]) -> Result<(), ESQError> {
+        let keys: HashSet<String> = hits
+            .iter()
+            .filter_map(|hit| lookup_field(hit.source(), &self.spec.field).and_then(key_value))
+            .filter(|key| !self.cache.contains_key(key))
+            .collect();
+        if !keys.is_empty() {
+            self.resolve(keys.into_iter().collect())?;
+        }
+
+        for hit in hits {
+            let Some(key) = lookup_field(hit.source(), &self.spec.field).and_then(key_value) else {
+                continue;
+            };
+            if let Some(Some(fields)) = self.cache.get(&key) {
+                let source = hit.source.get_or_insert_with(|| json!({}));
+                for (field, value) in fields {
+                    insert_field(source, field, value.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn source_fields(&self) -> Vec<String> {
+        vec![self.spec.field.clone()]
+    }
+
+    fn output_fields(&self) -> Vec<String> {
+        self.spec.fields.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lookup_spec() {
+        assert_eq!(
+            parse_lookup_spec("host.name->inventory-index:hostname:owner, rack"),
+            Ok(LookupSpec {
+                field: "host.name".to_string(),
+                index: "inventory-index".to_string(),
+                key: "hostname".to_string(),
+                fields: vec!["owner".to_string(), "rack".to_string()],
+            })
+        );
+        assert!(parse_lookup_spec("host.name:inventory:hostname:owner").is_err());
+        assert!(parse_lookup_spec("host.name->inventory:hostname").is_err());
+        assert!(parse_lookup_spec("host.name->inventory:hostname:").is_err());
+    }
+
+    #[test]
+    fn test_lookup_query() {
+        let spec = parse_lookup_spec("host.name->inventory:hostname:owner").unwrap();
+        let keys = ["web-1".to_string(), "db-1".to_string()];
+        assert_eq!(
+            lookup_query(&spec, &keys),
+            json!({
+                "size": 2,
+                "_source": ["hostname", "owner"],
+                "query": {"terms": {"hostname": ["web-1", "db-1"]}},
+                "collapse": {"field": "hostname"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_lookup_documents() {
+        let spec = parse_lookup_spec("host.name->inventory:hostname:owner,rack").unwrap();
+        let documents = [
+            json!({"hostname": "web-1", "owner": "team-a", "rack": "r12"}),
+            json!({"hostname": "web-1", "owner": "team-b"}),
+            json!({"hostname": "db-1", "owner": "team-c"}),
+            json!({"owner": "nobody"}),
+        ];
+        let found = lookup_documents(&spec, documents.iter());
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            Value::Object(found["web-1"].clone()),
+            json!({"owner": "team-a", "rack": "r12"})
+        );
+        assert_eq!(
+            Value::Object(found["db-1"].clone()),
+            json!({"owner": "team-c"})
+        );
+    }
+}
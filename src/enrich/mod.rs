@@ -0,0 +1,173 @@
+// src/enrich/mod.rs
+pub mod anonymize;
+#[cfg(feature = "geoip")]
+pub mod geoip;
+pub mod lookup;
+#[cfg(feature = "useragent")]
+pub mod useragent;
+
+use crate::elasticsearch::model::Hit;
+use crate::enrich::anonymize::{Anonymizer, DEFAULT_ANONYMIZED_FIELDS};
+use crate::enrich::lookup::{IndexLookup, LookupSpec, parse_lookup_spec};
+use crate::utils::*;
+use clap::Args;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+// Adds or rewrites fields of the documents of a batch before they are printed
+pub trait Enricher {
+    fn enrich(&mut self, hits: &mut [Hit]) -> Result<(), ESQError>;
+
+    // Fields the enricher reads, which must be fetched even when not selected
+    fn source_fields(&self) -> Vec<String>;
+
+    // Fields the enricher adds, printed as columns next to the selected ones
+    fn output_fields(&self) -> Vec<String>;
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct EnrichArgs {
+    /// Merge fields of the matching document of another index (e.g. 'host.name->inventory:hostname:owner,rack', repeatable)
+    #[arg(long, value_name = "field->index:key:fields", value_parser = parse_lookup_spec)]
+    pub enrich: Vec<LookupSpec>,
+
+    /// Add geo fields next to an IP field from a MaxMind database (e.g. source.ip, repeatable)
+    #[arg(long, value_name = "field")]
+    pub geoip: Vec<String>,
+
+    /// MaxMind City or Country database, defaults to geoip_db in the configuration
+    #[arg(long, value_name = "file")]
+    pub geoip_db: Option<PathBuf>,
+
+    /// Parse a user agent field into browser, version and OS fields (e.g. user_agent.original, repeatable)
+    #[arg(long, value_name = "field")]
+    pub user_agent: Vec<String>,
+
+    /// Replace identifiers (IPs, user names, emails) with hashes salted for this run only
+    #[arg(long)]
+    pub anonymize: bool,
+
+    /// Fields hashed by --anonymize, comma-separated (defaults to common ECS identifier fields)
+    #[arg(long, value_name = "field1,field2,..", requires = "anonymize")]
+    pub anonymize_fields: Option<String>,
+}
+
+impl EnrichArgs {
+    pub fn build(&self, config: &Config) -> Result<Vec<Box<dyn Enricher>>, ESQError> {
+        let mut enrichers: Vec<Box<dyn Enricher>> = Vec::new();
+        for spec in &self.enrich {
+            enrichers.push(Box::new(IndexLookup::new(config, spec)?));
+        }
+        if !self.geoip.is_empty() {
+            let database = self
+                .geoip_db
+                .clone()
+                .or_else(|| config.default.geoip_db.as_ref().map(PathBuf::from))
+                .ok_or_else(|| {
+                    ESQError::ConfigError(
+                        "--geoip needs --geoip-db or geoip_db in the configuration".to_string(),
+                    )
+                })?;
+            enrichers.push(geoip_enricher(&database, &self.geoip)?);
+        }
+        if !self.user_agent.is_empty() {
+            enrichers.push(user_agent_enricher(&self.user_agent)?);
+        }
+        // Last, so values added by the other enrichers get hashed too
+        if self.anonymize {
+            enrichers.push(Box::new(Anonymizer::new(
+                self.anonymize_fields
+                    .as_deref()
+                    .unwrap_or(DEFAULT_ANONYMIZED_FIELDS),
+            )));
+        }
+        Ok(enrichers)
+    }
+}
+
+#[cfg(feature = "geoip")]
+fn geoip_enricher(database: &Path, fields: &[String]) -> Result<Box<dyn Enricher>, ESQError> {
+    Ok(Box::new(crate::enrich::geoip::GeoIp::open(
+        database, fields,
+    )?))
+}
+
+#[cfg(not(feature = "geoip"))]
+fn geoip_enricher(_database: &Path, _fields: &[String]) -> Result<Box<dyn Enricher>, ESQError> {
+    Err(ESQError::ConfigError(
+        "esq was built without GeoIP support, rebuild it with --features geoip".to_string(),
+    ))
+}
+
+#[cfg(feature = "useragent")]
+fn user_agent_enricher(fields: &[String]) -> Result<Box<dyn Enricher>, ESQError> {
+    Ok(Box::new(crate::enrich::useragent::UserAgent::new(fields)))
+}
+
+#[cfg(not(feature = "useragent"))]
+fn user_agent_enricher(_fields: &[String]) -> Result<Box<dyn Enricher>, ESQError> {
+    Err(ESQError::ConfigError(
+        "esq was built without user agent parsing, rebuild it with --features useragent"
+            .to_string(),
+    ))
+}
+
+// Value of a field usable as a lookup key, arrays and objects have none
+pub fn key_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+// Set a field by dot-path, creating the intermediate objects. A value of the document in the way
+// of the path is kept and nothing is set, false telling so
+pub fn insert_field(source: &mut Value, path: &str, value: Value) -> bool {
+    let Some(object) = source.as_object_mut() else {
+        return false;
+    };
+    match path.split_once('.') {
+        Some((head, rest)) => insert_field(
+            object
+                .entry(head)
+                .or_insert_with(|| Value::Object(Map::new())),
+            rest,
+            value,
+        ),
+        None => {
+            object.insert(path.to_string(), value);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_insert_field() {
+        let mut source = json!({"host": {"name": "web-1"}});
+        insert_field(&mut source, "host.owner", json!("team-a"));
+        insert_field(&mut source, "rack", json!("r12"));
+        assert_eq!(
+            source,
+            json!({"host": {"name": "web-1", "owner": "team-a"}, "rack": "r12"})
+        );
+
+        // The rack string is not replaced by an object
+        assert!(!insert_field(&mut source, "rack.row", json!("b")));
+        assert!(!insert_field(&mut json!("text"), "rack", json!("r12")));
+        assert_eq!(source["rack"], json!("r12"));
+    }
+
+    #[test]
+    fn test_key_value() {
+        assert_eq!(key_value(&json!("web-1")), Some("web-1".to_string()));
+        assert_eq!(key_value(&json!(42)), Some("42".to_string()));
+        assert_eq!(key_value(&json!(["a", "b"])), None);
+    }
+}
@@ -0,0 +1,134 @@
+// src/enrich/useragent.rs
+use crate::elasticsearch::model::Hit;
+use crate::enrich::{Enricher, insert_field};
+use crate::output::lookup_field;
+use crate::utils::*;
+use serde_json::{Map, Value, json};
+use woothee::parser::Parser;
+
+// Woothee reports what it cannot tell as UNKNOWN
+const UNKNOWN: &str = "UNKNOWN";
+
+// Parses user agent strings into ECS user_agent fields next to the original
+pub struct UserAgent {
+    parser: Parser,
+    fields: Vec<String>,
+}
+
+impl UserAgent {
+    pub fn new(fields: &[String]) -> Self {
+        Self {
+            parser: Parser::new(),
+            fields: fields.to_vec(),
+        }
+    }
+}
+
+// user_agent.original gets user_agent.name, user_agent.os.name, ..., any other field a
+// <field>_parsed object so that neither it nor its siblings are overwritten
+fn user_agent_target(field: &str) -> String {
+    match field.strip_suffix(".original") {
+        Some(parent) => parent.to_string(),
+        None => format!("{}_parsed", field),
+    }
+}
+
+fn user_agent_fields(parser: &Parser, agent: &str) -> Map<String, Value> {
+    let mut fields = Map::new();
+    let Some(result) = parser.parse(agent) else {
+        return fields;
+    };
+    for (name, value) in [
+        ("name", result.name),
+        ("version", result.version),
+        ("os.name", result.os),
+        ("os.version", result.os_version.as_ref()),
+        ("device.name", result.category),
+    ] {
+        if !value.is_empty() && value != UNKNOWN {
+            fields.insert(name.to_string(), json!(value));
+        }
+    }
+    fields
+}
+
+impl Enricher for UserAgent {
+    fn enrich(&mut self, hits: &mut [Hit]) -> Result<(), ESQError> {
+        for hit in hits {
+            for field in &self.fields {
+                let Some(agent) = lookup_field(hit.source(), field).and_then(Value::as_str) else {
+                    continue;
+                };
+                let parsed = user_agent_fields(&self.parser, agent);
+                let source = hit.source.get_or_insert_with(|| json!({}));
+                for (name, value) in parsed {
+                    insert_field(
+                        source,
+                        &format!("{}.{}", user_agent_target(field), name),
+                        value,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn source_fields(&self) -> Vec<String> {
+        self.fields.clone()
+    }
+
+    fn output_fields(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .map(|field| user_agent_target(field))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_agent_fields() {
+        let agent = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                     (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+        let fields = user_agent_fields(&Parser::new(), agent);
+        assert_eq!(fields["name"], json!("Chrome"));
+        assert_eq!(fields["version"], json!("120.0.0.0"));
+        assert_eq!(fields["os.name"], json!("Windows 10"));
+        assert_eq!(fields["device.name"], json!("pc"));
+        assert!(user_agent_fields(&Parser::new(), "curl-ish/0").is_empty());
+    }
+
+    #[test]
+    fn test_enrich_keeps_the_parsed_field() {
+        let mut hits: Vec<Hit> = serde_json::from_value(json!([{
+            "_index": "logs",
+            "_id": "1",
+            "_source": {
+                "user_agent": "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                               (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+                "http": {"version": "1.1"}
+            }
+        }]))
+        .unwrap();
+        UserAgent::new(&["user_agent".to_string()])
+            .enrich(&mut hits)
+            .unwrap();
+        let source = hits[0].source();
+        assert!(source["user_agent"].is_string());
+        assert_eq!(source["user_agent_parsed"]["name"], json!("Chrome"));
+        assert_eq!(source["http"]["version"], json!("1.1"));
+    }
+
+    #[test]
+    fn test_user_agent_target() {
+        assert_eq!(user_agent_target("user_agent.original"), "user_agent");
+        assert_eq!(
+            user_agent_target("http.user_agent"),
+            "http.user_agent_parsed"
+        );
+        assert_eq!(user_agent_target("user_agent"), "user_agent_parsed");
+    }
+}
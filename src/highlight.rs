@@ -0,0 +1,100 @@
+// src/highlight.rs
+use crate::color::paint;
+use serde_json::Value;
+
+const INDENT: &str = "  ";
+const KEY: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+const BOOL: &str = "\x1b[33m";
+const NULL: &str = "\x1b[90m";
+
+// Indented like serde_json's pretty printer, with ANSI colors for terminals
+pub fn pretty_json(value: &Value, color: bool) -> String {
+    if !color {
+        return serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string());
+    }
+    let mut out = String::new();
+    write_value(&mut out, value, 0);
+    out
+}
+
+fn push_painted(out: &mut String, color: &str, text: &str) {
+    out.push_str(&paint(text, color));
+}
+
+fn newline(out: &mut String, depth: usize) {
+    out.push('\n');
+    out.push_str(&INDENT.repeat(depth));
+}
+
+fn write_value(out: &mut String, value: &Value, depth: usize) {
+    match value {
+        Value::Null => push_painted(out, NULL, "null"),
+        Value::Bool(flag) => push_painted(out, BOOL, &flag.to_string()),
+        Value::Number(number) => push_painted(out, NUMBER, &number.to_string()),
+        Value::String(_) => push_painted(out, STRING, &value.to_string()),
+        Value::Array(items) if items.is_empty() => out.push_str("[]"),
+        Value::Object(fields) if fields.is_empty() => out.push_str("{}"),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, depth + 1);
+                write_value(out, item, depth + 1);
+            }
+            newline(out, depth);
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            out.push('{');
+            for (i, (key, item)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, depth + 1);
+                push_painted(out, KEY, &Value::String(key.clone()).to_string());
+                out.push_str(": ");
+                write_value(out, item, depth + 1);
+            }
+            newline(out, depth);
+            out.push('}');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn strip_ansi(text: &str) -> String {
+        let mut plain = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| *c == 'm');
+            } else {
+                plain.push(c);
+            }
+        }
+        plain
+    }
+
+    #[test]
+    fn test_pretty_json_layout_matches_serde() {
+        let value = json!({
+            "kubernetes": {"labels": {}, "pod": {"name": "api-1", "restarts": 2}},
+            "message": "say \"hi\"",
+            "tags": ["a", true, null],
+            "empty": []
+        });
+        let colored = pretty_json(&value, true);
+        assert!(
+            colored.contains("\x1b[1;34m\"message\"\x1b[0m: \x1b[32m\"say \\\"hi\\\"\"\x1b[0m")
+        );
+        assert_eq!(strip_ansi(&colored), pretty_json(&value, false));
+    }
+}
@@ -0,0 +1,165 @@
+// src/history.rs
+use crate::session::ClusterFingerprint;
+use crate::utils::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+// Exports older than this are considered stale, the data behind them may have changed
+const RECENT_HOURS: i64 = 24;
+
+// What makes two exports produce the same files, time ranges resolved to instants
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportFingerprint {
+    pub cluster: ClusterFingerprint,
+    pub index: String,
+    pub query: Value,
+}
+
+// One completed export, a line of the history file
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportRecord {
+    pub fingerprint: ExportFingerprint,
+    pub output_dir: PathBuf,
+    // Files written by the export, records of older versions have none and are never reused
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    pub completed_at: String,
+}
+
+impl ExportRecord {
+    fn completed_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.completed_at)
+            .ok()
+            .map(|completed_at| completed_at.with_timezone(&Utc))
+    }
+
+    fn is_recent(&self, now: DateTime<Utc>) -> bool {
+        self.completed_at()
+            .is_some_and(|completed_at| now - completed_at < chrono::Duration::hours(RECENT_HOURS))
+    }
+
+    // The files can only be reused while every one of them is still there
+    pub fn output_available(&self) -> bool {
+        !self.files.is_empty() && self.files.iter().all(|file| file.is_file())
+    }
+}
+
+fn read_records(path: &Path) -> Result<Vec<ExportRecord>, ESQError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut records = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        // Lines written by other versions are skipped rather than failing the export
+        if let Ok(record) = serde_json::from_str(&line) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+// Most recent completed export with the same fingerprint
+pub fn find_recent(
+    path: &Path,
+    fingerprint: &ExportFingerprint,
+    now: DateTime<Utc>,
+) -> Result<Option<ExportRecord>, ESQError> {
+    Ok(read_records(path)?
+        .into_iter()
+        .rev()
+        .find(|record| &record.fingerprint == fingerprint && record.is_recent(now)))
+}
+
+// Append a completed export, dropping the records that are no longer recent
+pub fn record_export(path: &Path, record: ExportRecord) -> Result<(), ESQError> {
+    let now = Utc::now();
+    let mut records: Vec<ExportRecord> = read_records(path)?
+        .into_iter()
+        .filter(|record| record.is_recent(now))
+        .collect();
+    records.push(record);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(path)?;
+    for record in &records {
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fingerprint(index: &str) -> ExportFingerprint {
+        ExportFingerprint {
+            cluster: ClusterFingerprint {
+                url: "https://es.example.com:9200/".to_string(),
+                authenticated: true,
+            },
+            index: index.to_string(),
+            query: json!({"from": 1717200000, "to": 1717286400}),
+        }
+    }
+
+    #[test]
+    fn test_find_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = Utc::now();
+        let record = |index: &str, completed_at: DateTime<Utc>| ExportRecord {
+            fingerprint: fingerprint(index),
+            output_dir: PathBuf::from("out"),
+            files: Vec::new(),
+            completed_at: completed_at.to_rfc3339(),
+        };
+        record_export(&path, record("logs", now - chrono::Duration::hours(1))).unwrap();
+        record_export(&path, record("metrics", now)).unwrap();
+        record_export(&path, record("stale", now - chrono::Duration::hours(48))).unwrap();
+
+        let found = find_recent(&path, &fingerprint("logs"), now).unwrap();
+        assert_eq!(found.unwrap().fingerprint.index, "logs");
+        assert!(
+            find_recent(&path, &fingerprint("stale"), now)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            find_recent(&path, &fingerprint("other"), now)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_output_available() {
+        let dir = tempfile::tempdir().unwrap();
+        let export = dir.path().join("export.ndjson");
+        fs::write(&export, "{}\n").unwrap();
+        // Other files in the directory do not stand for the export
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let mut record = ExportRecord {
+            fingerprint: fingerprint("logs"),
+            output_dir: dir.path().to_path_buf(),
+            files: vec![export.clone(), dir.path().join("schema.json")],
+            completed_at: Utc::now().to_rfc3339(),
+        };
+        assert!(!record.output_available());
+        fs::write(dir.path().join("schema.json"), "{}").unwrap();
+        assert!(record.output_available());
+        fs::remove_file(&export).unwrap();
+        assert!(!record.output_available());
+        record.files.clear();
+        assert!(!record.output_available());
+    }
+}
@@ -1,18 +1,62 @@
+mod abbrev;
+mod cache;
+mod color;
 mod commands;
+mod context;
 mod elasticsearch;
+mod enrich;
+mod highlight;
+mod history;
+mod output;
+mod project;
+mod redact;
+mod schema;
+mod session;
+mod sink;
+mod snapshot;
+mod table;
+mod template;
+mod usage;
 mod utils;
+mod windows;
 
-use clap::{Parser, Subcommand};
-use commands::alias::{AliasCommands, handle_alias_command};
-use commands::cat::{CatArgs, handle_cat_command};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use commands::alias::{
+    AliasCommands, handle_alias_command, resolve_index, resolve_targets, run_targets_cat,
+};
+use commands::assert::{AssertArgs, handle_assert_command};
+use commands::cat::CatArgs;
+use commands::export::{ExportArgs, handle_export_command};
+use commands::get::{GetArgs, handle_get_command};
 use commands::login::handle_login_command;
 use commands::logout::handle_logout_command;
 use commands::ls::handle_ls_command;
+use commands::man::handle_man_command;
+use commands::replay::handle_replay_command;
+use commands::self_update::handle_self_update_command;
+use commands::sizeof::{NumberFormat, SizeofArgs, handle_sizeof_command};
+use commands::watch::{WatchArgs, Watched, handle_watch_command};
+use context::AppContext;
+use project::require_index;
+use std::path::PathBuf;
+use usage::{handle_stats_command, record_run};
 use utils::*;
 
 #[derive(Parser)]
 #[command(version)]
 struct Cli {
+    /// Configuration profile to use, a [profiles.<name>] section of the config file
+    #[arg(long, global = true, value_name = "name")]
+    profile: Option<String>,
+
+    /// Send every request to the configured URL even when the profile sets sniff, e.g. behind a proxy
+    #[arg(long, global = true)]
+    no_sniff: bool,
+
+    /// Read no file and write none, taking the settings from ESQ_URL, ESQ_USERNAME, ESQ_PASSWORD and the other ESQ_ variables
+    #[arg(long, global = true)]
+    no_config: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -23,55 +67,205 @@ enum Commands {
     Ls,
 
     /// Display data from a specific index
+    #[command(after_long_help = commands::cat::EXAMPLES)]
     Cat(CatArgs),
 
+    /// Write the documents of an extraction to NDJSON files
+    #[command(after_long_help = commands::export::EXAMPLES)]
+    Export(ExportArgs),
+
+    /// Fetch documents by ID, or by the values of a field
+    #[command(after_long_help = commands::get::EXAMPLES)]
+    Get(GetArgs),
+
+    /// Estimate the size and duration of a query before running it
+    #[command(after_long_help = commands::sizeof::EXAMPLES)]
+    Sizeof(SizeofArgs),
+
+    /// Check the number of matching documents, failing when out of bounds
+    #[command(after_long_help = commands::assert::EXAMPLES)]
+    Assert(AssertArgs),
+
+    /// Re-run an aggregation on a schedule and print what changed, e.g. 'top my-logs level'
+    #[command(after_long_help = commands::watch::EXAMPLES)]
+    Watch(WatchArgs),
+
     /// Manage aliases for indices used in the cat command
     Alias {
         #[command(subcommand)]
         command: AliasCommands,
     },
 
+    /// Print the output of a session recorded with cat --record-session
+    ReplaySession {
+        /// Session file to replay
+        #[arg(value_name = "file")]
+        file: PathBuf,
+    },
+
     /// Login to Elasticsearch instance
     Login,
 
     /// Logout from Elasticsearch instance
     Logout,
+
+    /// Generate roff man pages from the command-line definitions
+    Man {
+        /// Write esq.1 and one esq-<command>.1 page per command here instead of printing esq.1
+        #[arg(long, value_name = "dir")]
+        out_dir: Option<PathBuf>,
+    },
+
+    /// Show the usage recorded on this machine, never sent anywhere
+    Stats {
+        /// Report the commands run, documents fetched and bytes transferred per profile
+        #[arg(long = "self", required = true)]
+        own_usage: bool,
+
+        /// Print exact document counts and sizes in bytes, for scripts
+        #[arg(long, visible_alias = "bytes")]
+        raw_numbers: bool,
+    },
+
+    /// Replace this binary with the latest release, after checking its SHA-256 checksum
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    match run() {
+        Ok(()) | Err(ESQError::OutputClosed) => {}
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
     }
 }
 
 fn run() -> Result<(), ESQError> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let command_name = matches.subcommand_name().unwrap_or_default();
+
+    // Man pages need neither a configuration nor a project
+    if let Commands::Man { out_dir } = &cli.command {
+        return handle_man_command(Cli::command(), out_dir.as_deref());
+    }
+    if let Commands::SelfUpdate { check } = &cli.command {
+        return handle_self_update_command(*check);
+    }
 
     // Try to load existing config at startup
-    let config_dir = dirs::home_dir()
-        .ok_or(ESQError::ConfigError(
-            "Could not determine home directory".to_string(),
-        ))?
-        .join(".esq");
-    let config_file = config_dir.join("config.toml");
-    let config = load_config(&config_file)?;
-
-    match &cli.command {
-        Commands::Ls => handle_ls_command(config),
-        Commands::Cat(args) => handle_cat_command(
-            config,
-            &args.index,
-            &args.from,
-            &args.to,
-            &args.select_clause,
-            &args.where_clause,
-            args.follow,
-            &args.around,
-            &args.lines,
-        ),
-        Commands::Alias { command } => handle_alias_command(command),
-        Commands::Login => handle_login_command(config, &config_file),
-        Commands::Logout => handle_logout_command(config, &config_file),
+    let context = AppContext::load(cli.profile.as_deref(), cli.no_sniff, cli.no_config)?;
+    let usage_file = context.usage_file();
+    let profile = context.profile_name().to_string();
+
+    let result = match &mut cli.command {
+        Commands::Ls => handle_ls_command(&context.client()?),
+        Commands::Cat(args) => {
+            if let Some(offset) = args.output.tz.and_then(|tz| tz.offset()) {
+                set_calendar_offset(offset);
+            }
+            args.cache_dir = context.cache_dir();
+            args.spool_dir = context.spool_dir();
+            context.project.apply(args.query_target())?;
+            order_time_range(&mut args.from, &mut args.to, args.swap_ok)?;
+            let targets = resolve_targets(&context, require_index(&args.index)?);
+            run_targets_cat(&context, &targets, args)
+        }
+        Commands::Export(args) => {
+            if let Some(offset) = args.cat.output.tz.and_then(|tz| tz.offset()) {
+                set_calendar_offset(offset);
+            }
+            args.cat.spool_dir = context.spool_dir();
+            context.project.apply(args.cat.query_target())?;
+            order_time_range(&mut args.cat.from, &mut args.cat.to, args.cat.swap_ok)?;
+            let target = resolve_index(&context, require_index(&args.cat.index)?)?;
+            target.apply_to_cat(&mut args.cat);
+            handle_export_command(
+                context.client_for(target.profile.as_deref())?,
+                args,
+                context.history_file().as_deref(),
+            )
+        }
+        Commands::Get(args) => {
+            let target = resolve_index(&context, &args.index)?;
+            args.index.clone_from(&target.index);
+            args.select_clause = target.select(&args.select_clause);
+            handle_get_command(
+                context.client_for(target.profile.as_deref())?,
+                args,
+                target.query(&None).as_deref(),
+            )
+        }
+        Commands::Sizeof(args) => {
+            context.project.apply(args.query_target())?;
+            order_time_range(&mut args.from, &mut args.to, args.swap_ok)?;
+            let target = resolve_index(&context, require_index(&args.index)?)?;
+            let mut spec = args.query_spec()?;
+            target.apply_to_spec(&mut spec);
+            handle_sizeof_command(
+                context.client_for(target.profile.as_deref())?,
+                &spec,
+                NumberFormat::new(args.raw_numbers),
+            )
+        }
+        Commands::Assert(args) => {
+            context.project.apply(args.query_target())?;
+            order_time_range(&mut args.from, &mut args.to, args.swap_ok)?;
+            let target = resolve_index(&context, require_index(&args.index)?)?;
+            let mut spec = args.query_spec()?;
+            target.apply_to_spec(&mut spec);
+            handle_assert_command(
+                context.client_for(target.profile.as_deref())?,
+                &spec,
+                args.min,
+                args.max,
+            )
+        }
+        Commands::Watch(args) => {
+            let Watched::Top(mut top) = args.watched()?;
+            context.project.apply(top.query_target())?;
+            let target = resolve_index(&context, require_index(&top.index)?)?;
+            let mut spec = top.query_spec()?;
+            target.apply_to_spec(&mut spec);
+            handle_watch_command(
+                context.client_for(target.profile.as_deref())?,
+                &spec,
+                &top,
+                args.interval,
+            )
+        }
+        Commands::Alias { command } => handle_alias_command(command, &context),
+        Commands::ReplaySession { file } => handle_replay_command(file),
+        Commands::Login => {
+            let config_file = context.writable_config_file()?.clone();
+            let profile = context.profile().map(str::to_string);
+            handle_login_command(context.config, &config_file, profile.as_deref())
+        }
+        Commands::Logout => {
+            let config_file = context.writable_config_file()?.clone();
+            handle_logout_command(context.config, &config_file)
+        }
+        Commands::Stats { raw_numbers, .. } => {
+            let usage_file = usage_file.ok_or_else(|| {
+                ESQError::ConfigError("--no-config keeps no usage stats".to_string())
+            })?;
+            return handle_stats_command(&usage_file, NumberFormat::new(*raw_numbers));
+        }
+        Commands::Man { .. } | Commands::SelfUpdate { .. } => {
+            unreachable!("handled before loading the configuration")
+        }
+    };
+
+    // Usage stats are a convenience, failing to write them leaves the command's outcome alone
+    if let Some(usage_file) = &usage_file
+        && let Err(e) = record_run(usage_file, &profile, command_name)
+    {
+        eprintln!("Warning: could not update usage stats: {}", e);
     }
+    result
 }
@@ -0,0 +1,1740 @@
+// src/output.rs
+use crate::abbrev::Abbreviations;
+use crate::color::{ColorMode, DIM, level_color, paint};
+use crate::commands::sizeof::format_bytes;
+use crate::elasticsearch::model::Hit;
+use crate::highlight::pretty_json;
+use crate::session::SessionRecorder;
+use crate::sink::Sink;
+use crate::sink::file::BucketWriter;
+use crate::snapshot::Snapshotter;
+use crate::table::{Layout, Table};
+use crate::template::{LineTemplate, parse_template};
+use crate::utils::*;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, Utc};
+use clap::{Args, ValueEnum};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::{BufWriter, IsTerminal, Stdout, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SQUASH_FIELDS: &str = "message,level,service";
+const LEVEL_FIELDS: [&str; 3] = ["level", "log.level", "severity"];
+const SERVICE_FIELDS: [&str; 2] = ["service.name", "service"];
+const TOP_SERVICES: usize = 5;
+// How often --stats-panel repeats its block when stderr is not a terminal
+const PANEL_LOG_INTERVAL: Duration = Duration::from_secs(30);
+const PROGRESS_BAR_WIDTH: usize = 30;
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+// Lines are written to stdout in chunks of this size, and at least once per batch
+const STDOUT_BUFFER_SIZE: usize = 64 * 1024;
+const TRACE_FIELDS: [&str; 3] = ["trace.id", "trace_id", "traceId"];
+const META_FIELDS: [&str; 3] = ["_index", "_id", "_sort"];
+
+// A closed pipe stops the extraction, the error bubbling up releases the PIT on the way
+pub fn stdout_error(err: std::io::Error) -> ESQError {
+    match err.kind() {
+        std::io::ErrorKind::BrokenPipe => ESQError::OutputClosed,
+        _ => ESQError::IOError(err),
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct OutputArgs {
+    /// Print a separator when consecutive entries are further apart than this duration
+    #[arg(long, value_name = "duration", value_parser = parse_duration_arg)]
+    pub gap_marker: Option<Duration>,
+
+    /// Print a header line whenever the output enters a new hour or day
+    #[arg(long, value_name = "period")]
+    pub group_headers: Option<GroupBy>,
+
+    /// Prefix each entry with its line number
+    #[arg(long)]
+    #[arg(short = 'N')]
+    pub number: bool,
+
+    /// Print entries as JSON lines, logfmt key=value pairs, or as aligned columns, CSV or TSV rows of the selected fields
+    #[arg(long = "output", value_name = "format", default_value = "json")]
+    pub format: OutputFormat,
+
+    /// Write the entries to this new file instead of stdout, gzipped when it ends in .gz, documents only, reporting progress on stderr
+    #[arg(long, short = 'o', value_name = "path", conflicts_with = "sink")]
+    pub output_file: Option<PathBuf>,
+
+    /// Print batch diagnostics to stderr
+    #[arg(long)]
+    #[arg(short = 'v')]
+    pub verbose: bool,
+
+    /// Collapse consecutive entries identical on these fields into a repeat count
+    #[arg(long, value_name = "field1,field2,..", num_args = 0..=1, default_missing_value = DEFAULT_SQUASH_FIELDS)]
+    pub squash: Option<String>,
+
+    /// Print the legend of the selected fields, then again every N entries (e.g. in follow mode)
+    #[arg(long, value_name = "entries")]
+    pub header_interval: Option<u64>,
+
+    /// Render document and trace IDs as terminal hyperlinks to Kibana
+    #[arg(long)]
+    pub hyperlinks: bool,
+
+    /// Render times in this zone: local (the TZ environment variable), utc or a fixed offset such as +02:00; named zones such as Europe/Paris only through TZ with local
+    #[arg(long, value_name = "zone", value_parser = parse_timezone)]
+    pub tz: Option<OutputTimezone>,
+
+    /// Rewrite the timestamp field of printed entries: relative, epoch-ms or a strftime pattern
+    #[arg(long, value_name = "format", value_parser = parse_time_format)]
+    pub time_format: Option<TimeFormat>,
+
+    /// Print each entry as a line of text, e.g. "{@timestamp} [{level}] {message}" ("{{" for a brace)
+    // The id of --output is "format"
+    #[arg(long = "format", value_name = "template", value_parser = parse_template, conflicts_with = "format")]
+    pub template: Option<LineTemplate>,
+
+    /// Color entries by level and dim decorations: auto colors terminals unless NO_COLOR is set
+    #[arg(long, value_name = "when", default_value = "auto")]
+    pub color: ColorMode,
+
+    /// Print only the value of this field, unquoted, skipping entries without it (e.g. message)
+    #[arg(long, value_name = "field", conflicts_with_all = ["format", "template", "pretty", "meta"])]
+    pub raw: Option<String>,
+
+    /// Add the _index, _id and _sort values of each hit to the printed document
+    #[arg(long)]
+    pub meta: bool,
+
+    /// Print nested objects as dot-notation keys, e.g. kubernetes.pod.name, one column each in tables
+    #[arg(long)]
+    pub flatten: bool,
+
+    /// Keep the full key names of JSON lines wider than the terminal, e.g. kubernetes.namespace for k8s.ns
+    #[arg(long)]
+    pub no_abbrev: bool,
+
+    /// Print each entry as indented JSON, syntax highlighted on terminals
+    #[arg(long, conflicts_with_all = ["format", "template"])]
+    pub pretty: bool,
+
+    /// Text printed by --format for fields missing from an entry
+    #[arg(long, value_name = "text", default_value = "-", requires = "template")]
+    pub missing: String,
+
+    // Prefix telling apart the entries of parallel extractions, e.g. of an alias group
+    #[arg(skip)]
+    pub tag: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Logfmt,
+    Table,
+    Csv,
+    Tsv,
+}
+
+impl OutputFormat {
+    fn layout(&self, width: Option<usize>) -> Option<Layout> {
+        match self {
+            OutputFormat::Json | OutputFormat::Logfmt => None,
+            OutputFormat::Table => Some(Layout::Aligned(width)),
+            OutputFormat::Csv => Some(Layout::Delimited(',')),
+            OutputFormat::Tsv => Some(Layout::Delimited('\t')),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    Hour,
+    Day,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputTimezone {
+    Local,
+    Utc,
+    Offset(FixedOffset),
+}
+
+impl OutputTimezone {
+    // The zone as an offset, None for local time which moves with daylight saving
+    pub fn offset(&self) -> Option<FixedOffset> {
+        match self {
+            OutputTimezone::Local => None,
+            OutputTimezone::Utc => FixedOffset::east_opt(0),
+            OutputTimezone::Offset(offset) => Some(*offset),
+        }
+    }
+}
+
+// Offsets are written +02:00, -0530, +2 or UTC+2; named zones go through TZ with --tz local
+pub fn parse_timezone(input: &str) -> Result<OutputTimezone, String> {
+    let invalid = || {
+        format!(
+            "invalid timezone '{}', expected local, utc or a fixed offset such as +02:00; \
+             for a named zone run with TZ=<zone> and --tz local",
+            input
+        )
+    };
+    let zone = input.trim();
+    match zone.to_ascii_lowercase().as_str() {
+        "local" => return Ok(OutputTimezone::Local),
+        "utc" | "z" | "gmt" => return Ok(OutputTimezone::Utc),
+        _ => {}
+    }
+    let offset = zone
+        .strip_prefix("UTC")
+        .or_else(|| zone.strip_prefix("utc"))
+        .unwrap_or(zone);
+    let (sign, offset) = match offset.split_at_checked(1) {
+        Some(("+", offset)) => (1, offset),
+        Some(("-", offset)) => (-1, offset),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = match offset.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if offset.len() == 4 => offset.split_at_checked(2).ok_or_else(invalid)?,
+        None => (offset, "0"),
+    };
+    let hours: i32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+    // Offsets in use go from -12:00 to +14:00
+    if hours > 14 || (hours == 14 && minutes > 0) || minutes > 59 {
+        return Err(invalid());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+        .map(OutputTimezone::Offset)
+        .ok_or_else(invalid)
+}
+
+impl OutputTimezone {
+    pub fn format(&self, timestamp: &DateTime<Utc>, format: &str) -> String {
+        match self {
+            OutputTimezone::Local => timestamp.with_timezone(&Local).format(format).to_string(),
+            OutputTimezone::Utc => timestamp.format(format).to_string(),
+            OutputTimezone::Offset(offset) => {
+                timestamp.with_timezone(offset).format(format).to_string()
+            }
+        }
+    }
+
+    // RFC 3339 in the zone, as precise as the original timestamp
+    fn rfc3339(&self, timestamp: &DateTime<Utc>) -> String {
+        match self {
+            OutputTimezone::Local => timestamp
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            OutputTimezone::Utc => timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            OutputTimezone::Offset(offset) => timestamp
+                .with_timezone(offset)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeFormat {
+    Relative,
+    EpochMs,
+    Pattern(String),
+    // Only converted to the zone of --tz
+    Rfc3339,
+}
+
+pub fn parse_time_format(input: &str) -> Result<TimeFormat, String> {
+    match input {
+        "relative" => Ok(TimeFormat::Relative),
+        "epoch-ms" => Ok(TimeFormat::EpochMs),
+        // Rendering an invalid pattern would panic, it is rejected here instead
+        pattern if StrftimeItems::new(pattern).any(|item| item == Item::Error) => Err(format!(
+            "invalid time format '{}', expected relative, epoch-ms or a strftime pattern",
+            pattern
+        )),
+        pattern => Ok(TimeFormat::Pattern(pattern.to_string())),
+    }
+}
+
+impl TimeFormat {
+    fn render(&self, timestamp: &DateTime<Utc>, tz: OutputTimezone, now: DateTime<Utc>) -> Value {
+        match self {
+            TimeFormat::Relative => {
+                let secs = (now - *timestamp).num_milliseconds() as f64 / 1000.0;
+                if secs < 0.0 {
+                    Value::from(format!("in {}", format_duration(-secs)))
+                } else {
+                    Value::from(format!("{} ago", format_duration(secs)))
+                }
+            }
+            TimeFormat::EpochMs => Value::from(timestamp.timestamp_millis()),
+            TimeFormat::Pattern(pattern) => Value::from(tz.format(timestamp, pattern)),
+            TimeFormat::Rfc3339 => Value::from(tz.rfc3339(timestamp)),
+        }
+    }
+}
+
+// Look up a field by dot-path, accepting both nested objects and literal dotted keys
+pub fn lookup_field<'a>(source: &'a Value, path: &str) -> Option<&'a Value> {
+    if let Some(value) = source.get(path) {
+        return Some(value);
+    }
+    path.match_indices('.')
+        .find_map(|(split, _)| lookup_field(source.get(&path[..split])?, &path[split + 1..]))
+}
+
+// Whether a field matches a --select pattern, where * stands for any characters, dots included
+pub fn field_matches(pattern: &str, field: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = field.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+// Mutable counterpart of lookup_field, accepting nested objects and literal dotted keys
+pub(crate) fn lookup_field_mut<'a>(source: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    if source.get(path).is_some() {
+        return source.get_mut(path);
+    }
+    let split = path
+        .match_indices('.')
+        .map(|(split, _)| split)
+        .find(|split| {
+            source
+                .get(&path[..*split])
+                .and_then(|parent| lookup_field(parent, &path[split + 1..]))
+                .is_some()
+        })?;
+    lookup_field_mut(source.get_mut(&path[..split])?, &path[split + 1..])
+}
+
+// The source with its timestamp rendered for reading, left alone when it is not a date
+fn format_time_field(source: &Value, format: &TimeFormat, tz: OutputTimezone) -> Value {
+    let mut source = source.clone();
+    if let Some(field) = lookup_field_mut(&mut source, time_field())
+        && let Some(timestamp) = field
+            .as_str()
+            .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+    {
+        *field = format.render(&timestamp.with_timezone(&Utc), tz, Utc::now());
+    }
+    source
+}
+
+// Key identifying an entry for --squash, None when none of the fields are present
+fn squash_key(source: &Value, fields: &[String]) -> Option<Vec<Value>> {
+    let key: Vec<Value> = fields
+        .iter()
+        .map(|field| lookup_field(source, field).cloned().unwrap_or(Value::Null))
+        .collect();
+    if key.iter().all(Value::is_null) {
+        None
+    } else {
+        Some(key)
+    }
+}
+
+fn repeat_line(repeats: u64) -> String {
+    match repeats {
+        1 => "last message repeated 1 time".to_string(),
+        _ => format!("last message repeated {} times", repeats),
+    }
+}
+
+fn first_string_field(source: &Value, fields: &[&str]) -> Option<String> {
+    fields.iter().find_map(|field| {
+        lookup_field(source, field)
+            .and_then(Value::as_str)
+            .map(|value| value.to_string())
+    })
+}
+
+fn format_counts(counts: &[(&String, &u64)]) -> String {
+    counts
+        .iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Counters accumulated while following, summarized when the session ends
+pub struct FollowStats {
+    started: Instant,
+    docs: u64,
+    levels: HashMap<String, u64>,
+    services: HashMap<String, u64>,
+}
+
+impl FollowStats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            docs: 0,
+            levels: HashMap::new(),
+            services: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, source: &Value) {
+        self.docs += 1;
+        if let Some(level) = first_string_field(source, &LEVEL_FIELDS) {
+            *self.levels.entry(level.to_uppercase()).or_default() += 1;
+        }
+        if let Some(service) = first_string_field(source, &SERVICE_FIELDS) {
+            *self.services.entry(service).or_default() += 1;
+        }
+    }
+
+    fn ranked(counts: &HashMap<String, u64>) -> Vec<(&String, &u64)> {
+        let mut ranked: Vec<(&String, &u64)> = counts.iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        ranked
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "Followed for {}",
+                format_duration(self.started.elapsed().as_secs_f64())
+            ),
+            format!("Documents: {}", self.docs),
+        ];
+        if !self.levels.is_empty() {
+            lines.push(format!(
+                "By level: {}",
+                format_counts(&Self::ranked(&self.levels))
+            ));
+        }
+        if !self.services.is_empty() {
+            let ranked = Self::ranked(&self.services);
+            lines.push(format!(
+                "Top services: {}",
+                format_counts(&ranked[..ranked.len().min(TOP_SERVICES)])
+            ));
+        }
+        lines
+    }
+
+    // The counts of the summary as the few lines of --stats-panel
+    pub fn panel(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "── {} documents in {} ──",
+            self.docs,
+            format_duration(self.started.elapsed().as_secs_f64())
+        )];
+        if !self.levels.is_empty() {
+            lines.push(format!(
+                "levels    {}",
+                format_counts(&Self::ranked(&self.levels))
+            ));
+        }
+        if !self.services.is_empty() {
+            let ranked = Self::ranked(&self.services);
+            lines.push(format!(
+                "services  {}",
+                format_counts(&ranked[..ranked.len().min(TOP_SERVICES)])
+            ));
+        }
+        lines
+    }
+}
+
+// Moves back to the first of the lines last written and erases them along with what follows
+fn erase_lines(count: usize) -> String {
+    format!("\x1b[{}F\x1b[J", count)
+}
+
+// The block of --stats-panel under the streamed entries, erased before each batch and drawn again after it
+pub struct StatsPanel {
+    terminal: bool,
+    // Lines are cut to the terminal, a wrapped one would leave rows behind when erased
+    width: Option<usize>,
+    drawn: usize,
+    last_drawn: Option<Instant>,
+}
+
+impl StatsPanel {
+    pub fn new() -> Self {
+        Self {
+            terminal: std::io::stderr().is_terminal(),
+            width: stderr_width(),
+            drawn: 0,
+            last_drawn: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if self.drawn > 0 {
+            eprint!("{}", erase_lines(self.drawn));
+            self.drawn = 0;
+        }
+    }
+
+    // A stderr written to a file gets a copy of the block now and then instead
+    pub fn draw(&mut self, stats: &FollowStats) {
+        if !self.terminal
+            && self
+                .last_drawn
+                .is_some_and(|drawn| drawn.elapsed() < PANEL_LOG_INTERVAL)
+        {
+            return;
+        }
+        let lines = stats.panel();
+        for line in &lines {
+            match self.width {
+                Some(width) => eprintln!("{}", line.chars().take(width).collect::<String>()),
+                None => eprintln!("{}", line),
+            }
+        }
+        if self.terminal {
+            self.drawn = lines.len();
+        }
+        self.last_drawn = Some(Instant::now());
+    }
+}
+
+// Expose fields API values as the hit's _source, unwrapping single-valued arrays
+pub fn fields_as_source(hit: &Hit) -> Hit {
+    let mut hit = hit.clone();
+    let source: serde_json::Map<String, Value> = hit
+        .fields
+        .as_ref()
+        .map(|fields| {
+            fields
+                .iter()
+                .map(|(name, values)| {
+                    let value = match values.as_array() {
+                        Some(values) if values.len() == 1 => values[0].clone(),
+                        _ => values.clone(),
+                    };
+                    (name.clone(), value)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    hit.source = Some(Value::Object(source));
+    hit
+}
+
+// Timestamp of a hit, read from _source or from the time field sort value (epoch millis)
+pub fn hit_timestamp(hit: &Hit) -> Option<DateTime<Utc>> {
+    if let Some(timestamp) = lookup_field(hit.source(), time_field()).and_then(Value::as_str)
+        && let Ok(parsed) = DateTime::parse_from_rfc3339(timestamp)
+    {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    hit.sort
+        .as_ref()
+        .and_then(|sort| sort.first())
+        .and_then(Value::as_i64)
+        .and_then(DateTime::from_timestamp_millis)
+}
+
+fn bucket_label(timestamp: &DateTime<Utc>, group_by: GroupBy, tz: OutputTimezone) -> String {
+    match group_by {
+        GroupBy::Hour => tz.format(timestamp, "%Y-%m-%d %H:00"),
+        GroupBy::Day => tz.format(timestamp, "%Y-%m-%d"),
+    }
+}
+
+fn number_prefix(line: u64, batch: Option<u64>) -> String {
+    match batch {
+        Some(batch) => format!("{}:{}\t", batch, line),
+        None => format!("{:>6}\t", line),
+    }
+}
+
+// OSC 8 escape sequence understood by most modern terminal emulators
+fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn kibana_doc_url(kibana_url: &str, id: &str) -> String {
+    format!(
+        "{}/app/discover#/?_a=(query:(language:kuery,query:'{}'))",
+        kibana_url.trim_end_matches('/'),
+        percent_encode(&format!("_id:\"{}\"", id))
+    )
+}
+
+fn kibana_trace_url(kibana_url: &str, trace_id: &str) -> String {
+    format!(
+        "{}/app/apm/link-to/trace/{}",
+        kibana_url.trim_end_matches('/'),
+        percent_encode(trace_id)
+    )
+}
+
+// Link the document ID (as a prefix) and the first trace ID found in the source
+fn render_links(kibana_url: &str, hit: &Hit, line: &str) -> String {
+    let mut line = line.to_string();
+    if let Some(trace_id) = first_string_field(hit.source(), &TRACE_FIELDS) {
+        let quoted = Value::String(trace_id.clone()).to_string();
+        let inner = &quoted[1..quoted.len() - 1];
+        let linked = format!(
+            "\"{}\"",
+            hyperlink(&kibana_trace_url(kibana_url, &trace_id), inner)
+        );
+        line = line.replacen(&quoted, &linked, 1);
+    }
+    match &hit.id {
+        Some(id) => format!(
+            "{} {}",
+            hyperlink(&kibana_doc_url(kibana_url, id), id),
+            line
+        ),
+        None => line,
+    }
+}
+
+// Where a hit comes from, under names Elasticsearch reserves so no source field is overwritten
+fn with_metadata(hit: &Hit, source: &Value) -> Value {
+    let mut document = match source {
+        Value::Object(fields) => fields.clone(),
+        _ => serde_json::Map::new(),
+    };
+    let meta = [
+        (META_FIELDS[0], hit.index.clone().map(Value::from)),
+        (META_FIELDS[1], hit.id.clone().map(Value::from)),
+        (META_FIELDS[2], hit.sort.clone().map(Value::from)),
+    ];
+    for (name, value) in meta {
+        if let Some(value) = value {
+            document.insert(name.to_string(), value);
+        }
+    }
+    Value::Object(document)
+}
+
+// Nested objects turned into dot-notation keys, arrays and empty objects kept as values
+fn flatten_fields(source: &Value) -> Value {
+    fn flatten_into(value: &Value, key: &str, flat: &mut serde_json::Map<String, Value>) {
+        match value {
+            Value::Object(fields) if key.is_empty() || !fields.is_empty() => {
+                for (name, value) in fields {
+                    let key = match key {
+                        "" => name.clone(),
+                        key => format!("{}.{}", key, name),
+                    };
+                    flatten_into(value, &key, flat);
+                }
+            }
+            value => {
+                flat.insert(key.to_string(), value.clone());
+            }
+        }
+    }
+    if !source.is_object() {
+        return source.clone();
+    }
+    let mut flat = serde_json::Map::new();
+    flatten_into(source, "", &mut flat);
+    Value::Object(flat)
+}
+
+// Strings as they are, other values as JSON, nothing for missing or null fields
+fn raw_value(source: &Value, field: &str) -> Option<String> {
+    match lookup_field(source, field)? {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+// Nested objects flattened to dotted keys, e.g. log.level=ERROR host.name=web-1
+fn logfmt_line(source: &Value) -> String {
+    let mut pairs = Vec::new();
+    logfmt_pairs(source, "", &mut pairs);
+    pairs.join(" ")
+}
+
+fn logfmt_pairs(value: &Value, key: &str, pairs: &mut Vec<String>) {
+    match value {
+        Value::Object(fields) if key.is_empty() || !fields.is_empty() => {
+            for (name, value) in fields {
+                let name = name.replace(|c: char| c.is_whitespace() || c == '=' || c == '"', "_");
+                let key = if key.is_empty() {
+                    name
+                } else {
+                    format!("{}.{}", key, name)
+                };
+                logfmt_pairs(value, &key, pairs);
+            }
+        }
+        Value::Null => pairs.push(format!("{}=", key)),
+        Value::String(text) => pairs.push(format!("{}={}", key, logfmt_value(text))),
+        value => pairs.push(format!("{}={}", key, logfmt_value(&value.to_string()))),
+    }
+}
+
+// Quoted when empty or holding spaces, quotes, '=' or control characters
+fn logfmt_value(value: &str) -> String {
+    if !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '=' | '"' | '\\'))
+    {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Describe the selected columns once, dropping duplicated fields
+fn legend_line(fields: &[String]) -> String {
+    let mut unique: Vec<&str> = Vec::new();
+    for field in fields {
+        if !unique.contains(&field.as_str()) {
+            unique.push(field);
+        }
+    }
+    format!("fields: {}", unique.join(", "))
+}
+
+fn heartbeat_line(last_event: Option<DateTime<Utc>>, tz: OutputTimezone) -> String {
+    match (last_event, tz) {
+        (Some(last), OutputTimezone::Utc) => {
+            format!("no new events (last: {})", tz.format(&last, "%H:%M:%SZ"))
+        }
+        (Some(last), OutputTimezone::Local) => {
+            format!("no new events (last: {})", tz.format(&last, "%H:%M:%S"))
+        }
+        (Some(last), OutputTimezone::Offset(_)) => {
+            format!("no new events (last: {})", tz.format(&last, "%H:%M:%S%:z"))
+        }
+        (None, _) => "no new events".to_string(),
+    }
+}
+
+fn gap_line(gap: chrono::Duration) -> String {
+    format!(
+        "----- {} gap -----",
+        format_duration(gap.num_milliseconds() as f64 / 1000.0)
+    )
+}
+
+// Documents fetched out of the total of a bounded extraction, with throughput and ETA, redrawn on stderr
+pub struct ProgressBar {
+    total: u64,
+    done: u64,
+    started: Instant,
+}
+
+impl ProgressBar {
+    // Bars share stderr only when the entries do not go to the same terminal
+    pub fn wanted(args: &OutputArgs) -> bool {
+        std::io::stderr().is_terminal()
+            && (args.output_file.is_some() || !std::io::stdout().is_terminal())
+    }
+
+    pub fn new(total: u64) -> Self {
+        Self {
+            total,
+            done: 0,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn advance(&mut self, docs: u64) {
+        self.done += docs;
+        eprint!(
+            "\r{}\x1b[K",
+            self.line(self.started.elapsed().as_secs_f64())
+        );
+    }
+
+    fn line(&self, elapsed: f64) -> String {
+        // Documents indexed since the count can take it past the total
+        let done = self.done.min(self.total);
+        let ratio = match self.total {
+            0 => 1.0,
+            total => done as f64 / total as f64,
+        };
+        let filled = (ratio * PROGRESS_BAR_WIDTH as f64) as usize;
+        let rate = if elapsed > 0.0 {
+            self.done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = if rate > 0.0 {
+            format_duration((self.total - done) as f64 / rate)
+        } else {
+            "unknown".to_string()
+        };
+        format!(
+            "[{}{}] {:>3}% {}/{} docs, {:.0} docs/s, ETA {}",
+            "#".repeat(filled),
+            "-".repeat(PROGRESS_BAR_WIDTH - filled),
+            (ratio * 100.0) as u64,
+            done,
+            self.total,
+            rate,
+            eta
+        )
+    }
+
+    pub fn finish(&self) {
+        eprint!("\r\x1b[K");
+    }
+}
+
+// Turning on stderr while a slow request is waited for, cleared when dropped
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    // Nothing is drawn when stderr is not a terminal
+    pub fn start(label: &str) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = std::io::stderr().is_terminal().then(|| {
+            let stop = stop.clone();
+            let label = label.to_string();
+            std::thread::spawn(move || {
+                for frame in SPINNER_FRAMES.iter().cycle() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    eprint!("\r{} {}\x1b[K", frame, label);
+                    std::thread::sleep(SPINNER_INTERVAL);
+                }
+                eprint!("\r\x1b[K");
+            })
+        });
+        Self { stop, handle }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// The file of --output-file, its progress redrawn on stderr when that is a terminal
+struct OutputFile {
+    path: PathBuf,
+    writer: BucketWriter,
+    lines: u64,
+    bytes: u64,
+    progress: bool,
+}
+
+impl OutputFile {
+    // Never over an existing file, it may be the capture of an earlier run
+    fn create(path: &Path) -> Result<Self, ESQError> {
+        let gzip = path.extension().is_some_and(|extension| extension == "gz");
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => ESQError::ValidationError(format!(
+                    "{} already exists, remove it or write to another file",
+                    path.display()
+                )),
+                _ => ESQError::IOError(e),
+            })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: BucketWriter::new(file, gzip),
+            lines: 0,
+            bytes: 0,
+            progress: std::io::stderr().is_terminal(),
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), ESQError> {
+        writeln!(self.writer.writer(), "{}", line)?;
+        self.lines += 1;
+        self.bytes += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn progress_line(&self) -> String {
+        format!(
+            "{} lines ({}) written to {}",
+            self.lines,
+            format_bytes(self.bytes),
+            self.path.display()
+        )
+    }
+
+    fn report_progress(&self) {
+        if self.progress {
+            eprint!("\r{}\x1b[K", self.progress_line());
+        }
+    }
+
+    fn close(self) -> Result<(), ESQError> {
+        if self.progress {
+            eprintln!("\r{}\x1b[K", self.progress_line());
+        }
+        self.writer.close()
+    }
+}
+
+// Renders hits to stdout, or to a sink, along with any requested decorations
+pub struct Printer {
+    gap_marker: Option<chrono::Duration>,
+    last_timestamp: Option<DateTime<Utc>>,
+    group_headers: Option<GroupBy>,
+    tz: OutputTimezone,
+    last_bucket: Option<String>,
+    color: bool,
+    number: bool,
+    verbose: bool,
+    line: u64,
+    batch: u64,
+    squash_fields: Option<Vec<String>>,
+    kibana_links: Option<String>,
+    legend: Option<String>,
+    header_interval: Option<u64>,
+    since_header: u64,
+    last_squash_key: Option<Vec<Value>>,
+    repeats: u64,
+    recorder: Option<Rc<SessionRecorder>>,
+    sink: Option<Box<dyn Sink>>,
+    snapshot: Option<Snapshotter>,
+    tag: Option<String>,
+    heartbeat: Option<Duration>,
+    time_format: Option<TimeFormat>,
+    template: Option<LineTemplate>,
+    missing: String,
+    logfmt: bool,
+    pretty: bool,
+    meta: bool,
+    flatten: bool,
+    // Key names shortened on JSON lines too wide for the terminal
+    abbreviations: Option<Abbreviations>,
+    // Decided by the first JSON line, so the keys read the same on every line of the session
+    abbreviating: Option<bool>,
+    width: Option<usize>,
+    raw: Option<String>,
+    table: Option<Table>,
+    output_file: Option<OutputFile>,
+    stdout: BufWriter<Stdout>,
+    quiet_since: Instant,
+    last_event: Option<DateTime<Utc>>,
+}
+
+impl Printer {
+    pub fn new(
+        args: &OutputArgs,
+        kibana_url: Option<String>,
+        recorder: Option<Rc<SessionRecorder>>,
+    ) -> Result<Self, ESQError> {
+        if args.hyperlinks && kibana_url.is_none() {
+            eprintln!("Warning: --hyperlinks needs kibana_url to be set in the configuration");
+        }
+        // A file gets the plain lines, whatever stdout is
+        let terminal = args.output_file.is_none() && std::io::stdout().is_terminal();
+        let kibana_links = kibana_url.filter(|_| args.hyperlinks && terminal);
+        let width = terminal_width().filter(|_| terminal);
+        let output_file = match &args.output_file {
+            Some(path) => Some(OutputFile::create(path)?),
+            None => None,
+        };
+
+        Ok(Self {
+            gap_marker: args
+                .gap_marker
+                .and_then(|gap| chrono::Duration::from_std(gap).ok()),
+            last_timestamp: None,
+            group_headers: args.group_headers,
+            tz: args.tz.unwrap_or(OutputTimezone::Local),
+            last_bucket: None,
+            color: args.color.enabled() && args.output_file.is_none(),
+            number: args.number,
+            verbose: args.verbose,
+            line: 0,
+            batch: 0,
+            squash_fields: args.squash.as_ref().map(|fields| {
+                fields
+                    .split(',')
+                    .map(|field| field.trim().to_string())
+                    .filter(|field| !field.is_empty())
+                    .collect()
+            }),
+            kibana_links,
+            legend: None,
+            header_interval: args.header_interval.filter(|interval| *interval > 0),
+            since_header: 0,
+            last_squash_key: None,
+            repeats: 0,
+            recorder,
+            sink: None,
+            snapshot: None,
+            tag: args.tag.clone(),
+            heartbeat: None,
+            // A zone given explicitly also applies to the timestamps of the entries
+            time_format: args
+                .time_format
+                .clone()
+                .or_else(|| args.tz.map(|_| TimeFormat::Rfc3339)),
+            template: args.template.clone(),
+            missing: args.missing.clone(),
+            logfmt: args.format == OutputFormat::Logfmt,
+            pretty: args.pretty,
+            meta: args.meta,
+            flatten: args.flatten,
+            abbreviations: (!args.no_abbrev).then(|| Abbreviations::new(&BTreeMap::new())),
+            abbreviating: None,
+            width,
+            raw: args.raw.clone(),
+            table: args.format.layout(width).map(Table::new),
+            output_file,
+            stdout: BufWriter::with_capacity(STDOUT_BUFFER_SIZE, std::io::stdout()),
+            quiet_since: Instant::now(),
+            last_event: None,
+        })
+    }
+
+    // Abbreviations of the configuration, over the built-in ones
+    pub fn set_abbreviations(&mut self, overrides: &BTreeMap<String, String>) {
+        if let Some(abbreviations) = &mut self.abbreviations {
+            *abbreviations = Abbreviations::new(overrides);
+        }
+    }
+
+    // A progress bar of the extraction takes over from the one of --output-file
+    pub fn hide_file_progress(&mut self) {
+        if let Some(file) = &mut self.output_file {
+            file.progress = false;
+        }
+    }
+
+    // Send hits to the sink instead of stdout
+    pub fn set_sink(&mut self, sink: Box<dyn Sink>) {
+        self.sink = Some(sink);
+    }
+
+    // Also write the documents to periodic snapshot files
+    pub fn set_snapshot(&mut self, snapshot: Snapshotter) {
+        self.snapshot = Some(snapshot);
+    }
+
+    // Print a status line whenever following stays quiet for this long
+    pub fn set_heartbeat(&mut self, every: Duration) {
+        self.heartbeat = Some(every);
+    }
+
+    // Print the terminal rendering while recording the plain line
+    fn emit_rendered(&mut self, line: &str, rendered: &str) -> Result<(), ESQError> {
+        if let Some(table) = &mut self.table {
+            table.push_text(line, rendered);
+            return Ok(());
+        }
+        self.write_line(line, rendered)
+    }
+
+    // The plain line goes to --output-file, the rendered one to stdout
+    fn write_line(&mut self, line: &str, rendered: &str) -> Result<(), ESQError> {
+        match &mut self.output_file {
+            Some(file) => file.write_line(line)?,
+            // One write per line, so the lines of parallel printers never mix
+            None => self
+                .stdout
+                .write_all(format!("{}\n", rendered).as_bytes())
+                .map_err(stdout_error)?,
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.record_output(line)?;
+        }
+        Ok(())
+    }
+
+    // Lines that annotate the output rather than carry documents, dimmed on terminals
+    fn emit_decoration(&mut self, line: &str) -> Result<(), ESQError> {
+        // Files written by a sink or to --output-file hold documents only
+        if self.sink.is_some() || self.output_file.is_some() {
+            return Ok(());
+        }
+        if let Some(table) = &mut self.table {
+            let rendered = if self.color {
+                paint(line, DIM)
+            } else {
+                line.to_string()
+            };
+            table.push_text(line, &rendered);
+            return Ok(());
+        }
+        if self.color {
+            self.write_line(line, &paint(line, DIM))
+        } else {
+            self.write_line(line, line)
+        }
+    }
+
+    // Only printed when asked for with --header-interval, it is not one of the documents
+    pub fn set_fields(&mut self, fields: &[String]) {
+        // The fields named by --select are printed under those names
+        self.abbreviations = None;
+        // A table names its columns in its header
+        if let Some(table) = &mut self.table {
+            if self.meta {
+                let columns: Vec<String> = META_FIELDS
+                    .iter()
+                    .map(|field| field.to_string())
+                    .chain(fields.iter().cloned())
+                    .collect();
+                table.set_columns(&columns);
+            } else {
+                table.set_columns(fields);
+            }
+            return;
+        }
+        // Raw values are meant for pipes, a legend would be read as one of them
+        if self.raw.is_none() && self.header_interval.is_some() {
+            self.legend = Some(legend_line(fields));
+        }
+    }
+
+    fn print_legend(&mut self) -> Result<(), ESQError> {
+        let (Some(legend), Some(interval)) = (&self.legend, self.header_interval) else {
+            return Ok(());
+        };
+        if self.since_header == 0 || self.since_header >= interval {
+            self.emit_decoration(&legend.clone())?;
+            self.since_header = 0;
+        }
+        self.since_header += 1;
+        Ok(())
+    }
+
+    pub fn verbose(&self) -> bool {
+        self.verbose
+    }
+
+    pub fn start_batch(&mut self) {
+        self.batch += 1;
+    }
+
+    pub fn batch(&self) -> u64 {
+        self.batch
+    }
+
+    // Report entries collapsed by --squash since the last printed one
+    pub fn flush_repeats(&mut self) -> Result<(), ESQError> {
+        if self.repeats > 0 {
+            self.emit_decoration(&repeat_line(self.repeats))?;
+            self.repeats = 0;
+        }
+        Ok(())
+    }
+
+    // Called when following returned nothing new, tells a quiet session from a stuck one
+    pub fn heartbeat(&mut self) -> Result<(), ESQError> {
+        let Some(every) = self.heartbeat else {
+            return Ok(());
+        };
+        // On stderr, a status is not part of the output
+        if self.quiet_since.elapsed() >= every {
+            let line = heartbeat_line(self.last_event, self.tz);
+            if std::io::stderr().is_terminal() {
+                eprintln!("{}", paint(&line, DIM));
+            } else {
+                eprintln!("{}", line);
+            }
+            self.quiet_since = Instant::now();
+        }
+        Ok(())
+    }
+
+    // Separate independent extractions, e.g. one block per --around instant
+    pub fn start_block(&mut self, label: &str) -> Result<(), ESQError> {
+        self.flush_repeats()?;
+        self.last_squash_key = None;
+        self.last_timestamp = None;
+        self.since_header = 0;
+        self.emit_decoration(&format!("===== {} =====", label))
+    }
+
+    // Print the rows of the batch, the columns being sized on the first one
+    fn flush_table(&mut self) -> Result<(), ESQError> {
+        let Some(table) = &mut self.table else {
+            return Ok(());
+        };
+        let mut header_prefix = String::new();
+        if let Some(tag) = &self.tag {
+            header_prefix = format!("[{}] ", tag);
+        }
+        if self.number {
+            header_prefix.push('\t');
+        }
+        for (line, rendered) in table.drain(&header_prefix) {
+            self.write_line(&line, &rendered)?;
+        }
+        Ok(())
+    }
+
+    // Called between batches, also when following returned nothing new
+    pub fn flush_sink(&mut self) -> Result<(), ESQError> {
+        self.flush_table()?;
+        self.stdout.flush().map_err(stdout_error)?;
+        if let Some(file) = &self.output_file {
+            file.report_progress();
+        }
+        if let Some(snapshot) = &mut self.snapshot {
+            snapshot.rotate_if_due()?;
+        }
+        match &mut self.sink {
+            Some(sink) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<(), ESQError> {
+        self.flush_repeats()?;
+        self.flush_table()?;
+        self.stdout.flush().map_err(stdout_error)?;
+        if let Some(file) = self.output_file.take() {
+            file.close()?;
+        }
+        if let Some(snapshot) = &mut self.snapshot {
+            snapshot.close()?;
+        }
+        match &mut self.sink {
+            Some(sink) => sink.finish(),
+            None => Ok(()),
+        }
+    }
+
+    fn compact_json(&mut self, source: &Value, prefix_width: usize) -> String {
+        let line = source.to_string();
+        let (Some(abbreviations), Some(width)) = (&self.abbreviations, self.width) else {
+            return line;
+        };
+        let abbreviating = *self
+            .abbreviating
+            .get_or_insert(prefix_width + line.chars().count() > width);
+        if abbreviating {
+            abbreviations.apply(source).to_string()
+        } else {
+            line
+        }
+    }
+
+    pub fn print_hit(&mut self, hit: &Hit) -> Result<(), ESQError> {
+        self.quiet_since = Instant::now();
+        self.last_event = hit_timestamp(hit).or(self.last_event);
+
+        // Snapshots keep every document, squashed or not
+        if let Some(snapshot) = &mut self.snapshot {
+            snapshot.write(&hit.source().to_string())?;
+        }
+
+        if let Some(fields) = &self.squash_fields {
+            let key = squash_key(hit.source(), fields);
+            if key.is_some() && key == self.last_squash_key {
+                self.repeats += 1;
+                return Ok(());
+            }
+            self.last_squash_key = key;
+            self.flush_repeats()?;
+        }
+
+        let timestamp = hit_timestamp(hit);
+
+        if let (Some(threshold), Some(previous), Some(current)) =
+            (self.gap_marker, self.last_timestamp, timestamp)
+        {
+            // Reversed output walks back in time
+            let gap = (current - previous).abs();
+            if gap > threshold {
+                self.emit_decoration(&gap_line(gap))?;
+            }
+        }
+
+        if let (Some(group_by), Some(current)) = (self.group_headers, timestamp) {
+            let bucket = bucket_label(&current, group_by, self.tz);
+            if self.last_bucket.as_ref() != Some(&bucket) {
+                self.emit_decoration(&format!("===== {} =====", bucket))?;
+                self.last_bucket = Some(bucket);
+            }
+        }
+        if timestamp.is_some() {
+            self.last_timestamp = timestamp;
+        }
+
+        self.print_legend()?;
+        self.line += 1;
+        // Sinks receive documents as stored, formatting is for reading
+        let formatted = match &self.time_format {
+            Some(format) if self.sink.is_none() => {
+                Some(format_time_field(hit.source(), format, self.tz))
+            }
+            _ => None,
+        };
+        let source = formatted.as_ref().unwrap_or(hit.source());
+        let mut prefix = String::new();
+        if self.number {
+            let batch = self.verbose.then_some(self.batch);
+            prefix = number_prefix(self.line, batch);
+        }
+
+        if let Some(sink) = &mut self.sink {
+            return sink.write(hit, &format!("{}{}", prefix, source));
+        }
+        if let Some(tag) = &self.tag {
+            prefix = format!("[{}] {}", tag, prefix);
+        }
+        let with_meta = self.meta.then(|| with_metadata(hit, source));
+        let source = with_meta.as_ref().unwrap_or(source);
+        let flattened = self.flatten.then(|| flatten_fields(source));
+        let source = flattened.as_ref().unwrap_or(source);
+        if let Some(table) = &mut self.table {
+            let color = level_color(source).filter(|_| self.color);
+            table.push_row(prefix, source, color);
+            return Ok(());
+        }
+        if self.pretty {
+            let line = format!("{}{}", prefix, pretty_json(source, false));
+            let colored = format!("{}{}", prefix, pretty_json(source, self.color));
+            return match &self.kibana_links {
+                Some(kibana_url) => {
+                    self.emit_rendered(&line, &render_links(kibana_url, hit, &colored))
+                }
+                None => self.emit_rendered(&line, &colored),
+            };
+        }
+        let body = if let Some(field) = &self.raw {
+            match raw_value(source, field) {
+                Some(value) => value,
+                None => return Ok(()),
+            }
+        } else if let Some(template) = &self.template {
+            template.render(source, &self.missing)
+        } else if self.logfmt {
+            logfmt_line(source)
+        } else {
+            self.compact_json(source, prefix.chars().count())
+        };
+        let line = format!("{}{}", prefix, body);
+        let rendered = match &self.kibana_links {
+            Some(kibana_url) => render_links(kibana_url, hit, &line),
+            None => line.clone(),
+        };
+        match level_color(source).filter(|_| self.color) {
+            Some(color) => self.emit_rendered(&line, &paint(&rendered, color)),
+            None => self.emit_rendered(&line, &rendered),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_hit(hit: Value) -> Hit {
+        serde_json::from_value(hit).unwrap()
+    }
+
+    #[test]
+    fn test_lookup_field() {
+        let source = json!({
+            "message": "hello",
+            "log": {"level": "ERROR"},
+            "kubernetes.pod": {"name": "api-1"}
+        });
+        assert_eq!(lookup_field(&source, "message"), Some(&json!("hello")));
+        assert_eq!(lookup_field(&source, "log.level"), Some(&json!("ERROR")));
+        assert_eq!(
+            lookup_field(&source, "kubernetes.pod.name"),
+            Some(&json!("api-1"))
+        );
+        assert_eq!(lookup_field(&source, "log.missing"), None);
+    }
+
+    #[test]
+    fn test_squash_key() {
+        let fields = vec!["message".to_string(), "level".to_string()];
+        assert_eq!(
+            squash_key(&json!({"message": "boom", "level": "ERROR"}), &fields),
+            Some(vec![json!("boom"), json!("ERROR")])
+        );
+        assert_eq!(squash_key(&json!({"other": 1}), &fields), None);
+    }
+
+    #[test]
+    fn test_heartbeat_line() {
+        let last = DateTime::parse_from_rfc3339("2024-06-01T12:01:33Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            heartbeat_line(Some(last), OutputTimezone::Utc),
+            "no new events (last: 12:01:33Z)"
+        );
+        assert_eq!(heartbeat_line(None, OutputTimezone::Utc), "no new events");
+        let paris = OutputTimezone::Offset(FixedOffset::east_opt(7200).unwrap());
+        assert_eq!(
+            heartbeat_line(Some(last), paris),
+            "no new events (last: 14:01:33+02:00)"
+        );
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        let offset = |secs| Ok(OutputTimezone::Offset(FixedOffset::east_opt(secs).unwrap()));
+        assert_eq!(parse_timezone("local"), Ok(OutputTimezone::Local));
+        assert_eq!(parse_timezone("UTC"), Ok(OutputTimezone::Utc));
+        assert_eq!(parse_timezone("+02:00"), offset(7200));
+        assert_eq!(parse_timezone("-0530"), offset(-19800));
+        assert_eq!(parse_timezone("UTC+2"), offset(7200));
+        assert_eq!(parse_timezone("+14:00"), offset(50400));
+        for invalid in [
+            "Europe/Paris",
+            "02:00",
+            "+25:00",
+            "+14:30",
+            "-14:59",
+            "+02:75",
+            "+",
+            "+1é1",
+        ] {
+            assert!(parse_timezone(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_time_format() {
+        assert_eq!(parse_time_format("relative"), Ok(TimeFormat::Relative));
+        assert_eq!(
+            parse_time_format("%H:%M:%S%.3f"),
+            Ok(TimeFormat::Pattern("%H:%M:%S%.3f".to_string()))
+        );
+        assert!(parse_time_format("%Q").is_err());
+    }
+
+    #[test]
+    fn test_format_time_field() {
+        let source = json!({"@timestamp": "2024-06-01T12:01:33.123456789Z", "message": "hi"});
+        let formatted = format_time_field(
+            &source,
+            &TimeFormat::Pattern("%H:%M:%S%.3f".to_string()),
+            OutputTimezone::Utc,
+        );
+        assert_eq!(
+            formatted,
+            json!({"@timestamp": "12:01:33.123", "message": "hi"})
+        );
+        let formatted = format_time_field(&source, &TimeFormat::EpochMs, OutputTimezone::Utc);
+        assert_eq!(formatted["@timestamp"], json!(1717243293123i64));
+        let new_york = parse_timezone("-04:00").unwrap();
+        let formatted = format_time_field(&source, &TimeFormat::Rfc3339, new_york);
+        assert_eq!(
+            formatted["@timestamp"],
+            json!("2024-06-01T08:01:33.123456789-04:00")
+        );
+        let now = DateTime::parse_from_rfc3339("2024-06-01T12:04:33Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let timestamp = DateTime::parse_from_rfc3339("2024-06-01T12:01:33Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            TimeFormat::Relative.render(&timestamp, OutputTimezone::Utc, now),
+            json!("3m 0s ago")
+        );
+    }
+
+    #[test]
+    fn test_repeat_line() {
+        assert_eq!(repeat_line(1), "last message repeated 1 time");
+        assert_eq!(repeat_line(12), "last message repeated 12 times");
+    }
+
+    #[test]
+    fn test_follow_stats_summary() {
+        let mut stats = FollowStats::new();
+        stats.observe(&json!({"level": "error", "service": {"name": "api"}}));
+        stats.observe(&json!({"log": {"level": "INFO"}, "service": "api"}));
+        stats.observe(&json!({"severity": "INFO", "service": "worker"}));
+        stats.observe(&json!({"message": "no level"}));
+
+        let summary = stats.summary();
+        assert_eq!(summary[1], "Documents: 4");
+        assert_eq!(summary[2], "By level: INFO 2, ERROR 1");
+        assert_eq!(summary[3], "Top services: api 2, worker 1");
+    }
+
+    #[test]
+    fn test_stats_panel() {
+        let mut stats = FollowStats::new();
+        stats.observe(&json!({"level": "error", "service": {"name": "api"}}));
+        stats.observe(&json!({"log": {"level": "INFO"}, "service": "api"}));
+        stats.observe(&json!({"severity": "INFO", "service": "worker"}));
+        stats.observe(&json!({"message": "no level"}));
+
+        let panel = stats.panel();
+        assert!(panel[0].starts_with("── 4 documents in "));
+        assert_eq!(
+            panel[1..],
+            ["levels    INFO 2, ERROR 1", "services  api 2, worker 1"]
+        );
+        assert_eq!(erase_lines(3), "\x1b[3F\x1b[J");
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        let mut bar = ProgressBar::new(200);
+        bar.done = 50;
+        assert_eq!(
+            bar.line(5.0),
+            "[#######-----------------------]  25% 50/200 docs, 10 docs/s, ETA 15s"
+        );
+        bar.done = 210;
+        assert!(
+            bar.line(5.0)
+                .starts_with("[##############################] 100% 200/200 docs")
+        );
+    }
+
+    #[test]
+    fn test_fields_as_source() {
+        let hit = parse_hit(json!({
+            "_id": "x1",
+            "fields": {"level": ["ERROR"], "tags": ["a", "b"]}
+        }));
+        let hit = fields_as_source(&hit);
+        assert_eq!(hit.source(), &json!({"level": "ERROR", "tags": ["a", "b"]}));
+        assert_eq!(hit.id.as_deref(), Some("x1"));
+    }
+
+    #[test]
+    fn test_hit_timestamp_from_source() {
+        let hit =
+            parse_hit(json!({"_source": {"@timestamp": "2024-06-01T10:00:00Z"}, "sort": [0]}));
+        assert_eq!(
+            hit_timestamp(&hit).unwrap().to_rfc3339(),
+            "2024-06-01T10:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_hit_timestamp_from_sort() {
+        let hit = parse_hit(json!({"_source": {"message": "hello"}, "sort": [1717236000000i64]}));
+        assert_eq!(
+            hit_timestamp(&hit).unwrap().to_rfc3339(),
+            "2024-06-01T10:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_bucket_label() {
+        let timestamp = DateTime::parse_from_rfc3339("2024-06-01T14:35:12Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            bucket_label(&timestamp, GroupBy::Hour, OutputTimezone::Utc),
+            "2024-06-01 14:00"
+        );
+        assert_eq!(
+            bucket_label(&timestamp, GroupBy::Day, OutputTimezone::Utc),
+            "2024-06-01"
+        );
+    }
+
+    #[test]
+    fn test_number_prefix() {
+        assert_eq!(number_prefix(42, None), "    42\t");
+        assert_eq!(number_prefix(3020, Some(4)), "4:3020\t");
+    }
+
+    #[test]
+    fn test_kibana_urls() {
+        assert_eq!(
+            kibana_trace_url("https://kibana.example.com/", "abc123"),
+            "https://kibana.example.com/app/apm/link-to/trace/abc123"
+        );
+        assert_eq!(
+            kibana_doc_url("https://kibana.example.com", "x1"),
+            "https://kibana.example.com/app/discover#/?_a=(query:(language:kuery,query:'_id%3A%22x1%22'))"
+        );
+    }
+
+    #[test]
+    fn test_render_links() {
+        let hit =
+            parse_hit(json!({"_id": "x1", "_source": {"trace.id": "abc123", "message": "hi"}}));
+        let line = hit.source().to_string();
+        let rendered = render_links("https://kibana", &hit, &line);
+        assert!(rendered.starts_with(&hyperlink(&kibana_doc_url("https://kibana", "x1"), "x1")));
+        assert!(rendered.contains(&hyperlink(
+            "https://kibana/app/apm/link-to/trace/abc123",
+            "abc123"
+        )));
+        assert!(rendered.contains("\"message\":\"hi\""));
+    }
+
+    #[test]
+    fn test_with_metadata() {
+        let hit = parse_hit(json!({
+            "_index": ".ds-logs-2024.05.01-000003",
+            "_id": "x1",
+            "_source": {"message": "hello"},
+            "sort": [1714557600000i64, 42]
+        }));
+        assert_eq!(
+            with_metadata(&hit, hit.source()),
+            json!({
+                "_index": ".ds-logs-2024.05.01-000003",
+                "_id": "x1",
+                "_sort": [1714557600000i64, 42],
+                "message": "hello"
+            })
+        );
+        let hit = parse_hit(json!({"_id": "x2"}));
+        assert_eq!(with_metadata(&hit, hit.source()), json!({"_id": "x2"}));
+    }
+
+    #[test]
+    fn test_field_matches() {
+        assert!(field_matches("kubernetes.*", "kubernetes.pod.name"));
+        assert!(field_matches("*.name", "host.name"));
+        assert!(field_matches("http.*.bytes", "http.response.body.bytes"));
+        assert!(field_matches("message", "message"));
+        assert!(!field_matches("kubernetes.*", "kubernetes"));
+        assert!(!field_matches("host.*", "hostname"));
+        assert!(!field_matches("mess", "message"));
+    }
+
+    #[test]
+    fn test_flatten_fields() {
+        let source = json!({
+            "kubernetes": {"pod": {"name": "api-1"}, "labels": {}},
+            "tags": [{"k": "v"}],
+            "message": "hi"
+        });
+        assert_eq!(
+            flatten_fields(&source),
+            json!({
+                "kubernetes.pod.name": "api-1",
+                "kubernetes.labels": {},
+                "tags": [{"k": "v"}],
+                "message": "hi"
+            })
+        );
+        // Dotted keys are still found by the fields of --select and --format
+        assert_eq!(
+            lookup_field(&flatten_fields(&source), "kubernetes.pod.name"),
+            Some(&json!("api-1"))
+        );
+    }
+
+    #[test]
+    fn test_raw_value() {
+        let source = json!({
+            "message": "GET /api \"quoted\"",
+            "http": {"response": {"status_code": 502}},
+            "kubernetes.labels": {"app": "api"},
+            "trace": null
+        });
+        assert_eq!(
+            raw_value(&source, "message").as_deref(),
+            Some("GET /api \"quoted\"")
+        );
+        assert_eq!(
+            raw_value(&source, "http.response.status_code").as_deref(),
+            Some("502")
+        );
+        assert_eq!(
+            raw_value(&source, "kubernetes.labels").as_deref(),
+            Some(r#"{"app":"api"}"#)
+        );
+        assert_eq!(raw_value(&source, "trace"), None);
+        assert_eq!(raw_value(&source, "missing"), None);
+    }
+
+    #[test]
+    fn test_logfmt_line() {
+        let source = json!({
+            "@timestamp": "2024-05-01T10:00:00Z",
+            "message": "user said \"hi\"\nthen left",
+            "log": {"level": "ERROR", "origin": {}},
+            "status": 500,
+            "tags": ["a", "b"],
+            "trace": null,
+            "empty": "",
+            "path": "a=b"
+        });
+        assert_eq!(
+            logfmt_line(&source),
+            r#"@timestamp=2024-05-01T10:00:00Z empty="" log.level=ERROR log.origin={} message="user said \"hi\"\nthen left" path="a=b" status=500 tags="[\"a\",\"b\"]" trace="#
+        );
+    }
+
+    #[test]
+    fn test_legend_line() {
+        let fields = vec![
+            "@timestamp".to_string(),
+            "level".to_string(),
+            "@timestamp".to_string(),
+            "message".to_string(),
+        ];
+        assert_eq!(legend_line(&fields), "fields: @timestamp, level, message");
+    }
+
+    #[test]
+    fn test_gap_line() {
+        assert_eq!(
+            gap_line(chrono::Duration::seconds(425)),
+            "----- 7m 5s gap -----"
+        );
+    }
+    #[test]
+    fn test_closed_stdout_ends_the_run() {
+        let closed = std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+        assert!(matches!(stdout_error(closed), ESQError::OutputClosed));
+        let full = std::io::Error::from(std::io::ErrorKind::StorageFull);
+        assert!(matches!(stdout_error(full), ESQError::IOError(_)));
+    }
+}
@@ -0,0 +1,189 @@
+// src/project.rs
+use crate::utils::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PROJECT_FILE: &str = ".esq.toml";
+
+// Query stored in the project file, run with `esq cat @name`
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SavedQuery {
+    pub index: Option<String>,
+    pub select: Option<String>,
+    #[serde(rename = "where")]
+    pub where_clause: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+// Settings of the repository esq runs from, so each service knows where its logs live
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub profile: Option<String>,
+    pub index: Option<String>,
+    pub time_field: Option<String>,
+    #[serde(default)]
+    pub queries: BTreeMap<String, SavedQuery>,
+}
+
+// Command-line values a project can fill in, None where the command has no such option
+pub struct QueryTarget<'a> {
+    pub index: &'a mut Option<String>,
+    pub select: Option<&'a mut Option<String>>,
+    pub where_clause: &'a mut Option<String>,
+    pub from: &'a mut Option<String>,
+    pub to: &'a mut Option<String>,
+}
+
+impl ProjectConfig {
+    // Closest .esq.toml in the directory or one of its parents
+    pub fn discover(dir: &Path) -> Result<Option<(PathBuf, Self)>, ESQError> {
+        for dir in dir.ancestors() {
+            let path = dir.join(PROJECT_FILE);
+            if path.is_file() {
+                let project = toml::from_str(&fs::read_to_string(&path)?).map_err(|e| {
+                    ESQError::ConfigError(format!("Invalid {}: {}", path.display(), e))
+                })?;
+                return Ok(Some((path, project)));
+            }
+        }
+        Ok(None)
+    }
+
+    // Expand @name into the saved query and default the index, options given explicitly win
+    pub fn apply(&self, target: QueryTarget) -> Result<(), ESQError> {
+        if let Some(name) = target
+            .index
+            .as_deref()
+            .and_then(|index| index.strip_prefix('@'))
+        {
+            let query = self.queries.get(name).ok_or_else(|| {
+                ESQError::ConfigError(format!(
+                    "No saved query named '{}' in {}",
+                    name, PROJECT_FILE
+                ))
+            })?;
+            *target.index = query.index.clone().or_else(|| self.index.clone());
+            if let Some(select) = target.select {
+                fill(select, &query.select);
+            }
+            fill(target.where_clause, &query.where_clause);
+            fill(target.from, &query.from);
+            fill(target.to, &query.to);
+        }
+        fill(target.index, &self.index);
+        require_index(target.index)?;
+        Ok(())
+    }
+}
+
+fn fill(value: &mut Option<String>, default: &Option<String>) {
+    if value.is_none() {
+        value.clone_from(default);
+    }
+}
+
+// Index of a command once the project defaults were applied
+pub fn require_index(index: &Option<String>) -> Result<&str, ESQError> {
+    index.as_deref().ok_or_else(|| {
+        ESQError::ValidationError(format!(
+            "No index given and no default index in {}",
+            PROJECT_FILE
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> ProjectConfig {
+        toml::from_str(
+            r#"
+            index = "checkout-*"
+            time_field = "event.created"
+
+            [queries.errors]
+            where = "level:ERROR"
+            select = "@timestamp,message"
+
+            [queries.payments]
+            index = "payments-*"
+            from = "1h"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_saved_query() {
+        let project = project();
+        let (mut index, mut select, mut where_clause, mut from, mut to) = (
+            Some("@errors".to_string()),
+            None,
+            None,
+            Some("2d".to_string()),
+            None,
+        );
+        project
+            .apply(QueryTarget {
+                index: &mut index,
+                select: Some(&mut select),
+                where_clause: &mut where_clause,
+                from: &mut from,
+                to: &mut to,
+            })
+            .unwrap();
+        assert_eq!(index.as_deref(), Some("checkout-*"));
+        assert_eq!(select.as_deref(), Some("@timestamp,message"));
+        assert_eq!(where_clause.as_deref(), Some("level:ERROR"));
+        assert_eq!(from.as_deref(), Some("2d"));
+        assert_eq!(to, None);
+    }
+
+    #[test]
+    fn test_apply_default_index() {
+        let project = project();
+        let (mut index, mut where_clause, mut from, mut to) = (None, None, None, None);
+        project
+            .apply(QueryTarget {
+                index: &mut index,
+                select: None,
+                where_clause: &mut where_clause,
+                from: &mut from,
+                to: &mut to,
+            })
+            .unwrap();
+        assert_eq!(index.as_deref(), Some("checkout-*"));
+
+        let mut index = Some("@missing".to_string());
+        assert!(
+            project
+                .apply(QueryTarget {
+                    index: &mut index,
+                    select: None,
+                    where_clause: &mut where_clause,
+                    from: &mut from,
+                    to: &mut to,
+                })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_discover() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let nested = root.join("services/checkout");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(PROJECT_FILE), "index = \"checkout-*\"\n").unwrap();
+
+        let (path, project) = ProjectConfig::discover(&nested).unwrap().unwrap();
+        assert_eq!(path, root.join(PROJECT_FILE));
+        assert_eq!(project.index.as_deref(), Some("checkout-*"));
+    }
+}
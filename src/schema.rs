@@ -0,0 +1,180 @@
+// src/schema.rs
+use crate::elasticsearch::model::Hit;
+use crate::sink::Sink;
+use crate::utils::*;
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+// Fields with more distinct values than this only report their types and null rate
+const LOW_CARDINALITY_LIMIT: usize = 20;
+
+#[derive(Default)]
+struct FieldStats {
+    types: BTreeSet<&'static str>,
+    non_null: u64,
+    // The values by their JSON text, which tells them apart; None once the field proved to have
+    // too many distinct values
+    distinct: Option<BTreeMap<String, Value>>,
+}
+
+// Fields, types, null rates and low cardinality values seen across exported documents
+#[derive(Default)]
+pub struct SchemaReport {
+    documents: u64,
+    fields: BTreeMap<String, FieldStats>,
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+impl SchemaReport {
+    pub fn observe(&mut self, source: &Value) {
+        self.documents += 1;
+        if let Some(source) = source.as_object() {
+            self.observe_object("", source);
+        }
+    }
+
+    fn observe_object(&mut self, prefix: &str, object: &Map<String, Value>) {
+        for (key, value) in object {
+            let path = format!("{}{}", prefix, key);
+            match value {
+                Value::Object(nested) => self.observe_object(&format!("{}.", path), nested),
+                _ => self.observe_leaf(path, value),
+            }
+        }
+    }
+
+    fn observe_leaf(&mut self, path: String, value: &Value) {
+        let stats = self.fields.entry(path).or_insert_with(|| FieldStats {
+            distinct: Some(BTreeMap::new()),
+            ..Default::default()
+        });
+        stats.types.insert(type_name(value));
+        if value.is_null() {
+            return;
+        }
+        stats.non_null += 1;
+        if let Some(distinct) = &mut stats.distinct {
+            distinct.insert(value.to_string(), value.clone());
+            if distinct.len() > LOW_CARDINALITY_LIMIT {
+                stats.distinct = None;
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let fields: Map<String, Value> = self
+            .fields
+            .iter()
+            .map(|(path, stats)| {
+                let null_rate = if self.documents == 0 {
+                    0.0
+                } else {
+                    1.0 - stats.non_null as f64 / self.documents as f64
+                };
+                let mut field = json!({
+                    "types": stats.types,
+                    "null_rate": (null_rate * 1000.0).round() / 1000.0,
+                });
+                if let Some(distinct) = &stats.distinct {
+                    field["distinct"] = json!(distinct.len());
+                    field["values"] = json!(distinct.values().collect::<Vec<_>>());
+                }
+                (path.clone(), field)
+            })
+            .collect();
+        json!({
+            "documents": self.documents,
+            "fields": fields,
+        })
+    }
+}
+
+// Passes hits through to another sink and writes the schema report next to its files
+pub struct SchemaSink {
+    inner: Box<dyn Sink>,
+    report: SchemaReport,
+    path: PathBuf,
+}
+
+impl SchemaSink {
+    pub fn new(inner: Box<dyn Sink>, path: &Path) -> Self {
+        Self {
+            inner,
+            report: SchemaReport::default(),
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Sink for SchemaSink {
+    fn write(&mut self, hit: &Hit, line: &str) -> Result<(), ESQError> {
+        self.report.observe(hit.source());
+        self.inner.write(hit, line)
+    }
+
+    fn flush(&mut self) -> Result<(), ESQError> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), ESQError> {
+        self.inner.finish()?;
+        let writer = BufWriter::new(File::create(&self.path)?);
+        serde_json::to_writer_pretty(writer, &self.report.to_json())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_report() {
+        let mut report = SchemaReport::default();
+        report.observe(&json!({"level": "INFO", "http": {"status": 200}, "user": null}));
+        report.observe(&json!({"level": "ERROR", "http": {"status": 500}}));
+        report.observe(&json!({"level": "INFO", "tags": ["a"]}));
+
+        let schema = report.to_json();
+        assert_eq!(schema["documents"], json!(3));
+        assert_eq!(
+            schema["fields"]["level"],
+            json!({
+                "types": ["string"],
+                "null_rate": 0.0,
+                "distinct": 2,
+                "values": ["ERROR", "INFO"]
+            })
+        );
+        assert_eq!(schema["fields"]["http.status"]["types"], json!(["number"]));
+        assert_eq!(schema["fields"]["http.status"]["values"], json!([200, 500]));
+        assert_eq!(schema["fields"]["http.status"]["null_rate"], json!(0.333));
+        assert_eq!(schema["fields"]["user"]["types"], json!(["null"]));
+        assert_eq!(schema["fields"]["user"]["null_rate"], json!(1.0));
+        assert_eq!(schema["fields"]["tags"]["types"], json!(["array"]));
+    }
+
+    #[test]
+    fn test_schema_report_high_cardinality() {
+        let mut report = SchemaReport::default();
+        for id in 0..=LOW_CARDINALITY_LIMIT {
+            report.observe(&json!({"id": id}));
+        }
+        let schema = report.to_json();
+        assert!(schema["fields"]["id"].get("distinct").is_none());
+        assert!(schema["fields"]["id"].get("values").is_none());
+    }
+}
@@ -0,0 +1,188 @@
+// src/sink/file.rs
+use crate::elasticsearch::model::Hit;
+use crate::output::hit_timestamp;
+use crate::sink::Sink;
+use crate::utils::*;
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const UNKNOWN_BUCKET: &str = "unknown";
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SplitBy {
+    Day,
+    Hour,
+    Index,
+}
+
+impl SplitBy {
+    // Name of the file a hit belongs to, days and hours are UTC
+    fn bucket(&self, hit: &Hit) -> String {
+        let bucket = match self {
+            SplitBy::Day => hit_timestamp(hit).map(|ts| ts.format("%Y-%m-%d").to_string()),
+            SplitBy::Hour => hit_timestamp(hit).map(|ts| ts.format("%Y-%m-%dT%H").to_string()),
+            SplitBy::Index => hit.index.clone(),
+        };
+        bucket.unwrap_or_else(|| UNKNOWN_BUCKET.to_string())
+    }
+}
+
+pub enum BucketWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl BucketWriter {
+    pub fn new(file: File, gzip: bool) -> Self {
+        if gzip {
+            BucketWriter::Gzip(GzEncoder::new(BufWriter::new(file), Compression::default()))
+        } else {
+            BucketWriter::Plain(BufWriter::new(file))
+        }
+    }
+
+    pub fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            BucketWriter::Plain(writer) => writer,
+            BucketWriter::Gzip(writer) => writer,
+        }
+    }
+
+    // Gzip files need their trailer, dropping the encoder would swallow write errors
+    pub fn close(self) -> Result<(), ESQError> {
+        match self {
+            BucketWriter::Plain(mut writer) => writer.flush()?,
+            BucketWriter::Gzip(writer) => writer.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+// NDJSON files in a directory, one per bucket when splitting
+pub struct FileSink {
+    dir: PathBuf,
+    split_by: Option<SplitBy>,
+    gzip: bool,
+    current: Option<(String, BucketWriter)>,
+    // Buckets already written, reopened in append mode when they come back
+    written: HashSet<String>,
+    // Paths of the files created, still readable once the sink is handed over
+    files: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl FileSink {
+    pub fn create(dir: &Path, split_by: Option<SplitBy>, gzip: bool) -> Result<Self, ESQError> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            split_by,
+            gzip,
+            current: None,
+            written: HashSet::new(),
+            files: Arc::default(),
+        })
+    }
+
+    pub fn files(&self) -> Arc<Mutex<Vec<PathBuf>>> {
+        self.files.clone()
+    }
+
+    fn path(&self, bucket: &str) -> PathBuf {
+        let extension = if self.gzip { "ndjson.gz" } else { "ndjson" };
+        self.dir.join(format!("{}.{}", bucket, extension))
+    }
+
+    // Only the current bucket stays open, extractions come out in timestamp order
+    fn writer(&mut self, bucket: &str) -> Result<&mut dyn Write, ESQError> {
+        if self.current.as_ref().map(|(current, _)| current.as_str()) != Some(bucket) {
+            self.close()?;
+            let path = self.path(bucket);
+            let file = if self.written.insert(bucket.to_string()) {
+                if let Ok(mut files) = self.files.lock() {
+                    files.push(path.clone());
+                }
+                File::create(&path)?
+            } else {
+                // Concatenated gzip members still decompress as one stream
+                OpenOptions::new().append(true).open(&path)?
+            };
+            self.current = Some((bucket.to_string(), BucketWriter::new(file, self.gzip)));
+        }
+        Ok(self.current.as_mut().unwrap().1.writer())
+    }
+
+    fn close(&mut self) -> Result<(), ESQError> {
+        match self.current.take() {
+            Some((_, writer)) => writer.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&mut self, hit: &Hit, line: &str) -> Result<(), ESQError> {
+        let bucket = match self.split_by {
+            Some(split_by) => split_by.bucket(hit),
+            None => "export".to_string(),
+        };
+        writeln!(self.writer(&bucket)?, "{}", line)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ESQError> {
+        self.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_hit(hit: serde_json::Value) -> Hit {
+        serde_json::from_value(hit).unwrap()
+    }
+
+    #[test]
+    fn test_split_buckets() {
+        let hit = parse_hit(json!({
+            "_index": "logs-2024.06.01",
+            "_source": {"@timestamp": "2024-06-01T10:30:00Z"}
+        }));
+        assert_eq!(SplitBy::Day.bucket(&hit), "2024-06-01");
+        assert_eq!(SplitBy::Hour.bucket(&hit), "2024-06-01T10");
+        assert_eq!(SplitBy::Index.bucket(&hit), "logs-2024.06.01");
+        assert_eq!(SplitBy::Day.bucket(&parse_hit(json!({}))), "unknown");
+    }
+
+    #[test]
+    fn test_file_sink_split_by_day() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("export");
+        let mut sink = FileSink::create(&dir, Some(SplitBy::Day), false).unwrap();
+        for (timestamp, line) in [
+            ("2024-06-01T23:59:00Z", "a"),
+            ("2024-06-02T00:01:00Z", "b"),
+            ("2024-06-01T12:00:00Z", "c"),
+        ] {
+            let hit = parse_hit(json!({"_source": {"@timestamp": timestamp}}));
+            sink.write(&hit, line).unwrap();
+        }
+        sink.finish().unwrap();
+
+        let first = fs::read_to_string(dir.join("2024-06-01.ndjson")).unwrap();
+        let second = fs::read_to_string(dir.join("2024-06-02.ndjson")).unwrap();
+        assert_eq!(first, "a\nc\n");
+        assert_eq!(second, "b\n");
+        assert_eq!(
+            *sink.files().lock().unwrap(),
+            [dir.join("2024-06-01.ndjson"), dir.join("2024-06-02.ndjson")]
+        );
+    }
+}
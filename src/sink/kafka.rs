@@ -0,0 +1,108 @@
+// src/sink/kafka.rs
+use crate::elasticsearch::model::Hit;
+use crate::sink::Sink;
+use crate::utils::*;
+use kafka::producer::{Producer, Record, RequiredAcks};
+use std::time::Duration;
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Produces each document as a record keyed by its ID, in batches
+pub struct KafkaSink {
+    producer: Producer,
+    topic: String,
+    batch_size: usize,
+    // (document ID, line) pairs waiting to be produced
+    buffer: Vec<(Option<String>, String)>,
+}
+
+// The crate sends an empty key as a null one, so a document without an ID gets no key
+// and the partitioner spreads it instead of sending every such record to one partition
+fn records<'a>(
+    topic: &'a str,
+    buffer: &'a [(Option<String>, String)],
+) -> Vec<Record<'a, &'a [u8], &'a str>> {
+    buffer
+        .iter()
+        .map(|(id, line)| {
+            let key = id.as_deref().map_or(&[][..], str::as_bytes);
+            Record::from_key_value(topic, key, line.as_str())
+        })
+        .collect()
+}
+
+impl KafkaSink {
+    pub fn new(brokers: &[String], topic: &str, batch_size: usize) -> Result<Self, ESQError> {
+        let producer = Producer::from_hosts(brokers.to_vec())
+            .with_ack_timeout(ACK_TIMEOUT)
+            .with_required_acks(RequiredAcks::One)
+            .create()
+            .map_err(|e| ESQError::NetworkError(format!("Failed to connect to Kafka: {}", e)))?;
+        Ok(Self {
+            producer,
+            topic: topic.to_string(),
+            batch_size,
+            buffer: Vec::new(),
+        })
+    }
+
+    fn send(&mut self) -> Result<(), ESQError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let records = records(&self.topic, &self.buffer);
+        let confirms = self
+            .producer
+            .send_all(&records)
+            .map_err(|e| ESQError::NetworkError(format!("Failed to produce to Kafka: {}", e)))?;
+        for confirm in confirms {
+            for partition in confirm.partition_confirms {
+                if let Err(code) = partition.offset {
+                    return Err(ESQError::NetworkError(format!(
+                        "Kafka rejected records for {}[{}]: {:?}",
+                        confirm.topic, partition.partition, code
+                    )));
+                }
+            }
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl Sink for KafkaSink {
+    fn write(&mut self, hit: &Hit, line: &str) -> Result<(), ESQError> {
+        self.buffer.push((hit.id.clone(), line.to_string()));
+        if self.buffer.len() >= self.batch_size {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), ESQError> {
+        self.send()
+    }
+
+    fn finish(&mut self) -> Result<(), ESQError> {
+        self.send()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records() {
+        let buffer = vec![
+            (Some("abc".to_string()), "{\"n\":1}".to_string()),
+            (None, "{\"n\":2}".to_string()),
+        ];
+        let records = records("logs", &buffer);
+        assert_eq!(records[0].key, b"abc");
+        assert_eq!(records[0].value, "{\"n\":1}");
+        assert_eq!(records[0].topic, "logs");
+        assert!(records[1].key.is_empty());
+        assert_eq!(records[1].value, "{\"n\":2}");
+    }
+}
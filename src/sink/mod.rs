@@ -0,0 +1,164 @@
+// src/sink/mod.rs
+pub mod file;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod webhook;
+
+use crate::elasticsearch::model::Hit;
+use crate::sink::webhook::WebhookSink;
+use crate::utils::*;
+use clap::{Args, ValueEnum};
+
+// Destination of rendered hits other than stdout
+pub trait Sink {
+    fn write(&mut self, hit: &Hit, line: &str) -> Result<(), ESQError>;
+
+    // Called after each batch of hits, so buffering sinks deliver while following
+    fn flush(&mut self) -> Result<(), ESQError> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ESQError>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SinkSpec {
+    Webhook(String),
+    Kafka { brokers: Vec<String>, topic: String },
+    S3 { bucket: String, prefix: String },
+}
+
+pub fn parse_sink_spec(input: &str) -> Result<SinkSpec, String> {
+    if let Some(url) = input.strip_prefix("webhook:") {
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Ok(SinkSpec::Webhook(url.to_string()));
+        }
+        return Err(format!("invalid webhook URL '{}'", url));
+    }
+    if let Some(target) = input.strip_prefix("kafka://") {
+        return match target.split_once('/') {
+            Some((brokers, topic)) if !brokers.is_empty() && !topic.is_empty() => {
+                Ok(SinkSpec::Kafka {
+                    brokers: brokers.split(',').map(str::to_string).collect(),
+                    topic: topic.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "invalid Kafka sink '{}', expected kafka://broker[,broker..]/topic",
+                input
+            )),
+        };
+    }
+    if let Some(target) = input.strip_prefix("s3://") {
+        let (bucket, prefix) = target.split_once('/').unwrap_or((target, ""));
+        if bucket.is_empty() {
+            return Err(format!(
+                "invalid S3 sink '{}', expected s3://bucket/prefix/",
+                input
+            ));
+        }
+        return Ok(SinkSpec::S3 {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        });
+    }
+    Err(format!(
+        "unknown sink '{}', expected webhook:<url>, kafka://broker/topic or s3://bucket/prefix/",
+        input
+    ))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum BatchFormat {
+    Ndjson,
+    Json,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SinkArgs {
+    /// Send matched documents to a sink instead of stdout (webhook:<url>, kafka://broker/topic, s3://bucket/prefix/)
+    #[arg(long, value_name = "sink", value_parser = parse_sink_spec)]
+    pub sink: Option<SinkSpec>,
+
+    /// Documents sent to the sink per request
+    #[arg(long, value_name = "count", default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub sink_batch_size: u32,
+
+    /// Body of webhook requests: NDJSON lines or a JSON array
+    #[arg(long, value_name = "format", default_value = "ndjson")]
+    pub sink_format: BatchFormat,
+
+    /// Retries of a failed delivery before giving up
+    #[arg(long, value_name = "count", default_value_t = 3)]
+    pub sink_retries: u32,
+}
+
+impl SinkArgs {
+    pub fn build(&self) -> Result<Option<Box<dyn Sink>>, ESQError> {
+        Ok(match &self.sink {
+            Some(SinkSpec::Webhook(url)) => Some(Box::new(WebhookSink::new(
+                url,
+                self.sink_batch_size as usize,
+                self.sink_format,
+                self.sink_retries,
+            )?)),
+            #[cfg(feature = "kafka")]
+            Some(SinkSpec::Kafka { brokers, topic }) => Some(Box::new(
+                crate::sink::kafka::KafkaSink::new(brokers, topic, self.sink_batch_size as usize)?,
+            )),
+            #[cfg(not(feature = "kafka"))]
+            Some(SinkSpec::Kafka { .. }) => {
+                return Err(ESQError::ConfigError(
+                    "esq was built without Kafka support, rebuild it with --features kafka"
+                        .to_string(),
+                ));
+            }
+            #[cfg(feature = "s3")]
+            Some(SinkSpec::S3 { bucket, prefix }) => {
+                Some(Box::new(crate::sink::s3::S3Sink::new(bucket, prefix)?))
+            }
+            #[cfg(not(feature = "s3"))]
+            Some(SinkSpec::S3 { .. }) => {
+                return Err(ESQError::ConfigError(
+                    "esq was built without S3 support, rebuild it with --features s3".to_string(),
+                ));
+            }
+            None => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sink_spec() {
+        assert_eq!(
+            parse_sink_spec("webhook:https://collector.example.com/logs"),
+            Ok(SinkSpec::Webhook(
+                "https://collector.example.com/logs".to_string()
+            ))
+        );
+        assert!(parse_sink_spec("webhook:collector").is_err());
+        assert_eq!(
+            parse_sink_spec("kafka://k1:9092,k2:9092/captured-logs"),
+            Ok(SinkSpec::Kafka {
+                brokers: vec!["k1:9092".to_string(), "k2:9092".to_string()],
+                topic: "captured-logs".to_string()
+            })
+        );
+        assert!(parse_sink_spec("kafka://k1:9092").is_err());
+        assert_eq!(
+            parse_sink_spec("s3://captures/logs/2024/"),
+            Ok(SinkSpec::S3 {
+                bucket: "captures".to_string(),
+                prefix: "logs/2024/".to_string()
+            })
+        );
+        assert!(parse_sink_spec("s3://").is_err());
+        assert!(parse_sink_spec("ftp://collector").is_err());
+    }
+}
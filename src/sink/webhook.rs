@@ -0,0 +1,143 @@
+// src/sink/webhook.rs
+use crate::elasticsearch::model::Hit;
+use crate::sink::{BatchFormat, Sink};
+use crate::utils::*;
+use serde_json::Value;
+use std::thread;
+use std::time::Duration;
+
+// POSTs batches of documents to an HTTP endpoint
+pub struct WebhookSink {
+    client: reqwest::blocking::Client,
+    url: String,
+    batch_size: usize,
+    format: BatchFormat,
+    retries: u32,
+    buffer: Vec<String>,
+}
+
+impl WebhookSink {
+    pub fn new(
+        url: &str,
+        batch_size: usize,
+        format: BatchFormat,
+        retries: u32,
+    ) -> Result<Self, ESQError> {
+        let client = reqwest::blocking::Client::builder()
+            .user_agent(concat!("esq/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            batch_size,
+            format,
+            retries,
+            buffer: Vec::new(),
+        })
+    }
+
+    fn send(&mut self) -> Result<(), ESQError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let (content_type, body) = batch_body(&self.buffer, self.format);
+
+        let mut attempt = 0;
+        loop {
+            let reason = match self
+                .client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, content_type)
+                .body(body.clone())
+                .send()
+            {
+                Ok(response) if response.status().is_success() => break,
+                // Only overload and server errors are worth another attempt
+                Ok(response)
+                    if !response.status().is_server_error()
+                        && response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    return Err(ESQError::NetworkError(format!(
+                        "Webhook rejected the batch with status {}",
+                        response.status()
+                    )));
+                }
+                Ok(response) => format!("status {}", response.status()),
+                Err(e) => e.to_string(),
+            };
+            if attempt >= self.retries {
+                return Err(ESQError::NetworkError(format!(
+                    "Webhook delivery failed after {} attempts: {}",
+                    attempt + 1,
+                    reason
+                )));
+            }
+            attempt += 1;
+            let delay = 1 << attempt.min(5);
+            eprintln!(
+                "Webhook delivery failed ({}). Retrying in {}s...",
+                reason, delay
+            );
+            thread::sleep(Duration::from_secs(delay));
+        }
+
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+fn batch_body(lines: &[String], format: BatchFormat) -> (&'static str, String) {
+    match format {
+        BatchFormat::Ndjson => {
+            let mut body = lines.join("\n");
+            body.push('\n');
+            ("application/x-ndjson", body)
+        }
+        BatchFormat::Json => {
+            // Lines are JSON documents unless decorated, e.g. by --number
+            let documents: Vec<Value> = lines
+                .iter()
+                .map(|line| {
+                    serde_json::from_str(line).unwrap_or_else(|_| Value::String(line.clone()))
+                })
+                .collect();
+            ("application/json", Value::Array(documents).to_string())
+        }
+    }
+}
+
+impl Sink for WebhookSink {
+    fn write(&mut self, _hit: &Hit, line: &str) -> Result<(), ESQError> {
+        self.buffer.push(line.to_string());
+        if self.buffer.len() >= self.batch_size {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), ESQError> {
+        self.send()
+    }
+
+    fn finish(&mut self) -> Result<(), ESQError> {
+        self.send()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_body() {
+        let lines = vec!["{\"a\":1}".to_string(), "{\"a\":2}".to_string()];
+        assert_eq!(
+            batch_body(&lines, BatchFormat::Ndjson),
+            ("application/x-ndjson", "{\"a\":1}\n{\"a\":2}\n".to_string())
+        );
+        assert_eq!(
+            batch_body(&lines, BatchFormat::Json),
+            ("application/json", "[{\"a\":1},{\"a\":2}]".to_string())
+        );
+    }
+}
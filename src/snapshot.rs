@@ -0,0 +1,114 @@
+// src/snapshot.rs
+use crate::utils::*;
+use chrono::{DateTime, Utc};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// An open snapshot, written under a temporary name until its period ends
+struct OpenSnapshot {
+    path: PathBuf,
+    partial: PathBuf,
+    writer: BufWriter<File>,
+    documents: u64,
+}
+
+// Cuts a follow session into NDJSON files, one per period holding the documents that arrived in it
+pub struct Snapshotter {
+    dir: PathBuf,
+    every: Duration,
+    period_start: DateTime<Utc>,
+    started: Instant,
+    current: Option<OpenSnapshot>,
+}
+
+fn snapshot_name(period_start: &DateTime<Utc>) -> String {
+    format!(
+        "esq-snapshot-{}.ndjson",
+        period_start.format("%Y%m%dT%H%M%SZ")
+    )
+}
+
+impl Snapshotter {
+    pub fn create(dir: &Path, every: Duration) -> Result<Self, ESQError> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            every,
+            period_start: Utc::now(),
+            started: Instant::now(),
+            current: None,
+        })
+    }
+
+    // Periods without documents leave no file
+    pub fn write(&mut self, line: &str) -> Result<(), ESQError> {
+        let snapshot = match &mut self.current {
+            Some(snapshot) => snapshot,
+            None => {
+                let path = self.dir.join(snapshot_name(&self.period_start));
+                let partial = path.with_extension("ndjson.partial");
+                let writer = BufWriter::new(File::create(&partial)?);
+                self.current.insert(OpenSnapshot {
+                    path,
+                    partial,
+                    writer,
+                    documents: 0,
+                })
+            }
+        };
+        writeln!(snapshot.writer, "{}", line)?;
+        snapshot.documents += 1;
+        Ok(())
+    }
+
+    // Close the snapshot once its period is over, called between batches
+    pub fn rotate_if_due(&mut self) -> Result<(), ESQError> {
+        if self.started.elapsed() < self.every {
+            return Ok(());
+        }
+        self.close()?;
+        self.period_start = Utc::now();
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    // Renamed only when complete, so readers of the directory never see half a snapshot
+    pub fn close(&mut self) -> Result<(), ESQError> {
+        if let Some(mut snapshot) = self.current.take() {
+            snapshot.writer.flush()?;
+            fs::rename(&snapshot.partial, &snapshot.path)?;
+            eprintln!(
+                "Snapshot {} ({} documents)",
+                snapshot.path.display(),
+                snapshot.documents
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshots() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("snapshots");
+        let mut snapshotter = Snapshotter::create(&dir, Duration::ZERO).unwrap();
+        snapshotter.write(r#"{"message":"a"}"#).unwrap();
+        snapshotter.write(r#"{"message":"b"}"#).unwrap();
+        let name = snapshot_name(&snapshotter.period_start);
+        assert!(!dir.join(&name).exists());
+
+        snapshotter.rotate_if_due().unwrap();
+        let content = fs::read_to_string(dir.join(&name)).unwrap();
+        assert_eq!(content, "{\"message\":\"a\"}\n{\"message\":\"b\"}\n");
+
+        // An empty period writes nothing
+        snapshotter.rotate_if_due().unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,419 @@
+// src/table.rs
+use crate::color::paint;
+use crate::output::{field_matches, lookup_field};
+use serde_json::Value;
+
+const COLUMN_GAP: &str = "  ";
+// Widest a column other than the last one gets, the last one takes the rest of the line
+const MAX_COLUMN_WIDTH: usize = 40;
+const MIN_LAST_COLUMN_WIDTH: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    // Padded columns for reading, fitted to the terminal width when known
+    Aligned(Option<usize>),
+    // One record per line for spreadsheets and other tools, e.g. CSV or TSV
+    Delimited(char),
+}
+
+enum TableLine {
+    Row {
+        prefix: String,
+        source: Value,
+        color: Option<&'static str>,
+    },
+    Text {
+        plain: String,
+        rendered: String,
+    },
+}
+
+// Entries rendered as rows of fields, the columns decided on the first batch then kept stable
+pub struct Table {
+    layout: Layout,
+    columns: Vec<String>,
+    widths: Vec<usize>,
+    started: bool,
+    pending: Vec<TableLine>,
+}
+
+fn cell(source: &Value, field: &str) -> String {
+    match lookup_field(source, field) {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+// Dotted paths of the values that are not objects, in order of appearance
+fn leaf_fields(value: &Value, path: &str, fields: &mut Vec<String>) {
+    match value.as_object() {
+        Some(object) if !object.is_empty() => {
+            for (key, value) in object {
+                let path = match path {
+                    "" => key.clone(),
+                    path => format!("{}.{}", path, key),
+                };
+                leaf_fields(value, &path, fields);
+            }
+        }
+        _ => {
+            if !path.is_empty() && !fields.iter().any(|field| field == path) {
+                fields.push(path.to_string());
+            }
+        }
+    }
+}
+
+fn fit(value: &str, width: usize) -> String {
+    if value.chars().count() <= width {
+        return value.to_string();
+    }
+    let cut: String = value.chars().take(width.saturating_sub(1)).collect();
+    format!("{}…", cut.trim_end())
+}
+
+// Quoted as RFC 4180 asks for CSV; TSV has no quoting, so separators become spaces
+fn delimited_field(value: &str, delimiter: char) -> String {
+    if delimiter == '\t' {
+        return value.replace(['\t', '\n', '\r'], " ");
+    }
+    if value.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Table {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            layout,
+            columns: Vec::new(),
+            widths: Vec::new(),
+            started: false,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn set_columns(&mut self, columns: &[String]) {
+        self.columns = Vec::new();
+        for column in columns {
+            if !self.columns.contains(column) {
+                self.columns.push(column.clone());
+            }
+        }
+    }
+
+    // The color is for aligned rows on terminals, delimited rows are read by other tools
+    pub fn push_row(&mut self, prefix: String, source: &Value, color: Option<&'static str>) {
+        self.pending.push(TableLine::Row {
+            prefix,
+            source: source.clone(),
+            color: color.filter(|_| matches!(self.layout, Layout::Aligned(_))),
+        });
+    }
+
+    // Decorations keep their place between aligned rows, delimited output holds records only
+    pub fn push_text(&mut self, plain: &str, rendered: &str) {
+        if let Layout::Aligned(_) = self.layout {
+            self.pending.push(TableLine::Text {
+                plain: plain.to_string(),
+                rendered: rendered.to_string(),
+            });
+        }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &Value> {
+        self.pending.iter().filter_map(|line| match line {
+            TableLine::Row { source, .. } => Some(source),
+            TableLine::Text { .. } => None,
+        })
+    }
+
+    // Without --select, the fields found in the first batch in order of appearance
+    fn union_of_fields(&self) -> Vec<String> {
+        let mut columns: Vec<String> = Vec::new();
+        for source in self.rows() {
+            for field in source
+                .as_object()
+                .into_iter()
+                .flat_map(|fields| fields.keys())
+            {
+                if !columns.contains(field) {
+                    columns.push(field.clone());
+                }
+            }
+        }
+        columns
+    }
+
+    // Wildcard columns become the fields of the first batch they match, e.g. kubernetes.pod.name,
+    // and so does a column only found as the prefix of dotted keys, as --flatten writes them
+    fn expand_columns(&self) -> Vec<String> {
+        let mut fields = Vec::new();
+        for source in self.rows() {
+            leaf_fields(source, "", &mut fields);
+        }
+        let mut columns: Vec<String> = Vec::new();
+        for column in &self.columns {
+            let prefix = format!("{}.", column);
+            let matched: Vec<&String> = if column.contains('*') {
+                fields
+                    .iter()
+                    .filter(|field| field_matches(column, field))
+                    .collect()
+            } else if self
+                .rows()
+                .all(|source| lookup_field(source, column).is_none())
+                && fields.iter().any(|field| field.starts_with(&prefix))
+            {
+                fields
+                    .iter()
+                    .filter(|field| field.starts_with(&prefix))
+                    .collect()
+            } else {
+                vec![column]
+            };
+            for field in matched {
+                if !columns.contains(field) {
+                    columns.push(field.clone());
+                }
+            }
+        }
+        columns
+    }
+
+    fn size_columns(&self, terminal_width: Option<usize>) -> Vec<usize> {
+        let mut widths: Vec<usize> = self
+            .columns
+            .iter()
+            .map(|column| column.chars().count())
+            .collect();
+        for source in self.rows() {
+            for (width, field) in widths.iter_mut().zip(&self.columns) {
+                *width = (*width).max(cell(source, field).chars().count());
+            }
+        }
+        let last = widths.len().saturating_sub(1);
+        for width in widths.iter_mut().take(last) {
+            *width = (*width).min(MAX_COLUMN_WIDTH);
+        }
+        if let (Some(terminal_width), Some(last_width)) = (terminal_width, widths.last()) {
+            let used: usize = widths[..last]
+                .iter()
+                .map(|width| width + COLUMN_GAP.len())
+                .sum();
+            let room = terminal_width
+                .saturating_sub(used)
+                .max(MIN_LAST_COLUMN_WIDTH);
+            widths[last] = (*last_width).min(room);
+        }
+        widths
+    }
+
+    fn render(&self, cells: &[String]) -> String {
+        match self.layout {
+            Layout::Aligned(_) => {
+                let last = cells.len().saturating_sub(1);
+                cells
+                    .iter()
+                    .zip(&self.widths)
+                    .enumerate()
+                    .map(|(i, (cell, width))| {
+                        let cell = fit(&cell.replace(['\n', '\r', '\t'], " "), *width);
+                        if i == last {
+                            cell
+                        } else {
+                            format!("{:<width$}", cell, width = width)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(COLUMN_GAP)
+            }
+            Layout::Delimited(delimiter) => cells
+                .iter()
+                .map(|cell| delimited_field(cell, delimiter))
+                .collect::<Vec<_>>()
+                .join(&delimiter.to_string()),
+        }
+    }
+
+    // Lines ready to print, as (plain, rendered), with the header before the first rows
+    pub fn drain(&mut self, header_prefix: &str) -> Vec<(String, String)> {
+        let mut lines = Vec::new();
+        if !self.started && self.rows().next().is_some() {
+            self.columns = if self.columns.is_empty() {
+                self.union_of_fields()
+            } else {
+                self.expand_columns()
+            };
+            if let Layout::Aligned(terminal_width) = self.layout {
+                self.widths = self.size_columns(terminal_width);
+            }
+            let header = format!("{}{}", header_prefix, self.render(&self.columns));
+            lines.push((header.clone(), header));
+            self.started = true;
+        }
+        for line in std::mem::take(&mut self.pending) {
+            match line {
+                TableLine::Row {
+                    prefix,
+                    source,
+                    color,
+                } => {
+                    let cells: Vec<String> = self
+                        .columns
+                        .iter()
+                        .map(|field| cell(&source, field))
+                        .collect();
+                    let row = format!("{}{}", prefix, self.render(&cells));
+                    let rendered = match color {
+                        Some(color) => paint(&row, color),
+                        None => row.clone(),
+                    };
+                    lines.push((row, rendered));
+                }
+                TableLine::Text { plain, rendered } => lines.push((plain, rendered)),
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn plain_lines(table: &mut Table) -> Vec<String> {
+        table
+            .drain("")
+            .into_iter()
+            .map(|(plain, _)| plain)
+            .collect()
+    }
+
+    #[test]
+    fn test_table_aligns_columns() {
+        let mut table = Table::new(Layout::Aligned(None));
+        table.set_columns(&["level".to_string(), "message".to_string()]);
+        table.push_row(
+            String::new(),
+            &json!({"level": "INFO", "message": "started"}),
+            None,
+        );
+        table.push_text("----- gap -----", "----- gap -----");
+        table.push_row(
+            String::new(),
+            &json!({"level": "ERROR", "message": "boom\nstack"}),
+            None,
+        );
+        assert_eq!(
+            plain_lines(&mut table),
+            vec![
+                "level  message",
+                "INFO   started",
+                "----- gap -----",
+                "ERROR  boom stack"
+            ]
+        );
+
+        // Later batches keep the widths of the first one
+        table.push_row(
+            String::new(),
+            &json!({"level": "CRITICAL", "message": "x"}),
+            Some("\x1b[31m"),
+        );
+        assert_eq!(
+            table.drain(""),
+            vec![(
+                "CRIT…  x".to_string(),
+                "\x1b[31mCRIT…  x\x1b[0m".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_table_fits_terminal() {
+        let mut table = Table::new(Layout::Aligned(Some(30)));
+        table.push_row(
+            String::new(),
+            &json!({"level": "INFO", "message": "a message much longer than the terminal"}),
+            None,
+        );
+        let lines = plain_lines(&mut table);
+        assert_eq!(lines[0], "level  message");
+        assert_eq!(lines[1], "INFO   a message much longer…");
+    }
+
+    #[test]
+    fn test_table_expands_wildcard_columns() {
+        let mut table = Table::new(Layout::Delimited(','));
+        table.set_columns(&["kubernetes.*".to_string(), "message".to_string()]);
+        table.push_row(
+            String::new(),
+            &json!({"kubernetes": {"pod": {"name": "api-1"}, "namespace": "prod"}, "message": "hi"}),
+            None,
+        );
+        assert_eq!(
+            plain_lines(&mut table),
+            vec![
+                "kubernetes.namespace,kubernetes.pod.name,message",
+                "prod,api-1,hi"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_expands_flattened_prefix_columns() {
+        let mut table = Table::new(Layout::Aligned(None));
+        table.set_columns(&["@timestamp".to_string(), "kubernetes".to_string()]);
+        table.push_row(
+            String::new(),
+            &json!({
+                "@timestamp": "2024-06-01T12:00:00Z",
+                "kubernetes.namespace": "prod",
+                "kubernetes.pod.name": "api-1"
+            }),
+            None,
+        );
+        assert_eq!(
+            plain_lines(&mut table),
+            vec![
+                "@timestamp            kubernetes.namespace  kubernetes.pod.name",
+                "2024-06-01T12:00:00Z  prod                  api-1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let mut table = Table::new(Layout::Delimited(','));
+        table.push_row(
+            String::new(),
+            &json!({"level": "INFO", "message": "a, b"}),
+            None,
+        );
+        table.push_text("----- gap -----", "----- gap -----");
+        table.push_row(
+            String::new(),
+            &json!({"message": "say \"hi\"\nbye", "host": {"name": "web-1"}}),
+            None,
+        );
+        assert_eq!(
+            plain_lines(&mut table),
+            vec![
+                "level,message,host",
+                "INFO,\"a, b\",",
+                ",\"say \"\"hi\"\"\nbye\",\"{\"\"name\"\":\"\"web-1\"\"}\""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tsv_fields() {
+        assert_eq!(delimited_field("a\tb\nc", '\t'), "a b c");
+        assert_eq!(delimited_field("plain", ','), "plain");
+    }
+}
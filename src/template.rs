@@ -0,0 +1,114 @@
+// src/template.rs
+use crate::output::lookup_field;
+use serde_json::Value;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+// A line such as "{@timestamp} [{level}] {message}", fields looked up by dot-path
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineTemplate {
+    parts: Vec<Part>,
+}
+
+// Braces are doubled to be printed as text, e.g. "{{" for "{"
+pub fn parse_template(input: &str) -> Result<LineTemplate, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some('{') | None => {
+                            return Err(format!("unclosed field '{{{}' in template", field));
+                        }
+                        Some(c) => field.push(c),
+                    }
+                }
+                let field = field.trim();
+                if field.is_empty() {
+                    return Err("empty field '{}' in template".to_string());
+                }
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(Part::Field(field.to_string()));
+            }
+            '}' => return Err("unmatched '}' in template, write '}}' to print one".to_string()),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+    if !parts.iter().any(|part| matches!(part, Part::Field(_))) {
+        return Err(format!("template '{}' has no {{field}}", input));
+    }
+    Ok(LineTemplate { parts })
+}
+
+impl LineTemplate {
+    // Strings are printed bare, other values as JSON and missing or null fields as the placeholder
+    pub fn render(&self, source: &Value, placeholder: &str) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => line.push_str(text),
+                Part::Field(field) => match lookup_field(source, field) {
+                    None | Some(Value::Null) => line.push_str(placeholder),
+                    Some(Value::String(value)) => line.push_str(value),
+                    Some(value) => line.push_str(&value.to_string()),
+                },
+            }
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_template() {
+        let template = parse_template("{@timestamp} [{log.level}] {message} {{{status}}}").unwrap();
+        let source = json!({
+            "@timestamp": "2024-05-01T10:00:00Z",
+            "log": {"level": "ERROR"},
+            "message": "boom",
+            "status": 500
+        });
+        assert_eq!(
+            template.render(&source, "-"),
+            "2024-05-01T10:00:00Z [ERROR] boom {500}"
+        );
+        assert_eq!(
+            template.render(&json!({"message": "hi", "status": null}), "<none>"),
+            "<none> [<none>] hi {<none>}"
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(parse_template("{message").is_err());
+        assert!(parse_template("{} text").is_err());
+        assert!(parse_template("message}").is_err());
+        assert!(parse_template("no fields").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+// src/usage.rs
+use crate::commands::sizeof::NumberFormat;
+use crate::utils::*;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const USAGE_FILE: &str = "usage.json";
+
+// Counted by every client of the run, whichever thread it lives on
+static DOCUMENTS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub fn record_documents(documents: u64) {
+    DOCUMENTS.fetch_add(documents, Ordering::Relaxed);
+}
+
+pub fn record_bytes(bytes: u64) {
+    BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+// Totals of one profile since the first run that used it
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ProfileUsage {
+    pub since: String,
+    pub commands: BTreeMap<String, u64>,
+    pub documents: u64,
+    pub bytes: u64,
+}
+
+// Kept in the esq directory only, nothing of it ever leaves the machine
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct UsageStats {
+    pub profiles: BTreeMap<String, ProfileUsage>,
+}
+
+impl UsageStats {
+    pub fn load(path: &Path) -> Result<Self, ESQError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn add(&mut self, profile: &str, command: &str, documents: u64, bytes: u64) {
+        let usage = self
+            .profiles
+            .entry(profile.to_string())
+            .or_insert_with(|| ProfileUsage {
+                since: Utc::now().to_rfc3339(),
+                ..ProfileUsage::default()
+            });
+        *usage.commands.entry(command.to_string()).or_default() += 1;
+        usage.documents += documents;
+        usage.bytes += bytes;
+    }
+
+    fn lines(&self, numbers: NumberFormat) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (profile, usage) in &self.profiles {
+            let commands: Vec<String> = usage
+                .commands
+                .iter()
+                .map(|(command, runs)| format!("{} {}", command, runs))
+                .collect();
+            lines.push(format!("{} (since {})", profile, usage.since));
+            lines.push(format!("  Commands:    {}", commands.join(", ")));
+            lines.push(format!("  Documents:   {}", numbers.count(usage.documents)));
+            lines.push(format!("  Transferred: {}", numbers.bytes(usage.bytes)));
+        }
+        lines
+    }
+}
+
+// Add this run to the totals, called once the command is over
+pub fn record_run(path: &Path, profile: &str, command: &str) -> Result<(), ESQError> {
+    let mut stats = UsageStats::load(path)?;
+    stats.add(
+        profile,
+        command,
+        DOCUMENTS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    );
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&stats)?)?;
+    Ok(())
+}
+
+pub fn handle_stats_command(path: &Path, numbers: NumberFormat) -> Result<(), ESQError> {
+    let stats = UsageStats::load(path)?;
+    if stats.profiles.is_empty() {
+        println!("No usage recorded yet");
+    }
+    for line in stats.lines(numbers) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_stats() {
+        let mut stats = UsageStats::default();
+        stats.add("default", "cat", 120, 4096);
+        stats.add("default", "cat", 30, 1024);
+        stats.add("prod", "export", 1000, 2048);
+
+        let default = &stats.profiles["default"];
+        assert_eq!(default.commands["cat"], 2);
+        assert_eq!(default.documents, 150);
+        assert_eq!(default.bytes, 5120);
+
+        let lines = stats.lines(NumberFormat::Human('.'));
+        assert_eq!(lines[1], "  Commands:    cat 2");
+        assert_eq!(lines[3], "  Transferred: 5.0 KiB");
+        assert!(lines[4].starts_with("prod (since "));
+        assert_eq!(lines[6], "  Documents:   1.0k");
+
+        let lines = stats.lines(NumberFormat::Raw);
+        assert_eq!(lines[3], "  Transferred: 5120");
+    }
+}
@@ -1,20 +1,98 @@
 // src/utils.rs
 //use crate::ESQError;
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, TimeZone, Utc, Weekday};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::IsTerminal;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Config {
+    // Shared config files merged under this one, e.g. distributed by a platform team
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // Aliases shared by a team, fetched over HTTP and cached, local aliases win on a name clash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliases_url: Option<String>,
     pub default: DefaultConfig,
+    // Other clusters, selected with --profile or by the project file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, DefaultConfig>,
+    // Local names for index patterns, managed with esq alias
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, LocalAlias>,
+    // Key abbreviations of JSON lines too wide for the terminal, over the built-in ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub abbreviations: BTreeMap<String, String>,
+}
+
+// Targets are "index" or "profile/index", a group of several is queried in parallel
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LocalAlias {
+    pub targets: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub select: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+}
+
+// Profile and index of an alias target, index names cannot contain '/'
+pub fn parse_alias_target(target: &str) -> (Option<&str>, &str) {
+    match target.split_once('/') {
+        Some((profile, index)) => (Some(profile), index),
+        None => (None, target),
+    }
+}
+
+impl Config {
+    // Configuration with the settings of the profile as its default ones
+    pub fn for_profile(&self, profile: Option<&str>) -> Result<Config, ESQError> {
+        let Some(name) = profile else {
+            return Ok(self.clone());
+        };
+        let settings = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| ESQError::ConfigError(format!("No profile named '{}'", name)))?;
+        Ok(Config {
+            default: settings.clone(),
+            ..self.clone()
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Default)]
 pub struct DefaultConfig {
     pub url: String,
+    // Other nodes of the cluster, tried in order when the current one cannot be reached
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<String>,
+    // Spread searches over the data nodes found with _nodes/http, for clusters reached directly
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sniff: bool,
     pub username: Option<String>,
     pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kibana_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geoip_db: Option<String>,
+    // SHA-256 of the certificate of url, pinned by esq login and required of every node connected to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_fingerprint: Option<String>,
+}
+
+impl DefaultConfig {
+    // The url first, then the failover nodes
+    pub fn node_urls(&self) -> Vec<&str> {
+        std::iter::once(self.url.as_str())
+            .chain(self.nodes.iter().map(String::as_str))
+            .collect()
+    }
 }
 
 //Custom Error
@@ -27,8 +105,15 @@ pub enum ESQError {
     DateParseError(String),
     IOError(std::io::Error),
     ValidationError(String),
+    AssertionError(String),
     ESError(String),
-    NotYetImplemented(String),
+    TooManyRequests(Option<Duration>),
+    CircuitBreaking(String),
+    // An aggregation asked for more buckets than search.max_buckets, with the limit when reported
+    TooManyBuckets(Option<u64>),
+    Interrupted,
+    // Whoever read stdout went away, e.g. `esq cat | head`; the run ends without failing
+    OutputClosed,
 }
 
 // Error conversions
@@ -65,45 +150,192 @@ impl From<toml::ser::Error> for ESQError {
 // Error display
 impl std::fmt::Display for ESQError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ESQError::ConfigError(msg) => write!(f, "Configuration error: {}", msg),
-            ESQError::AuthError => write!(f, "Authentication failed"),
-            ESQError::NetworkError(msg) => write!(f, "Network error: {}", msg),
-            ESQError::ParseError(msg) => write!(f, "Parse error: {}", msg),
-            ESQError::DateParseError(msg) => write!(
-                f,
+        // Messages may quote URLs, headers or responses, never with their credentials
+        let message = match self {
+            ESQError::ConfigError(msg) => format!("Configuration error: {}", msg),
+            ESQError::AuthError => "Authentication failed".to_string(),
+            ESQError::NetworkError(msg) => format!("Network error: {}", msg),
+            ESQError::ParseError(msg) => format!("Parse error: {}", msg),
+            ESQError::DateParseError(msg) => format!(
                 "Date error: {}\nSee accepted formats at https://docs.rs/dateparser/latest/dateparser/#accepted-date-formats",
                 msg
             ),
-            ESQError::IOError(err) => write!(f, "IO error: {}", err),
-            ESQError::ValidationError(msg) => write!(f, "Validation error: {}", msg),
-            ESQError::ESError(msg) => write!(f, "Elasticsearch error: {}", msg),
-            ESQError::NotYetImplemented(msg) => write!(f, "Not yet implemented: {}", msg),
-        }
+            ESQError::IOError(err) => format!("IO error: {}", err),
+            ESQError::ValidationError(msg) => format!("Validation error: {}", msg),
+            ESQError::AssertionError(msg) => format!("Assertion failed: {}", msg),
+            ESQError::ESError(msg) => format!("Elasticsearch error: {}", msg),
+            ESQError::CircuitBreaking(msg) => format!("Elasticsearch circuit breaker: {}", msg),
+            ESQError::TooManyRequests(_) => {
+                "Elasticsearch rejected the request (429 Too Many Requests)".to_string()
+            }
+            ESQError::TooManyBuckets(Some(limit)) => format!(
+                "Elasticsearch error: the aggregation needs more buckets than search.max_buckets ({})",
+                limit
+            ),
+            ESQError::TooManyBuckets(None) => {
+                "Elasticsearch error: the aggregation needs more buckets than search.max_buckets"
+                    .to_string()
+            }
+            ESQError::Interrupted => "Interrupted".to_string(),
+            ESQError::OutputClosed => "Output closed".to_string(),
+        };
+        f.write_str(&crate::redact::redact(&message))
     }
 }
 
 impl std::error::Error for ESQError {}
 
-pub fn load_config(config_file: &PathBuf) -> Result<Option<Config>, ESQError> {
+const DEFAULT_TIME_FIELD: &str = "@timestamp";
+static TIME_FIELD: OnceLock<String> = OnceLock::new();
+
+// Field holding the event time, @timestamp unless the project pins another one
+pub fn time_field() -> &'static str {
+    TIME_FIELD.get().map_or(DEFAULT_TIME_FIELD, String::as_str)
+}
+
+pub fn set_time_field(field: &str) {
+    let _ = TIME_FIELD.set(field.to_string());
+}
+
+// Zone of --tz, where today or monday start; the local one when unset
+static CALENDAR_OFFSET: OnceLock<FixedOffset> = OnceLock::new();
+
+pub fn set_calendar_offset(offset: FixedOffset) {
+    let _ = CALENDAR_OFFSET.set(offset);
+}
+
+pub fn load_config(config_file: &Path) -> Result<Option<Config>, ESQError> {
     if config_file.exists() {
-        let content = fs::read_to_string(config_file)?;
-        let config = toml::from_str(&content)?;
+        let table = read_config_table(config_file, &mut Vec::new())?;
+        let config = table.try_into()?;
         Ok(Some(config))
     } else {
         Ok(None)
     }
 }
 
-pub fn save_config(config: &Config, config_file: &PathBuf) -> Result<(), ESQError> {
-    if let Some(parent_dir) = config_file.parent() {
-        if !parent_dir.exists() {
-            fs::create_dir_all(parent_dir)?;
-            set_dir_permissions(parent_dir)?;
+// Settings of a run that may not touch the disk, read from ESQ_URL and the other ESQ_ variables
+pub fn config_from_env(var: impl Fn(&str) -> Option<String>) -> Result<Config, ESQError> {
+    let var = |name: &str| var(name).filter(|value| !value.is_empty());
+    let url = var("ESQ_URL").ok_or_else(|| {
+        ESQError::ConfigError(
+            "--no-config reads the cluster URL from ESQ_URL, which is not set".to_string(),
+        )
+    })?;
+    let nodes = var("ESQ_NODES")
+        .map(|nodes| {
+            nodes
+                .split(',')
+                .map(str::trim)
+                .filter(|node| !node.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Config {
+        default: DefaultConfig {
+            url,
+            nodes,
+            username: var("ESQ_USERNAME"),
+            password: var("ESQ_PASSWORD"),
+            kibana_url: var("ESQ_KIBANA_URL"),
+            geoip_db: var("ESQ_GEOIP_DB"),
+            tls_fingerprint: var("ESQ_TLS_FINGERPRINT"),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+// A config file merged over the files it includes, recursively
+fn read_config_table(path: &Path, chain: &mut Vec<PathBuf>) -> Result<toml::Table, ESQError> {
+    let canonical = fs::canonicalize(path).map_err(|e| {
+        ESQError::ConfigError(format!("Cannot read config {}: {}", path.display(), e))
+    })?;
+    if chain.contains(&canonical) {
+        return Err(ESQError::ConfigError(format!(
+            "Config {} includes itself",
+            path.display()
+        )));
+    }
+    let table: toml::Table = toml::from_str(&fs::read_to_string(path)?)?;
+    chain.push(canonical);
+    let mut merged = included_table(&table, path, chain)?;
+    chain.pop();
+    merge_tables(&mut merged, table);
+    Ok(merged)
+}
+
+// Merge of the files included by a config, later includes taking precedence
+fn included_table(
+    table: &toml::Table,
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> Result<toml::Table, ESQError> {
+    let mut merged = toml::Table::new();
+    let Some(includes) = table.get("include") else {
+        return Ok(merged);
+    };
+    let includes = includes.as_array().ok_or_else(|| {
+        ESQError::ConfigError(format!(
+            "include must be a list of files in {}",
+            path.display()
+        ))
+    })?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for include in includes {
+        let include = include.as_str().ok_or_else(|| {
+            ESQError::ConfigError(format!(
+                "include must be a list of files in {}",
+                path.display()
+            ))
+        })?;
+        // Relative includes are resolved against the including file
+        let mut included = read_config_table(&dir.join(include), chain)?;
+        included.remove("include");
+        merge_tables(&mut merged, included);
+    }
+    Ok(merged)
+}
+
+// Values of the overlay win, tables are merged key by key
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// Drop the values equal to the included ones, so updates of shared files keep applying
+fn strip_inherited(table: &mut toml::Table, base: &toml::Table) {
+    table.retain(|key, value| match (base.get(key), value) {
+        (Some(toml::Value::Table(base)), toml::Value::Table(table)) => {
+            strip_inherited(table, base);
+            true
         }
+        (Some(base), value) => base != value,
+        (None, _) => true,
+    });
+}
+
+pub fn save_config(config: &Config, config_file: &PathBuf) -> Result<(), ESQError> {
+    if let Some(parent_dir) = config_file.parent()
+        && !parent_dir.exists()
+    {
+        fs::create_dir_all(parent_dir)?;
+        set_dir_permissions(parent_dir)?;
     }
 
-    let toml = toml::to_string(&config)?;
+    let mut table = toml::Table::try_from(config)?;
+    let base = included_table(&table, config_file, &mut Vec::new())?;
+    strip_inherited(&mut table, &base);
+    let toml = toml::to_string(&table)?;
     fs::write(config_file, toml)?;
 
     let metadata = fs::metadata(config_file)?;
@@ -113,7 +345,7 @@ pub fn save_config(config: &Config, config_file: &PathBuf) -> Result<(), ESQErro
     Ok(())
 }
 
-fn set_dir_permissions(dir: &Path) -> Result<(), ESQError> {
+pub fn set_dir_permissions(dir: &Path) -> Result<(), ESQError> {
     let metadata = fs::metadata(dir)?;
     let mut perms = metadata.permissions();
     perms.set_mode(0o700);
@@ -131,3 +363,473 @@ pub fn add_auth(
         request
     }
 }
+
+pub fn format_duration(secs: f64) -> String {
+    if secs < 1.0 {
+        return "<1s".to_string();
+    }
+    let secs = secs.round() as u64;
+    let (hours, minutes, seconds) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+// Columns of the terminal behind stdout, None when the output is not one. COLUMNS stands in
+// where the terminal cannot be asked
+pub fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    terminal_columns(STDOUT_FD).or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+}
+
+// The same for the terminal behind stderr, where the progress and panels are drawn
+pub fn stderr_width() -> Option<usize> {
+    if !std::io::stderr().is_terminal() {
+        return None;
+    }
+    terminal_columns(STDERR_FD).or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+}
+
+const STDOUT_FD: i32 = 1;
+const STDERR_FD: i32 = 2;
+
+#[cfg(unix)]
+fn terminal_columns(fd: i32) -> Option<usize> {
+    // SAFETY: TIOCGWINSZ only fills in the winsize it is given
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+    (result == 0 && size.ws_col > 0).then(|| size.ws_col.into())
+}
+
+#[cfg(not(unix))]
+fn terminal_columns(_fd: i32) -> Option<usize> {
+    None
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+type InterruptHook = Box<dyn Fn() + Send>;
+// One hook per blocking call in flight, parallel extractions each adding their own
+static INTERRUPT_HOOKS: Mutex<BTreeMap<u64, InterruptHook>> = Mutex::new(BTreeMap::new());
+static NEXT_INTERRUPT_HOOK: AtomicU64 = AtomicU64::new(0);
+
+static HANDLER_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Turn the first Ctrl-C into a flag polled by long-running loops, a second one exits at once.
+// Extractions running in parallel share the handler.
+pub fn install_interrupt_handler() -> Result<(), ESQError> {
+    if HANDLER_INSTALLED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        run_interrupt_hooks();
+    })
+    .map_err(|e| ESQError::ConfigError(format!("Failed to install Ctrl-C handler: {}", e)))
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+fn run_interrupt_hooks() {
+    if let Ok(hooks) = INTERRUPT_HOOKS.lock() {
+        for hook in hooks.values() {
+            hook();
+        }
+    }
+}
+
+// Removes its hook when the blocking call is over
+pub struct InterruptHookGuard(u64);
+
+impl Drop for InterruptHookGuard {
+    fn drop(&mut self) {
+        if let Ok(mut hooks) = INTERRUPT_HOOKS.lock() {
+            hooks.remove(&self.0);
+        }
+    }
+}
+
+// Run on the first Ctrl-C while a blocking call that cannot poll interrupted() is in flight
+pub fn add_interrupt_hook(hook: InterruptHook) -> InterruptHookGuard {
+    let id = NEXT_INTERRUPT_HOOK.fetch_add(1, Ordering::SeqCst);
+    if let Ok(mut hooks) = INTERRUPT_HOOKS.lock() {
+        hooks.insert(id, hook);
+    }
+    InterruptHookGuard(id)
+}
+
+// Parse a short duration such as "30s", "10m", "2h", "1d" or "1w"
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let input = input.trim();
+    // The unit may be any character, split on its boundary
+    let (split, _) = input.char_indices().last()?;
+    let (amount, unit) = input.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 604800,
+        _ => return None,
+    };
+    Some(Duration::from_secs(amount.checked_mul(unit_secs)?))
+}
+
+pub fn parse_duration_arg(input: &str) -> Result<Duration, String> {
+    parse_duration(input).ok_or_else(|| {
+        format!(
+            "invalid duration '{}', expected a number followed by s, m, h, d or w",
+            input
+        )
+    })
+}
+
+// Start of a day named the way people do, or of an ISO week (2024-W25), in the zone of `now`
+fn calendar_shorthand<Tz: TimeZone>(input: &str, now: &DateTime<Tz>) -> Option<DateTime<Utc>> {
+    let input = input.trim().to_lowercase();
+    let today = now.date_naive();
+    let day = match input.as_str() {
+        "today" => today,
+        "yesterday" => today.pred_opt()?,
+        _ => match input.parse::<Weekday>() {
+            // The latest such day, today included
+            Ok(weekday) => {
+                let back = (7 + today.weekday().num_days_from_monday()
+                    - weekday.num_days_from_monday())
+                    % 7;
+                today - chrono::Duration::days(back.into())
+            }
+            Err(_) => {
+                let (year, week) = input.split_once("-w")?;
+                NaiveDate::from_isoywd_opt(year.parse().ok()?, week.parse().ok()?, Weekday::Mon)?
+            }
+        },
+    };
+    let midnight = now
+        .timezone()
+        .from_local_datetime(&day.and_hms_opt(0, 0, 0)?)
+        .earliest()?;
+    Some(midnight.with_timezone(&Utc))
+}
+
+// Parse an absolute datetime, or a duration relative to now ("10m" means ten minutes ago)
+pub fn parse_datetime(input: &str) -> Result<DateTime<Utc>, ESQError> {
+    if let Some(duration) = parse_duration(input) {
+        let delta = chrono::Duration::from_std(duration)
+            .map_err(|e| ESQError::DateParseError(e.to_string()))?;
+        return Ok(Utc::now() - delta);
+    }
+    let start = match CALENDAR_OFFSET.get() {
+        Some(offset) => calendar_shorthand(input, &Utc::now().with_timezone(offset)),
+        None => calendar_shorthand(input, &Local::now()),
+    };
+    if let Some(start) = start {
+        return Ok(start);
+    }
+    dateparser::parse(input).map_err(|e| ESQError::DateParseError(e.to_string()))
+}
+
+// A --from after --to can only match nothing, fail unless asked to swap them
+pub fn order_time_range(
+    from: &mut Option<String>,
+    to: &mut Option<String>,
+    swap: bool,
+) -> Result<(), ESQError> {
+    let (Some(from_str), Some(to_str)) = (from.as_deref(), to.as_deref()) else {
+        return Ok(());
+    };
+    // Unparseable bounds are reported by the query itself
+    let (Ok(from_dt), Ok(to_dt)) = (parse_datetime(from_str), parse_datetime(to_str)) else {
+        return Ok(());
+    };
+    if from_dt <= to_dt {
+        return Ok(());
+    }
+    if !swap {
+        return Err(ESQError::ValidationError(format!(
+            "--from {} ({}) is after --to {} ({}), swap them or pass --swap-ok",
+            from_str,
+            from_dt.to_rfc3339(),
+            to_str,
+            to_dt.to_rfc3339()
+        )));
+    }
+    std::mem::swap(from, to);
+    Ok(())
+}
+
+// Check a comma-separated index expression, where "-pattern" excludes indices matched so far
+pub fn validate_index_pattern(pattern: &str) -> Result<(), ESQError> {
+    let mut has_wildcard = false;
+    for part in pattern.split(',').map(str::trim) {
+        match part.strip_prefix('-') {
+            Some("") => {
+                return Err(ESQError::ValidationError(format!(
+                    "Empty exclusion in index pattern '{}'",
+                    pattern
+                )));
+            }
+            // Without a wildcard before it, Elasticsearch reads "-name" as a literal index name
+            Some(excluded) if !has_wildcard => {
+                return Err(ESQError::ValidationError(format!(
+                    "Exclusion '-{}' must follow a wildcard pattern such as 'logs-*'",
+                    excluded
+                )));
+            }
+            Some(_) => {}
+            None if part.is_empty() => {
+                return Err(ESQError::ValidationError(format!(
+                    "Empty index name in pattern '{}'",
+                    pattern
+                )));
+            }
+            None => has_wildcard |= part.contains('*'),
+        }
+    }
+    Ok(())
+}
+
+// Levenshtein distance over characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Closest candidates to a mistyped name, at most 3 and only reasonably close ones
+pub fn suggestions<'a>(name: &str, candidates: &'a [String]) -> Vec<&'a str> {
+    let threshold = (name.chars().count() / 3).max(2);
+    let mut scored: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate.as_str()))
+        .filter(|(distance, _)| *distance <= threshold)
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .take(3)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_interrupt_hooks() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hook = |calls: &Arc<AtomicUsize>| -> InterruptHook {
+            let calls = calls.clone();
+            Box::new(move || {
+                calls.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        let first = add_interrupt_hook(hook(&calls));
+        let second = add_interrupt_hook(hook(&calls));
+        run_interrupt_hooks();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // A search ending leaves the hook of the other in place
+        drop(first);
+        run_interrupt_hooks();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        drop(second);
+        run_interrupt_hooks();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("10m"), Some(Duration::from_secs(600)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("1w"), Some(Duration::from_secs(604800)));
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration("10y"), None);
+        assert_eq!(parse_duration("2024-01-01"), None);
+        assert_eq!(parse_duration("10é"), None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0.2), "<1s");
+        assert_eq!(format_duration(42.0), "42s");
+        assert_eq!(format_duration(125.0), "2m 5s");
+        assert_eq!(format_duration(3725.0), "1h 2m 5s");
+    }
+
+    #[test]
+    fn test_order_time_range() {
+        let mut from = Some("2024-06-02T00:00:00Z".to_string());
+        let mut to = Some("2024-06-01T00:00:00Z".to_string());
+        assert!(order_time_range(&mut from, &mut to, false).is_err());
+        order_time_range(&mut from, &mut to, true).unwrap();
+        assert_eq!(from.as_deref(), Some("2024-06-01T00:00:00Z"));
+        assert_eq!(to.as_deref(), Some("2024-06-02T00:00:00Z"));
+
+        // Relative bounds count back from now, 1h is after 2h
+        let (mut from, mut to) = (Some("1h".to_string()), Some("2h".to_string()));
+        assert!(order_time_range(&mut from, &mut to, false).is_err());
+        let (mut from, mut to) = (Some("2h".to_string()), None);
+        assert!(order_time_range(&mut from, &mut to, false).is_ok());
+    }
+
+    #[test]
+    fn test_calendar_shorthand() {
+        // A Wednesday
+        let now = Utc.with_ymd_and_hms(2024, 6, 19, 15, 30, 0).unwrap();
+        let day = |m, d| Some(Utc.with_ymd_and_hms(2024, m, d, 0, 0, 0).unwrap());
+        assert_eq!(calendar_shorthand("today", &now), day(6, 19));
+        assert_eq!(calendar_shorthand("yesterday", &now), day(6, 18));
+        assert_eq!(calendar_shorthand("monday", &now), day(6, 17));
+        assert_eq!(calendar_shorthand("Wednesday", &now), day(6, 19));
+        assert_eq!(calendar_shorthand("thu", &now), day(6, 13));
+        assert_eq!(calendar_shorthand("2024-W25", &now), day(6, 17));
+        assert_eq!(calendar_shorthand("2024-W54", &now), None);
+        assert_eq!(calendar_shorthand("10:00", &now), None);
+
+        // Midnight of the --tz zone, already the next day at +10:00
+        let offset = FixedOffset::east_opt(10 * 3600).unwrap();
+        assert_eq!(
+            calendar_shorthand("today", &now.with_timezone(&offset)),
+            Some(Utc.with_ymd_and_hms(2024, 6, 19, 14, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_parse_datetime_relative() {
+        let parsed = parse_datetime("10m").unwrap();
+        let expected = Utc::now() - chrono::Duration::minutes(10);
+        assert!((parsed - expected).num_seconds().abs() < 5);
+    }
+
+    #[test]
+    fn test_validate_index_pattern() {
+        assert!(validate_index_pattern("logs").is_ok());
+        assert!(validate_index_pattern("logs-*,-logs-debug-*").is_ok());
+        assert!(validate_index_pattern("logs-*, metrics-*, -logs-noisy-*").is_ok());
+        assert!(validate_index_pattern("-logs-debug-*,logs-*").is_err());
+        assert!(validate_index_pattern("logs,-logs-debug").is_err());
+        assert!(validate_index_pattern("logs-*,-").is_err());
+        assert!(validate_index_pattern("logs-*,,metrics-*").is_err());
+    }
+
+    #[test]
+    fn test_config_include() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::write(
+            dir.join("base.toml"),
+            "[default]\nurl = \"https://es.example.com\"\nkibana_url = \"https://kibana.example.com\"\n",
+        )
+        .unwrap();
+        let config_file = dir.join("config.toml");
+        fs::write(
+            &config_file,
+            "include = [\"base.toml\"]\n[default]\nusername = \"alice\"\n",
+        )
+        .unwrap();
+
+        let mut config = load_config(&config_file).unwrap().unwrap();
+        assert_eq!(config.default.url, "https://es.example.com");
+        assert_eq!(config.default.username.as_deref(), Some("alice"));
+        assert_eq!(
+            config.default.kibana_url.as_deref(),
+            Some("https://kibana.example.com")
+        );
+
+        // Inherited values stay in the shared file
+        config.default.password = Some("secret".to_string());
+        save_config(&config, &config_file).unwrap();
+        let saved = fs::read_to_string(&config_file).unwrap();
+        assert!(saved.contains("include"));
+        assert!(saved.contains("password"));
+        assert!(!saved.contains("es.example.com"));
+    }
+
+    #[test]
+    fn test_config_include_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.toml"), "include = [\"b.toml\"]\n").unwrap();
+        fs::write(dir.path().join("b.toml"), "include = [\"a.toml\"]\n").unwrap();
+        assert!(load_config(&dir.path().join("a.toml")).is_err());
+    }
+
+    #[test]
+    fn test_config_from_env() {
+        let env = BTreeMap::from([
+            ("ESQ_URL", "https://es.ci:9200"),
+            ("ESQ_NODES", "https://es-2.ci:9200, https://es-3.ci:9200"),
+            ("ESQ_USERNAME", "ci"),
+            ("ESQ_PASSWORD", ""),
+        ]);
+        let config = config_from_env(|name| env.get(name).map(|value| value.to_string())).unwrap();
+        assert_eq!(
+            config.default.node_urls(),
+            vec![
+                "https://es.ci:9200",
+                "https://es-2.ci:9200",
+                "https://es-3.ci:9200"
+            ]
+        );
+        assert_eq!(config.default.username.as_deref(), Some("ci"));
+        assert_eq!(config.default.password, None);
+        assert!(config_from_env(|_| None).is_err());
+    }
+
+    #[test]
+    fn test_parse_datetime_absolute() {
+        let parsed = parse_datetime("2024-06-01T10:00:00Z").unwrap();
+        assert_eq!(parsed.to_rfc3339(), "2024-06-01T10:00:00+00:00");
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("logs", "logs"), 0);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let indices = vec![
+            "app-logs-2024.06.18".to_string(),
+            "app-logs-2024.06.17".to_string(),
+            "metrics-2024.06.18".to_string(),
+        ];
+        assert_eq!(
+            suggestions("app-logs-2024.6.18", &indices),
+            vec!["app-logs-2024.06.18", "app-logs-2024.06.17"]
+        );
+        assert!(suggestions("billing", &indices).is_empty());
+    }
+}
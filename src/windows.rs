@@ -0,0 +1,263 @@
+// src/windows.rs
+use crate::elasticsearch::builder::SearchQueryBuilder;
+use crate::elasticsearch::client::ClientSettings;
+use crate::elasticsearch::model::Hit;
+use crate::utils::*;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Split [from, to) into equal sub-windows, in chronological order
+pub fn split_range(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    windows: u32,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let step = (to - from) / windows as i32;
+    (0..windows)
+        .map(|i| {
+            let start = from + step * i as i32;
+            let end = if i + 1 == windows {
+                to
+            } else {
+                from + step * (i + 1) as i32
+            };
+            (start, end)
+        })
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+// Directory only this user can read holding the spools of one extraction, removed with the last
+// of them. It is made anew, never one found in place, which someone else could have prepared
+struct SpoolDir {
+    path: PathBuf,
+}
+
+impl SpoolDir {
+    fn create(parent: &Path) -> Result<Self, ESQError> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos())
+            .unwrap_or_default();
+        let path = parent.join(format!("esq-windows-{}-{}", std::process::id(), nanos));
+        DirBuilder::new().mode(0o700).create(&path).map_err(|e| {
+            ESQError::IOError(std::io::Error::new(
+                e.kind(),
+                format!(
+                    "Cannot create the spool directory {}: {}, set TMPDIR to a writable directory",
+                    path.display(),
+                    e
+                ),
+            ))
+        })?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for SpoolDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Hits of one window written to a temporary file, so later windows never wait on memory
+pub struct WindowSpool {
+    path: PathBuf,
+    _dir: Arc<SpoolDir>,
+}
+
+impl WindowSpool {
+    // Batches of hits read back from the spool
+    pub fn batches(&self, size: usize) -> Result<SpoolBatches, ESQError> {
+        read_spool(&self.path, size)
+    }
+}
+
+// Batches of the hits of an NDJSON file, one serialized hit per line
+pub fn read_spool(path: &Path, size: usize) -> Result<SpoolBatches, ESQError> {
+    Ok(SpoolBatches {
+        lines: BufReader::new(File::open(path)?).lines(),
+        size,
+    })
+}
+
+impl Drop for WindowSpool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct SpoolBatches {
+    lines: Lines<BufReader<File>>,
+    size: usize,
+}
+
+impl Iterator for SpoolBatches {
+    type Item = Result<Vec<Hit>, ESQError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::new();
+        for line in self.lines.by_ref() {
+            let hit = line
+                .map_err(ESQError::from)
+                .and_then(|line| Ok(serde_json::from_str::<Hit>(&line)?));
+            match hit {
+                Ok(hit) => batch.push(hit),
+                Err(e) => return Some(Err(e)),
+            }
+            if batch.len() == self.size {
+                break;
+            }
+        }
+        (!batch.is_empty()).then_some(Ok(batch))
+    }
+}
+
+// Page through one window with its own client and PIT
+fn spool_window(
+    settings: ClientSettings,
+    query_builder: SearchQueryBuilder,
+    batch_size: u32,
+    dir: Arc<SpoolDir>,
+    path: PathBuf,
+    stop: &AtomicBool,
+) -> Result<WindowSpool, ESQError> {
+    let spool = WindowSpool { path, _dir: dir };
+    let mut writer = BufWriter::new(
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&spool.path)?,
+    );
+    let mut es = settings.connect()?;
+    es.create_pit()?;
+
+    let mut search_after: Option<Value> = None;
+    while !interrupted() && !stop.load(Ordering::SeqCst) {
+        let mut builder = query_builder.clone().with_size(batch_size);
+        if let Some(sort) = &search_after {
+            builder = builder.with_search_after(sort.clone());
+        }
+        let response = es.search(&builder.build())?;
+        let hits = &response.hits.hits;
+        let Some(last) = hits.last() else {
+            break;
+        };
+        for hit in hits {
+            writeln!(writer, "{}", serde_json::to_string(hit)?)?;
+        }
+        search_after = last.sort.clone().map(Value::from);
+    }
+    writer.flush()?;
+    Ok(spool)
+}
+
+// Windows being fetched by their own threads, yielded in order as each one completes
+pub struct WindowExtraction {
+    handles: VecDeque<JoinHandle<Result<WindowSpool, ESQError>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Iterator for WindowExtraction {
+    type Item = Result<WindowSpool, ESQError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let handle = self.handles.pop_front()?;
+        Some(handle.join().expect("window extraction thread panicked"))
+    }
+}
+
+// Windows left unread are stopped and joined, so each thread releases its PIT
+impl Drop for WindowExtraction {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+// Start one thread per window, each filling its spool in a new directory under spool_dir
+pub fn spawn_windows(
+    settings: &ClientSettings,
+    query_builder: &SearchQueryBuilder,
+    ranges: &[(DateTime<Utc>, DateTime<Utc>)],
+    batch_size: u32,
+    spool_dir: &Path,
+) -> Result<WindowExtraction, ESQError> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let dir = Arc::new(SpoolDir::create(spool_dir)?);
+    let handles = ranges
+        .iter()
+        .enumerate()
+        .map(|(i, (start, end))| {
+            let builder = query_builder.clone().with_time_range(
+                Some(&start.to_rfc3339_opts(SecondsFormat::Millis, true)),
+                Some(&end.to_rfc3339_opts(SecondsFormat::Millis, true)),
+                "",
+            )?;
+            let settings = settings.clone();
+            let path = dir.path.join(format!("window-{}.ndjson", i));
+            let (dir, stop) = (Arc::clone(&dir), Arc::clone(&stop));
+            Ok(thread::spawn(move || {
+                spool_window(settings, builder, batch_size, dir, path, &stop)
+            }))
+        })
+        .collect::<Result<_, ESQError>>()?;
+    Ok(WindowExtraction { handles, stop })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_split_range() {
+        let from = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 6, 1, 10, 0, 1).unwrap();
+        let windows = split_range(from, to, 4);
+        assert_eq!(windows.len(), 4);
+        assert_eq!(windows[0].0, from);
+        assert_eq!(windows[3].1, to);
+        for pair in windows.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+        assert!(split_range(from, from, 4).is_empty());
+    }
+
+    #[test]
+    fn test_spool_batches() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = Arc::new(SpoolDir::create(parent.path()).unwrap());
+        let dir_path = dir.path.clone();
+        let path = dir.path.join("window-0.ndjson");
+        fs::write(
+            &path,
+            "{\"_id\":\"a\",\"_source\":{}}\n{\"_id\":\"b\",\"_source\":{}}\n{\"_id\":\"c\",\"_source\":{}}\n",
+        )
+        .unwrap();
+        let spool = WindowSpool {
+            path: path.clone(),
+            _dir: dir,
+        };
+        let sizes: Vec<usize> = spool
+            .batches(2)
+            .unwrap()
+            .map(|batch| batch.unwrap().len())
+            .collect();
+        assert_eq!(sizes, vec![2, 1]);
+        drop(spool);
+        assert!(!path.exists());
+        assert!(!dir_path.exists());
+    }
+}